@@ -1,13 +1,123 @@
-use crate::device::ADBDevice;
+use crate::events::{DeviceEvent, EventBus};
+use crate::file_browser::{FileEntry, FileKind, validate_remote_path};
+use crate::hdmi_cec::{
+    CEC_AUTO_DEVICE_OFF_SETTING, CEC_AUTO_WAKEUP_SETTING, CEC_ENABLED_SETTING, CecAction,
+    CecStatus, TvPowerStatus, parse_setting_flag, parse_tv_power,
+};
+use crate::intent::{IntentKind, IntentRequest, validate_component};
+use crate::logcat::{
+    LogRecord, LogcatFilter, LogcatMode, MAX_DUMP_LINES, logcat_command, parse_threadtime_line,
+};
+use crate::macro_manager::{
+    KeyStep, KeycodeStep, LaunchAppStep, LongPressStep, MacroStep, SwipeStep, TapStep, TextStep,
+};
+use crate::media_session::{MediaAction, MediaStatus, parse_media_status};
+use crate::metrics::{command_kind, metrics};
+use crate::network_diagnostics::{
+    NETWORK_COMMAND, NetworkCheck, NetworkInfo, parse_network_info, parse_ping, ping_command,
+};
+use crate::packages::{
+    APK_STAGING_DIR, HOME_ACTIVITY_COMMAND, PackageFilter, check_enabled_state, check_pm_output,
+    parse_home_activity, parse_install_session, parse_package_list, parse_package_paths,
+};
+use crate::power::{PowerStatus, parse_power_status};
+use crate::reboot::{BootState, RebootMode, parse_boot_state};
+use crate::resource_monitor::{STATS_COMMAND, SystemSnapshot, parse_snapshot};
+use crate::session_recorder::{Recording, RecordingStatus, Session, validate_session_name};
+use crate::settings::{
+    KNOWN_SETTINGS, SETTINGS_HISTORY_LIMIT, Setting, SettingChange, SettingsNamespace,
+    parse_setting_value, parse_settings_list, validate_setting_key, validate_setting_value,
+};
+use crate::transport::DeviceTransport;
+use crate::volume::{VolumeState, VolumeStream, parse_muted, parse_volume_range};
+use crate::wake_on_lan::{MAC_COMMAND, parse_mac_address};
+use adb_client::RebootType;
+use chrono::Local;
+use poem_openapi::Object;
+use serde::Serialize;
+use std::collections::BTreeSet;
 use std::error::Error;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+const KEY_CODES: &[(&str, u32)] = &[
+    ("home", 3),
+    ("back", 4),
+    ("volume_up", 24),
+    ("volume_down", 25),
+    ("volume_mute", 164),
+    ("menu", 82),
+    ("recent_apps", 187),
+    ("dpad_up", 19),
+    ("dpad_down", 20),
+    ("dpad_left", 21),
+    ("dpad_right", 22),
+    ("dpad_center", 23),
+    ("play_pause", 85),
+    ("stop", 86),
+    ("next", 87),
+    ("previous", 88),
+    ("enter", 66),
+    ("space", 62),
+    ("backspace", 67),
+    ("tab", 61),
+    ("power", 26),
+    ("sleep", 223),
+    ("wake_up", 224),
+];
+
+const POWER_SETTLE_TIMEOUT: Duration = Duration::from_secs(3);
+const POWER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Object)]
+pub struct ForegroundApp {
+    pub package: String,
+    pub activity: Option<String>,
+}
 
 pub struct ATVController {
-    device: ADBDevice,
+    device_id: String,
+    device: Box<dyn DeviceTransport>,
+    events: EventBus,
+    settings_history: Vec<SettingChange>,
+    recording: Option<Recording>,
 }
 
 impl ATVController {
-    pub fn new(device: ADBDevice) -> Self {
-        Self { device }
+    pub fn new(
+        device_id: impl Into<String>,
+        device: Box<dyn DeviceTransport>,
+        events: EventBus,
+    ) -> Self {
+        Self {
+            device_id: device_id.into(),
+            device,
+            events,
+            settings_history: Vec::new(),
+            recording: None,
+        }
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    pub fn reconnect(&mut self) -> Result<(), Box<dyn Error>> {
+        self.device.reconnect()?;
+        Ok(())
+    }
+
+    pub fn open_connection(&self) -> Result<Box<dyn DeviceTransport>, Box<dyn Error>> {
+        Ok(self.device.open_connection()?)
+    }
+
+    pub fn ping(&mut self) -> Result<(), Box<dyn Error>> {
+        let output = self.shell("echo ok")?;
+        if output.trim() == "ok" {
+            Ok(())
+        } else {
+            Err(format!("Unexpected ping response: {}", output.trim()).into())
+        }
     }
 
     pub fn power(&mut self) -> Result<(), Box<dyn Error>> {
@@ -22,6 +132,276 @@ impl ATVController {
         self.send_keyevent(224)
     }
 
+    pub fn power_status(&mut self) -> Result<PowerStatus, Box<dyn Error>> {
+        let output = self.shell(
+            "dumpsys power | grep -E 'mWakefulness=|Display Power'; dumpsys display | grep mScreenState=",
+        )?;
+        Ok(parse_power_status(&output))
+    }
+
+    /// Wakes the device unless it is already awake; returns whether a key was sent.
+    pub fn ensure_on(&mut self) -> Result<bool, Box<dyn Error>> {
+        self.ensure_power(true)
+    }
+
+    /// Puts the device to sleep unless it is already asleep; returns whether a key was sent.
+    pub fn ensure_off(&mut self) -> Result<bool, Box<dyn Error>> {
+        self.ensure_power(false)
+    }
+
+    fn ensure_power(&mut self, on: bool) -> Result<bool, Box<dyn Error>> {
+        if self.power_status()?.awake == on {
+            return Ok(false);
+        }
+        if on {
+            self.wake_up()?;
+        } else {
+            self.sleep()?;
+        }
+        let deadline = Instant::now() + POWER_SETTLE_TIMEOUT;
+        loop {
+            std::thread::sleep(POWER_POLL_INTERVAL);
+            if self.power_status()?.awake == on {
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                let state = if on { "on" } else { "off" };
+                return Err(format!(
+                    "Device did not turn {} within {}s",
+                    state,
+                    POWER_SETTLE_TIMEOUT.as_secs()
+                )
+                .into());
+            }
+        }
+    }
+
+    pub fn reboot(&mut self, mode: RebootMode) -> Result<(), Box<dyn Error>> {
+        let reboot_type = match mode {
+            RebootMode::Normal => RebootType::System,
+            RebootMode::Recovery => RebootType::Recovery,
+            RebootMode::Bootloader => RebootType::Bootloader,
+            // adbd's reboot service cannot power off, but the power service can.
+            RebootMode::Shutdown => {
+                self.shell("svc power shutdown")?;
+                return Ok(());
+            }
+        };
+        let started = Instant::now();
+        let result = self.device.reboot(reboot_type);
+        self.record_result("reboot", started, &result);
+        result?;
+        Ok(())
+    }
+
+    pub fn boot_state(&mut self) -> Result<BootState, Box<dyn Error>> {
+        let output = self.shell("getprop sys.boot_completed; cat /proc/uptime")?;
+        parse_boot_state(&output)
+            .ok_or_else(|| format!("Unexpected boot state: {}", output.trim()).into())
+    }
+
+    pub fn cec_status(&mut self) -> Result<CecStatus, Box<dyn Error>> {
+        let output = self.shell("dumpsys hdmi_control")?;
+        Ok(CecStatus {
+            tv_power: parse_tv_power(&output),
+            enabled: self.global_flag(CEC_ENABLED_SETTING)?,
+            auto_wakeup: self.global_flag(CEC_AUTO_WAKEUP_SETTING)?,
+            auto_device_off: self.global_flag(CEC_AUTO_DEVICE_OFF_SETTING)?,
+        })
+    }
+
+    pub fn set_cec_settings(
+        &mut self,
+        enabled: Option<bool>,
+        auto_wakeup: Option<bool>,
+        auto_device_off: Option<bool>,
+    ) -> Result<CecStatus, Box<dyn Error>> {
+        let settings = [
+            (CEC_ENABLED_SETTING, enabled),
+            (CEC_AUTO_WAKEUP_SETTING, auto_wakeup),
+            (CEC_AUTO_DEVICE_OFF_SETTING, auto_device_off),
+        ];
+        for (key, value) in settings {
+            if let Some(value) = value {
+                let command = format!("settings put global {} {}", key, u8::from(value));
+                self.shell(&command)?;
+            }
+        }
+        self.cec_status()
+    }
+
+    pub fn cec_action(&mut self, action: CecAction) -> Result<(), Box<dyn Error>> {
+        match action {
+            CecAction::TvOn => {
+                self.ensure_on()?;
+                self.one_touch_play()
+            }
+            CecAction::TvOff => {
+                let output = self.shell("dumpsys hdmi_control")?;
+                // KEYCODE_TV_POWER toggles, so leave a TV that is already off alone.
+                match parse_tv_power(&output) {
+                    TvPowerStatus::Standby | TvPowerStatus::TransientToStandby => Ok(()),
+                    _ => self.send_keyevent(177),
+                }
+            }
+            CecAction::SwitchInput => self.one_touch_play(),
+        }
+    }
+
+    fn one_touch_play(&mut self) -> Result<(), Box<dyn Error>> {
+        let output = self.shell("cmd hdmi_control onetouchplay")?;
+        if output.contains("Can't find service") || output.contains("Unknown command") {
+            // Before Android 11 there is no shell command, but waking a
+            // playback device sends One Touch Play on its own.
+            return self.wake_up();
+        }
+        if output.to_lowercase().contains("fail") {
+            return Err(format!("One Touch Play failed: {}", output.trim()).into());
+        }
+        Ok(())
+    }
+
+    fn global_flag(&mut self, key: &str) -> Result<Option<bool>, Box<dyn Error>> {
+        let output = self.shell(&format!("settings get global {}", key))?;
+        Ok(parse_setting_flag(&output))
+    }
+
+    pub fn get_setting(
+        &mut self,
+        namespace: SettingsNamespace,
+        key: &str,
+    ) -> Result<Setting, Box<dyn Error>> {
+        validate_setting_key(key)?;
+        let output = self.shell(&format!("settings get {} {}", namespace.as_arg(), key))?;
+        Ok(Setting::new(namespace, key, parse_setting_value(&output)))
+    }
+
+    /// Current values of the allow-listed settings.
+    pub fn known_settings(&mut self) -> Result<Vec<Setting>, Box<dyn Error>> {
+        KNOWN_SETTINGS
+            .iter()
+            .map(|known| self.get_setting(known.namespace, known.key))
+            .collect()
+    }
+
+    pub fn list_settings(
+        &mut self,
+        namespace: SettingsNamespace,
+    ) -> Result<Vec<Setting>, Box<dyn Error>> {
+        let output = self.shell(&format!("settings list {}", namespace.as_arg()))?;
+        Ok(parse_settings_list(namespace, &output))
+    }
+
+    pub fn put_setting(
+        &mut self,
+        namespace: SettingsNamespace,
+        key: &str,
+        value: &str,
+    ) -> Result<SettingChange, Box<dyn Error>> {
+        validate_setting_value(value)?;
+        self.change_setting(namespace, key, Some(value))
+    }
+
+    pub fn delete_setting(
+        &mut self,
+        namespace: SettingsNamespace,
+        key: &str,
+    ) -> Result<SettingChange, Box<dyn Error>> {
+        self.change_setting(namespace, key, None)
+    }
+
+    pub fn settings_history(&self) -> &[SettingChange] {
+        &self.settings_history
+    }
+
+    /// Restores the value from before the most recent change, if any.
+    pub fn undo_setting_change(&mut self) -> Result<Option<SettingChange>, Box<dyn Error>> {
+        let Some(change) = self.settings_history.pop() else {
+            return Ok(None);
+        };
+        if let Err(e) =
+            self.write_setting(change.namespace, &change.key, change.previous.as_deref())
+        {
+            self.settings_history.push(change);
+            return Err(e);
+        }
+        Ok(Some(change))
+    }
+
+    fn change_setting(
+        &mut self,
+        namespace: SettingsNamespace,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<SettingChange, Box<dyn Error>> {
+        let previous = self.get_setting(namespace, key)?.value;
+        self.write_setting(namespace, key, value)?;
+        let change = SettingChange {
+            namespace,
+            key: key.to_string(),
+            previous,
+            value: value.map(str::to_string),
+            changed_at: Local::now().to_rfc3339(),
+        };
+        if self.settings_history.len() == SETTINGS_HISTORY_LIMIT {
+            self.settings_history.remove(0);
+        }
+        self.settings_history.push(change.clone());
+        Ok(change)
+    }
+
+    fn write_setting(
+        &mut self,
+        namespace: SettingsNamespace,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        validate_setting_key(key)?;
+        let command = match value {
+            Some(value) => format!(
+                "settings put {} {} {}",
+                namespace.as_arg(),
+                key,
+                shell_quote(value)
+            ),
+            None => format!("settings delete {} {}", namespace.as_arg(), key),
+        };
+        let output = self.shell(&command)?;
+        // put prints nothing on success and delete prints "Deleted N rows".
+        let output = output.trim();
+        if output.is_empty() || output.starts_with("Deleted") {
+            Ok(())
+        } else {
+            Err(output.to_string().into())
+        }
+    }
+
+    pub fn system_snapshot(&mut self) -> Result<SystemSnapshot, Box<dyn Error>> {
+        let output = self.shell(STATS_COMMAND)?;
+        Ok(parse_snapshot(&output))
+    }
+
+    pub fn network_info(&mut self) -> Result<NetworkInfo, Box<dyn Error>> {
+        let output = self.shell(NETWORK_COMMAND)?;
+        Ok(parse_network_info(&output))
+    }
+
+    /// The MAC address to send Wake-on-LAN packets to, if the device has one.
+    pub fn mac_address(&mut self) -> Result<Option<String>, Box<dyn Error>> {
+        let output = self.shell(MAC_COMMAND)?;
+        Ok(parse_mac_address(&output))
+    }
+
+    /// Pings `target` from the device, which also checks that it resolves.
+    pub fn network_check(
+        &mut self,
+        target: &str,
+        count: u32,
+    ) -> Result<NetworkCheck, Box<dyn Error>> {
+        let output = self.shell(&ping_command(target, count)?)?;
+        Ok(parse_ping(target, &output))
+    }
+
     pub fn volume_up(&mut self) -> Result<(), Box<dyn Error>> {
         self.send_keyevent(24)
     }
@@ -34,6 +414,57 @@ impl ATVController {
         self.send_keyevent(164)
     }
 
+    pub fn volume(&mut self, stream: VolumeStream) -> Result<VolumeState, Box<dyn Error>> {
+        let command = format!(
+            "cmd media_session volume --stream {} --get",
+            stream.stream_id()
+        );
+        let output = self.shell(&command)?;
+        let (level, min, max) = parse_volume_range(&output)
+            .ok_or_else(|| format!("Failed to parse volume: {}", output.trim()))?;
+        let audio = self.shell("dumpsys audio | grep -A3 '^- STREAM_'")?;
+        Ok(VolumeState {
+            stream,
+            level,
+            min,
+            max,
+            muted: parse_muted(&audio, stream).unwrap_or(false),
+        })
+    }
+
+    pub fn set_volume(
+        &mut self,
+        stream: VolumeStream,
+        level: Option<u32>,
+        muted: Option<bool>,
+    ) -> Result<VolumeState, Box<dyn Error>> {
+        let current = self.volume(stream)?;
+        let toggle_mute = muted.is_some_and(|muted| muted != current.muted);
+        // KEYCODE_VOLUME_MUTE is the only shell-accessible mute and it acts on music.
+        if toggle_mute && stream != VolumeStream::Music {
+            return Err("Mute can only be changed on the music stream".into());
+        }
+        if let Some(level) = level {
+            if level < current.min || level > current.max {
+                return Err(format!(
+                    "Volume {} is outside the range {}..{}",
+                    level, current.min, current.max
+                )
+                .into());
+            }
+            let command = format!(
+                "cmd media_session volume --stream {} --set {}",
+                stream.stream_id(),
+                level
+            );
+            self.shell(&command)?;
+        }
+        if toggle_mute {
+            self.volume_mute()?;
+        }
+        self.volume(stream)
+    }
+
     pub fn home(&mut self) -> Result<(), Box<dyn Error>> {
         self.send_keyevent(3)
     }
@@ -103,25 +534,293 @@ impl ATVController {
         self.send_keyevent(61)
     }
 
+    pub fn keycode_for(key_name: &str) -> Option<u32> {
+        KEY_CODES
+            .iter()
+            .find(|(name, _)| *name == key_name)
+            .map(|(_, keycode)| *keycode)
+    }
+
+    pub fn key_name_for(keycode: u32) -> Option<&'static str> {
+        KEY_CODES
+            .iter()
+            .find(|(_, code)| *code == keycode)
+            .map(|(name, _)| *name)
+    }
+
+    pub fn press_key(&mut self, key_name: &str) -> Result<(), Box<dyn Error>> {
+        let keycode =
+            Self::keycode_for(key_name).ok_or_else(|| format!("Unknown key: {}", key_name))?;
+        self.send_keyevent(keycode)
+    }
+
+    pub fn long_press_key(&mut self, key_name: &str) -> Result<(), Box<dyn Error>> {
+        let keycode =
+            Self::keycode_for(key_name).ok_or_else(|| format!("Unknown key: {}", key_name))?;
+        self.send_long_press(keycode)
+    }
+
     pub fn send_keyevent(&mut self, keycode: u32) -> Result<(), Box<dyn Error>> {
         let command = format!("input keyevent {}", keycode);
-        let mut output = Vec::new();
-        self.device.shell_command(&command, &mut output)?;
+        self.shell(&command)?;
+        self.record(match Self::key_name_for(keycode) {
+            Some(key) => MacroStep::Key(KeyStep {
+                key: key.to_string(),
+            }),
+            None => MacroStep::Keycode(KeycodeStep { keycode }),
+        });
+        Ok(())
+    }
+
+    pub fn send_long_press(&mut self, keycode: u32) -> Result<(), Box<dyn Error>> {
+        let command = format!("input keyevent --longpress {}", keycode);
+        self.shell(&command)?;
+        if let Some(key) = Self::key_name_for(keycode) {
+            self.record(MacroStep::LongPress(LongPressStep {
+                key: key.to_string(),
+            }));
+        }
+        Ok(())
+    }
+
+    pub fn start_recording(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        validate_session_name(name)?;
+        if let Some(recording) = &self.recording {
+            return Err(format!("Already recording session {}", recording.status().name).into());
+        }
+        self.recording = Some(Recording::new(name));
+        Ok(())
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Session> {
+        let recording = self.recording.take()?;
+        Some(recording.finish(&self.device_id))
+    }
+
+    pub fn recording_status(&self) -> Option<RecordingStatus> {
+        self.recording.as_ref().map(Recording::status)
+    }
+
+    fn record(&mut self, step: MacroStep) {
+        if let Some(recording) = &mut self.recording {
+            recording.record(step);
+        }
+    }
+
+    pub fn launch_app(&mut self, package: &str) -> Result<(), Box<dyn Error>> {
+        validate_package_name(package)?;
+        let command = format!(
+            "monkey -p {} -c android.intent.category.LEANBACK_LAUNCHER 1",
+            package
+        );
+        let output = self.shell(&command)?;
+        if output.contains("No activities found") || output.contains("monkey aborted") {
+            return Err(format!("Failed to launch {}: {}", package, output.trim()).into());
+        }
+        self.events.publish(DeviceEvent::AppLaunched {
+            device_id: self.device_id.clone(),
+            package: package.to_string(),
+        });
+        self.record(MacroStep::LaunchApp(LaunchAppStep {
+            package: package.to_string(),
+        }));
         Ok(())
     }
 
+    pub fn packages(&mut self, filter: PackageFilter) -> Result<BTreeSet<String>, Box<dyn Error>> {
+        let output = self.shell(filter.list_command())?;
+        Ok(parse_package_list(&output))
+    }
+
+    /// Installs or updates `package` from an APK streamed from `input`.
+    pub fn install_apk(
+        &mut self,
+        package: &str,
+        input: &mut dyn Read,
+    ) -> Result<(), Box<dyn Error>> {
+        validate_package_name(package)?;
+        let staged = format!("{}/atvmate-{}.apk", APK_STAGING_DIR, package);
+        self.push_file(input, &staged)?;
+        let result = self.shell(&format!("pm install -r {}", staged));
+        let _ = self.shell(&format!("rm -f {}", staged));
+        check_pm_output(&format!("install {}", package), &result?)
+    }
+
+    /// Paths of the package's APKs, base first.
+    pub fn package_paths(&mut self, package: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        validate_package_name(package)?;
+        let output = self.shell(&format!("pm path {}", package))?;
+        let paths = parse_package_paths(&output);
+        if paths.is_empty() {
+            return Err(format!("Package {} not found", package).into());
+        }
+        Ok(paths)
+    }
+
+    /// Installs a base APK and its splits together in one install session.
+    pub fn install_apks(&mut self, package: &str, apks: &[Vec<u8>]) -> Result<(), Box<dyn Error>> {
+        match apks {
+            [] => return Err(format!("No APKs to install for {}", package).into()),
+            [apk] => return self.install_apk(package, &mut apk.as_slice()),
+            _ => validate_package_name(package)?,
+        }
+        let output = self.shell("pm install-create -r")?;
+        let session = parse_install_session(&output)
+            .ok_or_else(|| format!("Failed to install {}: {}", package, output.trim()))?;
+        let result = self.write_install_session(session, package, apks);
+        if result.is_err() {
+            let _ = self.shell(&format!("pm install-abandon {}", session));
+        }
+        result
+    }
+
+    fn write_install_session(
+        &mut self,
+        session: u32,
+        package: &str,
+        apks: &[Vec<u8>],
+    ) -> Result<(), Box<dyn Error>> {
+        for (index, apk) in apks.iter().enumerate() {
+            let staged = format!("{}/atvmate-{}-{}.apk", APK_STAGING_DIR, package, index);
+            self.push_file(&mut apk.as_slice(), &staged)?;
+            let result = self.shell(&format!(
+                "pm install-write -S {} {} {}.apk {}",
+                apk.len(),
+                session,
+                index,
+                staged
+            ));
+            let _ = self.shell(&format!("rm -f {}", staged));
+            check_pm_output(&format!("install {}", package), &result?)?;
+        }
+        let output = self.shell(&format!("pm install-commit {}", session))?;
+        check_pm_output(&format!("install {}", package), &output)
+    }
+
+    /// Removes the package for the default user, which also works for
+    /// preinstalled apps that cannot be removed outright.
+    pub fn uninstall_package(&mut self, package: &str) -> Result<(), Box<dyn Error>> {
+        validate_package_name(package)?;
+        let output = self.shell(&format!("pm uninstall --user 0 {}", package))?;
+        check_pm_output(&format!("uninstall {}", package), &output)
+    }
+
+    pub fn set_package_enabled(
+        &mut self,
+        package: &str,
+        enabled: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        validate_package_name(package)?;
+        let command = if enabled {
+            format!("pm enable {}", package)
+        } else {
+            format!("pm disable-user --user 0 {}", package)
+        };
+        let output = self.shell(&command)?;
+        check_enabled_state(package, &output)
+    }
+
+    pub fn home_activity(&mut self) -> Result<Option<String>, Box<dyn Error>> {
+        let output = self.shell(HOME_ACTIVITY_COMMAND)?;
+        Ok(parse_home_activity(&output))
+    }
+
+    pub fn set_home_activity(&mut self, component: &str) -> Result<(), Box<dyn Error>> {
+        validate_component(component)?;
+        let output = self.shell(&format!("cmd package set-home-activity {}", component))?;
+        check_pm_output(&format!("set launcher {}", component), &output)
+    }
+
+    pub fn send_intent(&mut self, intent: &IntentRequest) -> Result<String, Box<dyn Error>> {
+        let command = intent.to_command()?;
+        let output = self.shell(&command)?;
+        if let Some(error) = output
+            .lines()
+            .find(|line| line.starts_with("Error") || line.contains("Exception occurred"))
+        {
+            return Err(error.trim().into());
+        }
+        if intent.kind == IntentKind::Start
+            && let Some(package) = intent.target_package()
+        {
+            self.events.publish(DeviceEvent::AppLaunched {
+                device_id: self.device_id.clone(),
+                package: package.to_string(),
+            });
+        }
+        Ok(output.trim().to_string())
+    }
+
+    pub fn foreground_app(&mut self) -> Result<Option<ForegroundApp>, Box<dyn Error>> {
+        let output = self.shell("dumpsys window | grep -E 'mCurrentFocus|mFocusedApp'")?;
+        if let Some(app) = parse_window_focus(&output) {
+            return Ok(Some(app));
+        }
+        // Focus can sit on a system window such as the IME or a toast; the
+        // resumed activity still tells us which app is in front.
+        let output = self.shell("dumpsys activity activities | grep ResumedActivity")?;
+        Ok(output.lines().find_map(parse_component_line))
+    }
+
+    pub fn foreground_package(&mut self) -> Result<Option<String>, Box<dyn Error>> {
+        Ok(self.foreground_app()?.map(|app| app.package))
+    }
+
+    pub fn media_status(&mut self) -> Result<MediaStatus, Box<dyn Error>> {
+        let output = self.shell("cat /proc/uptime; dumpsys media_session")?;
+        Ok(parse_media_status(&output))
+    }
+
+    pub fn media_control(
+        &mut self,
+        package: &str,
+        action: MediaAction,
+    ) -> Result<(), Box<dyn Error>> {
+        validate_package_name(package)?;
+        let status = self.media_status()?;
+        if !status
+            .sessions
+            .iter()
+            .any(|session| session.package == package)
+        {
+            return Err(format!("No media session found for {}", package).into());
+        }
+        // Android only routes media keys to one session at a time.
+        if let Some(target) = &status.media_button_package
+            && target != package
+        {
+            return Err(format!(
+                "Media keys are routed to {}, not {}; start playback in {} first",
+                target, package, package
+            )
+            .into());
+        }
+        match action.dispatch_key() {
+            Some(key) => {
+                let output = self.shell(&format!("cmd media_session dispatch {}", key))?;
+                if output.contains("Error") || output.contains("Unknown") {
+                    return Err(format!("Failed to dispatch {}: {}", key, output.trim()).into());
+                }
+                Ok(())
+            }
+            None => self.send_keyevent(action.keycode()),
+        }
+    }
+
     pub fn input_text(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
         let escaped_text = text.replace("'", "\\'");
         let command = format!("input text '{}'", escaped_text);
-        let mut output = Vec::new();
-        self.device.shell_command(&command, &mut output)?;
+        self.shell(&command)?;
+        self.record(MacroStep::Text(TextStep {
+            text: text.to_string(),
+        }));
         Ok(())
     }
 
     pub fn tap(&mut self, x: u32, y: u32) -> Result<(), Box<dyn Error>> {
         let command = format!("input tap {} {}", x, y);
-        let mut output = Vec::new();
-        self.device.shell_command(&command, &mut output)?;
+        self.shell(&command)?;
+        self.record(MacroStep::Tap(TapStep { x, y }));
         Ok(())
     }
 
@@ -140,8 +839,210 @@ impl ATVController {
             ),
             None => format!("input swipe {} {} {} {}", from_x, from_y, to_x, to_y),
         };
+        self.shell(&command)?;
+        self.record(MacroStep::Swipe(SwipeStep {
+            from_x,
+            from_y,
+            to_x,
+            to_y,
+            duration_ms,
+        }));
+        Ok(())
+    }
+
+    pub fn stat_file(&mut self, path: &str) -> Result<Option<FileEntry>, Box<dyn Error>> {
+        validate_remote_path(path)?;
+        let started = Instant::now();
+        let result = self.device.stat(path);
+        self.record_result("sync_stat", started, &result);
+        let stat = result?;
+        // The sync protocol reports missing files as all zeroes rather than an error.
+        if stat.file_perm == 0 {
+            return Ok(None);
+        }
+        Ok(Some(FileEntry::from_stat(path, &stat)))
+    }
+
+    pub fn list_files(&mut self, path: &str) -> Result<Vec<FileEntry>, Box<dyn Error>> {
+        let entry = self
+            .stat_file(path)?
+            .ok_or_else(|| format!("Path {} not found", path))?;
+        if entry.kind == FileKind::File {
+            return Err(format!("{} is not a directory", path).into());
+        }
+        let started = Instant::now();
+        let result = self.device.list(path);
+        self.record_result("sync_list", started, &result);
+        let mut entries: Vec<_> = result?
+            .into_iter()
+            .map(|item| FileEntry::from_list_item(path, item))
+            .filter(|entry| entry.name != "." && entry.name != "..")
+            .collect();
+        entries.sort_by(|a, b| {
+            (a.kind != FileKind::Directory)
+                .cmp(&(b.kind != FileKind::Directory))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        Ok(entries)
+    }
+
+    pub fn pull_file(&mut self, path: &str, output: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+        validate_remote_path(path)?;
+        let started = Instant::now();
+        let result = self.device.pull(path, output);
+        self.record_result("sync_pull", started, &result);
+        result?;
+        Ok(())
+    }
+
+    pub fn push_file(&mut self, input: &mut dyn Read, path: &str) -> Result<(), Box<dyn Error>> {
+        validate_remote_path(path)?;
+        if path.ends_with('/') {
+            return Err(format!("Upload path must name a file: {}", path).into());
+        }
+        let started = Instant::now();
+        let result = self.device.push(input, path);
+        self.record_result("sync_push", started, &result);
+        result?;
+        Ok(())
+    }
+
+    pub fn make_directory(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
+        validate_remote_path(path)?;
+        self.file_command(&format!("mkdir -p {}", shell_quote(path)))
+    }
+
+    pub fn delete_path(&mut self, path: &str, recursive: bool) -> Result<(), Box<dyn Error>> {
+        if path.trim_end_matches('/').is_empty() {
+            return Err("Refusing to delete /".into());
+        }
+        let entry = self
+            .stat_file(path)?
+            .ok_or_else(|| format!("Path {} not found", path))?;
+        let command = match (entry.kind, recursive) {
+            (FileKind::Directory, true) => format!("rm -rf {}", shell_quote(path)),
+            (FileKind::Directory, false) => format!("rmdir {}", shell_quote(path)),
+            _ => format!("rm -f {}", shell_quote(path)),
+        };
+        self.file_command(&command)
+    }
+
+    pub fn rename_path(&mut self, from: &str, to: &str) -> Result<(), Box<dyn Error>> {
+        if self.stat_file(from)?.is_none() {
+            return Err(format!("Path {} not found", from).into());
+        }
+        if self.stat_file(to)?.is_some() {
+            return Err(format!("Path {} already exists", to).into());
+        }
+        self.file_command(&format!("mv {} {}", shell_quote(from), shell_quote(to)))
+    }
+
+    // mkdir, rm and mv are silent on success, so any output is an error message.
+    pub fn pidof(&mut self, package: &str) -> Result<Option<u32>, Box<dyn Error>> {
+        validate_package_name(package)?;
+        let output = self.shell(&format!("pidof {}", package))?;
+        Ok(output
+            .split_whitespace()
+            .next()
+            .and_then(|pid| pid.parse().ok()))
+    }
+
+    pub fn logcat_dump(
+        &mut self,
+        filter: &LogcatFilter,
+        lines: u32,
+    ) -> Result<Vec<LogRecord>, Box<dyn Error>> {
+        // A regex is applied here rather than on the device, so read the whole
+        // window and keep the last matches instead of the last raw lines.
+        let window = if filter.regex.is_some() {
+            MAX_DUMP_LINES
+        } else {
+            lines
+        };
+        let output = self.shell(&logcat_command(filter, LogcatMode::Dump(window))?)?;
+        let records: Vec<LogRecord> = output
+            .lines()
+            .filter_map(parse_threadtime_line)
+            .filter(|record| filter.matches(record))
+            .collect();
+        let skip = records.len().saturating_sub(lines as usize);
+        Ok(records.into_iter().skip(skip).collect())
+    }
+
+    fn file_command(&mut self, command: &str) -> Result<(), Box<dyn Error>> {
+        let output = self.shell(command)?;
+        if !output.trim().is_empty() {
+            return Err(output.trim().into());
+        }
+        Ok(())
+    }
+
+    fn shell(&mut self, command: &str) -> Result<String, Box<dyn Error>> {
+        let started = Instant::now();
         let mut output = Vec::new();
-        self.device.shell_command(&command, &mut output)?;
+        let result = self.device.shell_command(command, &mut output);
+        self.record_result(&command_kind(command), started, &result);
+        result?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    fn record_result<T>(&self, command: &str, started: Instant, result: &adb_client::Result<T>) {
+        metrics().record_device_command(
+            &self.device_id,
+            command,
+            started.elapsed(),
+            result.is_ok(),
+        );
+        if let Err(e) = result {
+            metrics().record_adb_error(e);
+        }
+    }
+}
+
+pub fn validate_package_name(package: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !package.is_empty()
+        && package
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_');
+    if valid {
         Ok(())
+    } else {
+        Err(format!("Invalid package name: {}", package).into())
     }
 }
+
+/// Single-quotes a value for the device shell; embedded quotes become '\''.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn parse_window_focus(output: &str) -> Option<ForegroundApp> {
+    let focused = |key: &str| {
+        output
+            .lines()
+            .filter(|line| line.contains(key))
+            .find_map(parse_component_line)
+    };
+    focused("mCurrentFocus").or_else(|| focused("mFocusedApp"))
+}
+
+// Handles the component formats printed by Android 9 through 14, e.g.
+//   mCurrentFocus=Window{1a2b3c u0 com.example/com.example.MainActivity}
+//   mFocusedApp=AppWindowToken{5e6f token=Token{7a8b ActivityRecord{9c u0 com.example/.Main t4}}}
+//   mFocusedApp=ActivityRecord{9c u0 com.example/.Main t4}
+//   topResumedActivity=ActivityRecord{9c u0 com.example/.Main t4}
+fn parse_component_line(line: &str) -> Option<ForegroundApp> {
+    line.split_whitespace().find_map(|token| {
+        let (package, activity) = token.trim_end_matches('}').split_once('/')?;
+        validate_package_name(package).ok()?;
+        let activity = match activity {
+            "" => None,
+            short if short.starts_with('.') => Some(format!("{}{}", package, short)),
+            full => Some(full.to_string()),
+        };
+        Some(ForegroundApp {
+            package: package.to_string(),
+            activity,
+        })
+    })
+}
@@ -0,0 +1,270 @@
+use crate::atv_controller::ATVController;
+use crate::packages::PackageFilter;
+use crate::provisioning::{ProvisionStep, StepKind, StepStatus};
+use crate::settings::{KNOWN_SETTINGS, SettingsNamespace};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::Local;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::Read;
+
+pub const BACKUP_VERSION: u32 = 1;
+/// Only readable and writable as root, so backups of production builds go
+/// without the wallpaper. A restored wallpaper shows after the next reboot.
+pub const WALLPAPER_PATH: &str = "/data/system/users/0/wallpaper";
+
+/// A device's configuration, stored as gzipped JSON.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeviceBackup {
+    pub version: u32,
+    pub device_id: String,
+    pub created_at: String,
+    pub packages: Vec<PackageBackup>,
+    /// Preinstalled packages that were disabled.
+    pub disabled_system_packages: Vec<String>,
+    pub settings: Vec<SettingBackup>,
+    pub launcher: Option<String>,
+    /// Base64 wallpaper image.
+    pub wallpaper: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PackageBackup {
+    pub package: String,
+    pub enabled: bool,
+    /// Base64 APKs, base first, then splits. Empty unless APKs were included.
+    pub apks: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SettingBackup {
+    pub namespace: SettingsNamespace,
+    pub key: String,
+    pub value: String,
+}
+
+impl DeviceBackup {
+    pub fn to_archive(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        serde_json::to_writer(&mut encoder, self)?;
+        Ok(encoder.finish()?)
+    }
+
+    pub fn from_archive(archive: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut json = Vec::new();
+        GzDecoder::new(archive)
+            .read_to_end(&mut json)
+            .map_err(|e| format!("Not a device backup: {}", e))?;
+        let backup: Self = serde_json::from_slice(&json)?;
+        if backup.version != BACKUP_VERSION {
+            return Err(format!("Unsupported backup version {}", backup.version).into());
+        }
+        Ok(backup)
+    }
+}
+
+pub fn capture_backup(
+    ctrl: &mut ATVController,
+    include_apks: bool,
+) -> Result<DeviceBackup, Box<dyn Error>> {
+    let third_party = ctrl.packages(PackageFilter::ThirdParty)?;
+    let disabled = ctrl.packages(PackageFilter::Disabled)?;
+
+    let mut packages = Vec::with_capacity(third_party.len());
+    for package in &third_party {
+        let mut apks = Vec::new();
+        if include_apks {
+            for path in ctrl.package_paths(package)? {
+                let mut apk = Vec::new();
+                ctrl.pull_file(&path, &mut apk)?;
+                apks.push(BASE64.encode(apk));
+            }
+        }
+        packages.push(PackageBackup {
+            package: package.clone(),
+            enabled: !disabled.contains(package),
+            apks,
+        });
+    }
+
+    let mut settings = Vec::new();
+    // Restoring ADB settings could cut off the connection doing the restore.
+    for known in KNOWN_SETTINGS
+        .iter()
+        .filter(|known| !known.key.starts_with("adb_"))
+    {
+        if let Some(value) = ctrl.get_setting(known.namespace, known.key)?.value
+            && !value.is_empty()
+        {
+            settings.push(SettingBackup {
+                namespace: known.namespace,
+                key: known.key.to_string(),
+                value,
+            });
+        }
+    }
+
+    let mut wallpaper = Vec::new();
+    let wallpaper = ctrl
+        .pull_file(WALLPAPER_PATH, &mut wallpaper)
+        .ok()
+        .filter(|_| !wallpaper.is_empty())
+        .map(|_| BASE64.encode(&wallpaper));
+
+    Ok(DeviceBackup {
+        version: BACKUP_VERSION,
+        device_id: ctrl.device_id().to_string(),
+        created_at: Local::now().to_rfc3339(),
+        packages,
+        disabled_system_packages: disabled.difference(&third_party).cloned().collect(),
+        settings,
+        launcher: ctrl.home_activity()?,
+        wallpaper,
+    })
+}
+
+fn step(
+    kind: StepKind,
+    target: &str,
+    current: Option<String>,
+    desired: Option<String>,
+) -> ProvisionStep {
+    let status = if current == desired {
+        StepStatus::Unchanged
+    } else {
+        StepStatus::Pending
+    };
+    ProvisionStep {
+        kind,
+        target: target.to_string(),
+        current,
+        desired,
+        status,
+        error: None,
+    }
+}
+
+fn run(step: &mut ProvisionStep, action: impl FnOnce() -> Result<(), Box<dyn Error>>) {
+    if step.status != StepStatus::Pending {
+        return;
+    }
+    match action() {
+        Ok(_) => step.status = StepStatus::Applied,
+        Err(e) => {
+            step.status = StepStatus::Failed;
+            step.error = Some(e.to_string());
+        }
+    }
+}
+
+/// Reapplies a backup, possibly to another device: installs missing apps,
+/// then restores settings, the launcher, enabled states and the wallpaper.
+/// Whatever already matches is left alone.
+pub fn restore_backup(
+    ctrl: &mut ATVController,
+    backup: &DeviceBackup,
+) -> Result<Vec<ProvisionStep>, Box<dyn Error>> {
+    let installed = ctrl.packages(PackageFilter::All)?;
+    let disabled = ctrl.packages(PackageFilter::Disabled)?;
+    let state = |installed: bool| installed.then(|| "installed".to_string());
+    let mut steps = Vec::new();
+
+    for package in &backup.packages {
+        let mut install = step(
+            StepKind::Install,
+            &package.package,
+            state(installed.contains(&package.package)),
+            state(true),
+        );
+        run(&mut install, || {
+            if package.apks.is_empty() {
+                return Err("The backup does not include its APK".into());
+            }
+            let apks = package
+                .apks
+                .iter()
+                .map(|apk| BASE64.decode(apk))
+                .collect::<Result<Vec<_>, _>>()?;
+            ctrl.install_apks(&package.package, &apks)
+        });
+        steps.push(install);
+    }
+
+    for setting in &backup.settings {
+        let current = ctrl.get_setting(setting.namespace, &setting.key)?.value;
+        let target = format!("{} {}", setting.namespace.as_arg(), setting.key);
+        let mut restore = step(
+            StepKind::Setting,
+            &target,
+            current,
+            Some(setting.value.clone()),
+        );
+        run(&mut restore, || {
+            ctrl.put_setting(setting.namespace, &setting.key, &setting.value)
+                .map(|_| ())
+        });
+        steps.push(restore);
+    }
+
+    if let Some(launcher) = &backup.launcher {
+        let mut restore = step(
+            StepKind::Launcher,
+            launcher,
+            ctrl.home_activity()?,
+            Some(launcher.clone()),
+        );
+        run(&mut restore, || ctrl.set_home_activity(launcher));
+        steps.push(restore);
+    }
+
+    let enabled_states = backup
+        .packages
+        .iter()
+        .map(|package| (package.package.as_str(), package.enabled))
+        .chain(
+            backup
+                .disabled_system_packages
+                .iter()
+                .map(|package| (package.as_str(), false)),
+        );
+    let installed = ctrl.packages(PackageFilter::All)?;
+    for (package, enabled) in enabled_states {
+        if !installed.contains(package) {
+            continue;
+        }
+        let describe =
+            |enabled: bool| Some(if enabled { "enabled" } else { "disabled" }.to_string());
+        let kind = if enabled {
+            StepKind::Enable
+        } else {
+            StepKind::Disable
+        };
+        let mut restore = step(
+            kind,
+            package,
+            describe(!disabled.contains(package)),
+            describe(enabled),
+        );
+        run(&mut restore, || ctrl.set_package_enabled(package, enabled));
+        steps.push(restore);
+    }
+
+    if let Some(wallpaper) = &backup.wallpaper {
+        let mut restore = step(
+            StepKind::Wallpaper,
+            WALLPAPER_PATH,
+            None,
+            Some("restored".to_string()),
+        );
+        run(&mut restore, || {
+            let image = BASE64.decode(wallpaper)?;
+            ctrl.push_file(&mut image.as_slice(), WALLPAPER_PATH)
+        });
+        steps.push(restore);
+    }
+    Ok(steps)
+}
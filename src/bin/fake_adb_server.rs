@@ -0,0 +1,38 @@
+use atvmate::fake_adb_server::{FakeAdbServer, SimulatedTv};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Duration;
+
+const DEFAULT_PORT: u16 = 5037;
+const SERIAL: &str = "SIMTV0001";
+
+/// Serves a simulated TV over the ADB server protocol, for working on the
+/// frontend without hardware. Pass a port to avoid clashing with a real adb.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let port = match std::env::args().nth(1) {
+        Some(port) => port
+            .parse()
+            .map_err(|e| format!("Invalid port '{}': {}", port, e))?,
+        None => DEFAULT_PORT,
+    };
+    let server = FakeAdbServer::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port))?;
+    server.add_device(SERIAL, SimulatedTv::new());
+    println!("Fake ADB server listening on {}", server.addr());
+    println!(
+        "Add the simulated TV with POST /api/devices/server {{\"serial\": \"{}\", \"server_addr\": \"{}\"}}",
+        SERIAL,
+        server.addr()
+    );
+
+    let mut seen = 0;
+    loop {
+        std::thread::sleep(Duration::from_millis(200));
+        let requests = server.requests();
+        for request in &requests[seen..] {
+            match &request.serial {
+                Some(serial) => println!("[{}] {}", serial, request.request),
+                None => println!("{}", request.request),
+            }
+        }
+        seen = requests.len();
+    }
+}
@@ -0,0 +1,177 @@
+use crate::global_device_manager::{DeviceHealth, GlobalDeviceManager};
+use crate::macro_manager::MacroManager;
+use crate::reboot::RebootMode;
+use crate::wake_on_lan::DEFAULT_WAKE_TIMEOUT;
+use poem_openapi::{Enum, Object};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum PowerState {
+    On,
+    Off,
+    Toggle,
+}
+
+#[derive(Clone, Debug)]
+pub enum DeviceCommand {
+    Key(String),
+    Text(String),
+    LaunchApp(String),
+    Macro(String),
+    Reboot,
+    Power(PowerState),
+}
+
+impl DeviceCommand {
+    pub fn describe(&self) -> String {
+        match self {
+            DeviceCommand::Key(key) => format!("key '{}'", key),
+            DeviceCommand::Text(_) => "text input".to_string(),
+            DeviceCommand::LaunchApp(package) => format!("launch of {}", package),
+            DeviceCommand::Macro(name) => format!("macro {}", name),
+            DeviceCommand::Reboot => "reboot".to_string(),
+            DeviceCommand::Power(PowerState::On) => "power on".to_string(),
+            DeviceCommand::Power(PowerState::Off) => "power off".to_string(),
+            DeviceCommand::Power(PowerState::Toggle) => "power toggle".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct DeviceCommandResult {
+    pub device_id: String,
+    pub success: bool,
+    pub message: String,
+}
+
+pub async fn execute_command(
+    device_manager: &Arc<GlobalDeviceManager>,
+    macro_manager: &MacroManager,
+    device_id: &str,
+    command: &DeviceCommand,
+) -> Result<String, String> {
+    let controller = device_manager
+        .get_controller(device_id)
+        .map_err(|e| format!("Failed to get controller: {}", e))?;
+
+    if let DeviceCommand::Macro(name) = command {
+        let report = macro_manager
+            .run_macro(controller, device_id, name)
+            .await
+            .map_err(|e| e.to_string())?;
+        if report.cancelled {
+            return Err(format!(
+                "Macro {} cancelled after {}/{} steps",
+                name, report.steps_completed, report.steps_total
+            ));
+        }
+        return Ok(format!("Macro {} completed", name));
+    }
+
+    if let DeviceCommand::Reboot = command {
+        // Goes through the device manager so health moves to rebooting and
+        // offline; the health monitor reconnects once the device is back.
+        device_manager
+            .reboot_device(device_id, RebootMode::Normal)
+            .map_err(|e| e.to_string())?;
+        return Ok(format!("Sent {}", command.describe()));
+    }
+
+    // Devices in deep standby drop off the network, taking ADB with them, so
+    // wake them over the network before asking them to turn the screen on.
+    if let DeviceCommand::Power(PowerState::On) = command
+        && device_manager.device_health(device_id).ok() == Some(DeviceHealth::Offline)
+        && device_manager
+            .wake_on_lan()
+            .mac_address(device_id)
+            .is_some()
+    {
+        let device_manager = device_manager.clone();
+        let target = device_id.to_string();
+        tokio::task::spawn_blocking(move || {
+            device_manager
+                .wake_device(&target, DEFAULT_WAKE_TIMEOUT)
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| format!("Wake-on-LAN task failed: {}", e))??;
+    }
+
+    let command = command.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut ctrl = controller
+            .lock()
+            .map_err(|e| format!("Failed to lock controller: {}", e))?;
+        let sent = format!("Sent {}", command.describe());
+        let result = match &command {
+            DeviceCommand::Key(key) => ctrl.press_key(key).map(|_| sent),
+            DeviceCommand::Text(text) => ctrl.input_text(text).map(|_| sent),
+            DeviceCommand::LaunchApp(package) => ctrl.launch_app(package).map(|_| sent),
+            DeviceCommand::Power(PowerState::On) => ctrl.ensure_on().map(|changed| {
+                if changed {
+                    sent
+                } else {
+                    "Device is already on".to_string()
+                }
+            }),
+            DeviceCommand::Power(PowerState::Off) => ctrl.ensure_off().map(|changed| {
+                if changed {
+                    sent
+                } else {
+                    "Device is already off".to_string()
+                }
+            }),
+            DeviceCommand::Power(PowerState::Toggle) => ctrl.power().map(|_| sent),
+            DeviceCommand::Macro(_) | DeviceCommand::Reboot => {
+                unreachable!("handled before locking the controller")
+            }
+        };
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("Command task failed: {}", e))?
+}
+
+pub async fn broadcast_command(
+    device_manager: Arc<GlobalDeviceManager>,
+    macro_manager: Arc<MacroManager>,
+    device_ids: Vec<String>,
+    command: DeviceCommand,
+) -> Vec<DeviceCommandResult> {
+    let handles: Vec<_> = device_ids
+        .into_iter()
+        .map(|device_id| {
+            let device_manager = device_manager.clone();
+            let macro_manager = macro_manager.clone();
+            let command = command.clone();
+            let target = device_id.clone();
+            let handle = tokio::spawn(async move {
+                execute_command(&device_manager, &macro_manager, &target, &command).await
+            });
+            (device_id, handle)
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for (device_id, handle) in handles {
+        let result = handle
+            .await
+            .unwrap_or_else(|e| Err(format!("Command task failed: {}", e)));
+        results.push(match result {
+            Ok(message) => DeviceCommandResult {
+                device_id,
+                success: true,
+                message,
+            },
+            Err(message) => DeviceCommandResult {
+                device_id,
+                success: false,
+                message,
+            },
+        });
+    }
+    results
+}
@@ -1,7 +1,8 @@
 use adb_client::{
-    ADBDeviceExt, Result, server_device::ADBServerDevice, tcp::ADBTcpDevice, usb::ADBUSBDevice,
+    ADBDeviceExt, ADBListItemType, AdbStatResponse, RebootType, Result,
+    server_device::ADBServerDevice, tcp::ADBTcpDevice, usb::ADBUSBDevice,
 };
-use std::io::Write;
+use std::io::{Read, Write};
 use std::net::{SocketAddr, SocketAddrV4};
 
 pub enum ADBDevice {
@@ -46,6 +47,88 @@ impl ADBDevice {
             Self::Usb(device) => ADBDeviceExt::shell_command(device, command, output),
         }
     }
+
+    pub fn exec(
+        &mut self,
+        command: &str,
+        input: &mut dyn Read,
+        output: Box<dyn Write + Send>,
+    ) -> Result<()> {
+        match self {
+            Self::Server(device) => ADBDeviceExt::exec(device, command, input, output),
+            Self::Tcp(device) => ADBDeviceExt::exec(device, command, input, output),
+            Self::Usb(device) => ADBDeviceExt::exec(device, command, input, output),
+        }
+    }
+
+    pub fn stat(&mut self, path: &dyn AsRef<str>) -> Result<AdbStatResponse> {
+        match self {
+            Self::Server(device) => ADBDeviceExt::stat(device, path),
+            Self::Tcp(device) => ADBDeviceExt::stat(device, path),
+            Self::Usb(device) => ADBDeviceExt::stat(device, path),
+        }
+    }
+
+    pub fn list(&mut self, path: &dyn AsRef<str>) -> Result<Vec<ADBListItemType>> {
+        match self {
+            Self::Server(device) => ADBDeviceExt::list(device, path),
+            Self::Tcp(device) => ADBDeviceExt::list(device, path),
+            Self::Usb(device) => ADBDeviceExt::list(device, path),
+        }
+    }
+
+    pub fn pull(&mut self, source: &dyn AsRef<str>, output: &mut dyn Write) -> Result<()> {
+        match self {
+            Self::Server(device) => ADBDeviceExt::pull(device, source, output),
+            Self::Tcp(device) => ADBDeviceExt::pull(device, source, output),
+            Self::Usb(device) => ADBDeviceExt::pull(device, source, output),
+        }
+    }
+
+    pub fn push(&mut self, stream: &mut dyn Read, path: &dyn AsRef<str>) -> Result<()> {
+        match self {
+            Self::Server(device) => ADBDeviceExt::push(device, stream, path),
+            Self::Tcp(device) => ADBDeviceExt::push(device, stream, path),
+            Self::Usb(device) => ADBDeviceExt::push(device, stream, path),
+        }
+    }
+
+    pub fn reboot(&mut self, reboot_type: RebootType) -> Result<()> {
+        match self {
+            Self::Server(device) => ADBDeviceExt::reboot(device, reboot_type),
+            Self::Tcp(device) => ADBDeviceExt::reboot(device, reboot_type),
+            Self::Usb(device) => ADBDeviceExt::reboot(device, reboot_type),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum DeviceConnection {
+    Tcp(SocketAddr),
+    Usb {
+        vendor_id: Option<u16>,
+        product_id: Option<u16>,
+    },
+    Server {
+        serial: String,
+        server_addr: Option<SocketAddrV4>,
+    },
+}
+
+impl DeviceConnection {
+    pub fn connect(&self) -> Result<ADBDevice> {
+        match self {
+            Self::Tcp(address) => ADBDevice::tcp(*address),
+            Self::Usb {
+                vendor_id,
+                product_id,
+            } => ADBDevice::usb(*vendor_id, *product_id),
+            Self::Server {
+                serial,
+                server_addr,
+            } => Ok(ADBDevice::server(serial.clone(), *server_addr)),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
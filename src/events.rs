@@ -0,0 +1,141 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DeviceEvent {
+    DeviceAdded {
+        device_id: String,
+    },
+    DeviceRemoved {
+        device_id: String,
+    },
+    DeviceOnline {
+        device_id: String,
+    },
+    DeviceOffline {
+        device_id: String,
+        reason: String,
+    },
+    DeviceRebooting {
+        device_id: String,
+        mode: String,
+    },
+    AppLaunched {
+        device_id: String,
+        package: String,
+    },
+    ForegroundChanged {
+        device_id: String,
+        package: Option<String>,
+        activity: Option<String>,
+    },
+    InstallFinished {
+        device_id: String,
+        package: String,
+        success: bool,
+        message: String,
+    },
+    ScheduledJobFailed {
+        job: String,
+        message: String,
+    },
+}
+
+impl DeviceEvent {
+    pub const NAMES: &[&str] = &[
+        "device_added",
+        "device_removed",
+        "device_online",
+        "device_offline",
+        "device_rebooting",
+        "app_launched",
+        "foreground_changed",
+        "install_finished",
+        "scheduled_job_failed",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeviceEvent::DeviceAdded { .. } => "device_added",
+            DeviceEvent::DeviceRemoved { .. } => "device_removed",
+            DeviceEvent::DeviceOnline { .. } => "device_online",
+            DeviceEvent::DeviceOffline { .. } => "device_offline",
+            DeviceEvent::DeviceRebooting { .. } => "device_rebooting",
+            DeviceEvent::AppLaunched { .. } => "app_launched",
+            DeviceEvent::ForegroundChanged { .. } => "foreground_changed",
+            DeviceEvent::InstallFinished { .. } => "install_finished",
+            DeviceEvent::ScheduledJobFailed { .. } => "scheduled_job_failed",
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        match self {
+            DeviceEvent::DeviceAdded { device_id } => format!("Device {} added", device_id),
+            DeviceEvent::DeviceRemoved { device_id } => format!("Device {} removed", device_id),
+            DeviceEvent::DeviceOnline { device_id } => format!("Device {} is online", device_id),
+            DeviceEvent::DeviceOffline { device_id, reason } => {
+                format!("Device {} went offline: {}", device_id, reason)
+            }
+            DeviceEvent::DeviceRebooting { device_id, mode } => {
+                format!("Device {} is rebooting: {}", device_id, mode)
+            }
+            DeviceEvent::AppLaunched { device_id, package } => {
+                format!("Launched {} on {}", package, device_id)
+            }
+            DeviceEvent::ForegroundChanged {
+                device_id,
+                package,
+                activity,
+            } => match (package, activity) {
+                (Some(_), Some(activity)) => format!("{} is showing {}", device_id, activity),
+                (Some(package), None) => format!("{} is showing {}", device_id, package),
+                (None, _) => format!("{} has no app in the foreground", device_id),
+            },
+            DeviceEvent::InstallFinished {
+                device_id,
+                package,
+                success,
+                message,
+            } => {
+                let outcome = if *success { "succeeded" } else { "failed" };
+                format!(
+                    "Install of {} on {} {}: {}",
+                    package, device_id, outcome, message
+                )
+            }
+            DeviceEvent::ScheduledJobFailed { job, message } => {
+                format!("Scheduled job {} failed: {}", job, message)
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<DeviceEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    pub fn publish(&self, event: DeviceEvent) {
+        // Sending only fails when nobody is subscribed, which is fine.
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
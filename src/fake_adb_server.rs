@@ -0,0 +1,506 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddrV4, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+/// What `host:version` reports; adb restarts servers with a different version.
+const ADB_SERVER_VERSION: u32 = 41;
+const LAUNCHER_PACKAGE: &str = "com.google.android.tvlauncher";
+const MAX_VOLUME: u32 = 15;
+
+/// Answers the shell commands sent to one fake device.
+pub trait FakeShell: Send {
+    fn run(&mut self, command: &str) -> String;
+}
+
+/// Replies to commands containing a pattern with fixed output; the reply
+/// added last wins and anything unmatched gets no output.
+#[derive(Default)]
+pub struct ScriptedShell {
+    replies: Vec<(String, String)>,
+}
+
+impl ScriptedShell {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn respond(mut self, pattern: &str, output: &str) -> Self {
+        self.replies.push((pattern.to_string(), output.to_string()));
+        self
+    }
+}
+
+impl FakeShell for ScriptedShell {
+    fn run(&mut self, command: &str) -> String {
+        self.replies
+            .iter()
+            .rev()
+            .find(|(pattern, _)| command.contains(pattern.as_str()))
+            .map(|(_, output)| output.clone())
+            .unwrap_or_default()
+    }
+}
+
+struct TvState {
+    awake: bool,
+    foreground: String,
+    installed: Vec<String>,
+    volume: u32,
+    muted: bool,
+    typed_text: String,
+    keys: Vec<u32>,
+    settings: HashMap<String, String>,
+    properties: BTreeMap<String, String>,
+}
+
+/// A TV that keeps power, foreground app, volume and settings state and
+/// changes it in response to key presses, app launches and text input.
+/// Clones share the same state.
+#[derive(Clone)]
+pub struct SimulatedTv {
+    state: Arc<Mutex<TvState>>,
+}
+
+impl Default for SimulatedTv {
+    fn default() -> Self {
+        let properties = [
+            ("ro.product.manufacturer", "Google"),
+            ("ro.product.model", "Simulated TV"),
+            ("ro.build.version.release", "12"),
+            ("ro.build.version.sdk", "31"),
+            ("sys.boot_completed", "1"),
+        ];
+        Self {
+            state: Arc::new(Mutex::new(TvState {
+                awake: true,
+                foreground: LAUNCHER_PACKAGE.to_string(),
+                installed: vec![
+                    LAUNCHER_PACKAGE.to_string(),
+                    "com.google.android.youtube.tv".to_string(),
+                    "com.netflix.ninja".to_string(),
+                ],
+                volume: 7,
+                muted: false,
+                typed_text: String::new(),
+                keys: Vec::new(),
+                settings: HashMap::new(),
+                properties: properties
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            })),
+        }
+    }
+}
+
+impl SimulatedTv {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn install(&self, package: &str) -> &Self {
+        self.state().installed.push(package.to_string());
+        self
+    }
+
+    pub fn is_awake(&self) -> bool {
+        self.state().awake
+    }
+
+    pub fn foreground(&self) -> String {
+        self.state().foreground.clone()
+    }
+
+    pub fn volume(&self) -> u32 {
+        self.state().volume
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.state().muted
+    }
+
+    pub fn typed_text(&self) -> String {
+        self.state().typed_text.clone()
+    }
+
+    /// Keycodes received so far, including ones ignored while asleep.
+    pub fn keys(&self) -> Vec<u32> {
+        self.state().keys.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, TvState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl TvState {
+    fn press(&mut self, keycode: u32) {
+        self.keys.push(keycode);
+        match keycode {
+            26 => self.awake = !self.awake,
+            223 => self.awake = false,
+            224 => self.awake = true,
+            // A sleeping TV ignores everything but the power keys.
+            _ if !self.awake => {}
+            3 => self.foreground = LAUNCHER_PACKAGE.to_string(),
+            4 if self.foreground != LAUNCHER_PACKAGE => {
+                self.foreground = LAUNCHER_PACKAGE.to_string()
+            }
+            24 => self.volume = (self.volume + 1).min(MAX_VOLUME),
+            25 => self.volume = self.volume.saturating_sub(1),
+            164 => self.muted = !self.muted,
+            _ => {}
+        }
+    }
+
+    fn launch(&mut self, package: &str) -> String {
+        if !self.installed.iter().any(|installed| installed == package) {
+            return "** No activities found to run, monkey aborted.\n".to_string();
+        }
+        self.awake = true;
+        self.foreground = package.to_string();
+        "Events injected: 1\n".to_string()
+    }
+
+    fn settings(&mut self, args: &[&str]) -> String {
+        match args {
+            ["get", namespace, key] => {
+                self.settings
+                    .get(&format!("{}/{}", namespace, key))
+                    .cloned()
+                    .unwrap_or_else(|| "null".to_string())
+                    + "\n"
+            }
+            ["put", namespace, key, value] => {
+                self.settings
+                    .insert(format!("{}/{}", namespace, key), unquote(value));
+                String::new()
+            }
+            ["delete", namespace, key] => {
+                let removed = self.settings.remove(&format!("{}/{}", namespace, key));
+                format!("Deleted {} rows\n", u8::from(removed.is_some()))
+            }
+            ["list", namespace] => {
+                let prefix = format!("{}/", namespace);
+                let mut lines: Vec<String> = self
+                    .settings
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        Some(format!("{}={}\n", key.strip_prefix(&prefix)?, value))
+                    })
+                    .collect();
+                lines.sort();
+                lines.concat()
+            }
+            _ => String::new(),
+        }
+    }
+
+    fn run(&mut self, command: &str) -> String {
+        let command = command.trim();
+        let words: Vec<&str> = command.split_whitespace().collect();
+        match words.as_slice() {
+            ["echo", rest @ ..] => format!("{}\n", rest.join(" ")),
+            ["input", "keyevent", "--longpress", keycode] | ["input", "keyevent", keycode] => {
+                match keycode.parse() {
+                    Ok(keycode) => {
+                        self.press(keycode);
+                        String::new()
+                    }
+                    Err(_) => format!("Error: Unknown keycode {}\n", keycode),
+                }
+            }
+            ["input", "text", ..] => {
+                let text = command["input text".len()..].trim();
+                self.typed_text.push_str(&unquote(text));
+                String::new()
+            }
+            ["monkey", "-p", package, ..] => self.launch(package),
+            ["settings", args @ ..] => self.settings(args),
+            ["getprop", "sys.boot_completed;", ..] => "1\n12345.67 23456.78\n".to_string(),
+            ["getprop", name] => self.properties.get(*name).cloned().unwrap_or_default() + "\n",
+            ["cmd", "media_session", "volume", "--stream", _, "--get"] => {
+                format!("volume is {} in range [0..{}]\n", self.volume, MAX_VOLUME)
+            }
+            [
+                "cmd",
+                "media_session",
+                "volume",
+                "--stream",
+                _,
+                "--set",
+                level,
+            ] => {
+                if let Ok(level) = level.parse::<u32>() {
+                    self.volume = level.min(MAX_VOLUME);
+                }
+                String::new()
+            }
+            ["dumpsys", "power", ..] => {
+                let (wakefulness, display) = if self.awake {
+                    ("Awake", "ON")
+                } else {
+                    ("Asleep", "OFF")
+                };
+                format!(
+                    "  mWakefulness={}\nDisplay Power: state={}\n",
+                    wakefulness, display
+                )
+            }
+            ["dumpsys", "window", ..] => format!(
+                "  mCurrentFocus=Window{{1a2b3c u0 {}/{}.MainActivity}}\n",
+                self.foreground, self.foreground
+            ),
+            ["dumpsys", "audio", ..] => format!("- STREAM_MUSIC:\n   Muted: {}\n", self.muted),
+            _ => String::new(),
+        }
+    }
+}
+
+impl FakeShell for SimulatedTv {
+    fn run(&mut self, command: &str) -> String {
+        self.state().run(command)
+    }
+}
+
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('\'')
+        .and_then(|value| value.strip_suffix('\''))
+        .unwrap_or(value)
+        .replace("'\\''", "'")
+        .replace("\\'", "'")
+}
+
+/// A request the server received, with the device selected on that
+/// connection at the time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReceivedRequest {
+    pub serial: Option<String>,
+    pub request: String,
+}
+
+struct FakeDevice {
+    state: String,
+    shell: Box<dyn FakeShell>,
+}
+
+#[derive(Default)]
+struct ServerState {
+    devices: BTreeMap<String, FakeDevice>,
+    requests: Vec<ReceivedRequest>,
+}
+
+/// An ADB server stand-in that speaks the smart-socket protocol on
+/// localhost, so code that talks to `adb` can run without a device.
+/// The server stops when dropped.
+pub struct FakeAdbServer {
+    addr: SocketAddrV4,
+    state: Arc<Mutex<ServerState>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl FakeAdbServer {
+    /// Listens on an ephemeral localhost port.
+    pub fn start() -> io::Result<Self> {
+        Self::bind(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0))
+    }
+
+    pub fn bind(addr: SocketAddrV4) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = match listener.local_addr()? {
+            std::net::SocketAddr::V4(addr) => addr,
+            std::net::SocketAddr::V6(_) => unreachable!("bound to an IPv4 address"),
+        };
+        let state = Arc::new(Mutex::new(ServerState::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let server_state = state.clone();
+        let server_stopped = stopped.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if server_stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let state = server_state.clone();
+                thread::spawn(move || {
+                    let _ = handle_connection(stream, &state);
+                });
+            }
+        });
+        Ok(Self {
+            addr,
+            state,
+            stopped,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddrV4 {
+        self.addr
+    }
+
+    /// Adds a device in the "device" state, i.e. online and authorized.
+    pub fn add_device(&self, serial: &str, shell: impl FakeShell + 'static) -> &Self {
+        self.state().devices.insert(
+            serial.to_string(),
+            FakeDevice {
+                state: "device".to_string(),
+                shell: Box::new(shell),
+            },
+        );
+        self
+    }
+
+    /// Changes what `host:devices` reports, e.g. "offline" or "unauthorized".
+    /// Only devices in the "device" state accept a transport.
+    pub fn set_device_state(&self, serial: &str, state: &str) {
+        if let Some(device) = self.state().devices.get_mut(serial) {
+            device.state = state.to_string();
+        }
+    }
+
+    pub fn remove_device(&self, serial: &str) {
+        self.state().devices.remove(serial);
+    }
+
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.state().requests.clone()
+    }
+
+    /// Shell commands sent to one device, in order.
+    pub fn shell_commands(&self, serial: &str) -> Vec<String> {
+        self.state()
+            .requests
+            .iter()
+            .filter(|request| request.serial.as_deref() == Some(serial))
+            .filter_map(|request| shell_command(&request.request))
+            .map(str::to_string)
+            .collect()
+    }
+
+    pub fn clear_requests(&self) {
+        self.state().requests.clear();
+    }
+
+    fn state(&self) -> MutexGuard<'_, ServerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for FakeAdbServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake the accept loop so it sees the flag.
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+/// Extracts the command from `shell:cmd`, `shell,v2,raw:cmd` or `exec:cmd`.
+fn shell_command(request: &str) -> Option<&str> {
+    if let Some(command) = request.strip_prefix("exec:") {
+        return Some(command);
+    }
+    let rest = request.strip_prefix("shell")?;
+    if !rest.starts_with([':', ',']) {
+        return None;
+    }
+    Some(rest.split_once(':')?.1)
+}
+
+fn handle_connection(mut stream: TcpStream, state: &Mutex<ServerState>) -> io::Result<()> {
+    let mut serial: Option<String> = None;
+    loop {
+        let Some(request) = read_request(&mut stream)? else {
+            return Ok(());
+        };
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.requests.push(ReceivedRequest {
+            serial: serial.clone(),
+            request: request.clone(),
+        });
+
+        if request == "host:version" {
+            let version = format!("{:04x}", ADB_SERVER_VERSION);
+            write_okay_with_body(&mut stream, &version)?;
+        } else if request == "host:devices" {
+            let list: String = state
+                .devices
+                .iter()
+                .map(|(serial, device)| format!("{}\t{}\n", serial, device.state))
+                .collect();
+            write_okay_with_body(&mut stream, &list)?;
+        } else if request == "host:features" {
+            // Without shell_v2, adb_client reads shell output unframed.
+            write_okay_with_body(&mut stream, "cmd")?;
+        } else if let Some(target) = request.strip_prefix("host:transport:") {
+            match state.devices.get(target) {
+                Some(device) if device.state == "device" => {
+                    serial = Some(target.to_string());
+                    stream.write_all(b"OKAY")?;
+                }
+                Some(device) => write_fail(&mut stream, &format!("device {}", device.state))?,
+                None => write_fail(&mut stream, &format!("device '{}' not found", target))?,
+            }
+        } else if request == "host:transport-any" {
+            let online: Vec<&String> = state
+                .devices
+                .iter()
+                .filter(|(_, device)| device.state == "device")
+                .map(|(serial, _)| serial)
+                .collect();
+            match online.as_slice() {
+                [only] => {
+                    serial = Some(only.to_string());
+                    stream.write_all(b"OKAY")?;
+                }
+                [] => write_fail(&mut stream, "no devices/emulators found")?,
+                _ => write_fail(&mut stream, "more than one device/emulator")?,
+            }
+        } else if let Some(command) = shell_command(&request) {
+            let Some(device) = serial.as_ref().and_then(|s| state.devices.get_mut(s)) else {
+                return write_fail(&mut stream, "no device selected");
+            };
+            let output = device.shell.run(command);
+            drop(state);
+            // Shell sessions end by closing the socket.
+            stream.write_all(b"OKAY")?;
+            stream.write_all(output.as_bytes())?;
+            return stream.shutdown(Shutdown::Both);
+        } else if request.starts_with("reboot:") && serial.is_some() {
+            stream.write_all(b"OKAY")?;
+            return stream.shutdown(Shutdown::Both);
+        } else {
+            write_fail(&mut stream, &format!("unknown host service: {}", request))?;
+        }
+    }
+}
+
+/// Reads one `<4 hex digits><payload>` request; None when the client hung up.
+fn read_request(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let mut length = [0; 4];
+    match stream.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let length = std::str::from_utf8(&length)
+        .ok()
+        .and_then(|length| usize::from_str_radix(length, 16).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid request length"))?;
+    let mut payload = vec![0; length];
+    stream.read_exact(&mut payload)?;
+    Ok(Some(String::from_utf8_lossy(&payload).into_owned()))
+}
+
+fn write_okay_with_body(stream: &mut TcpStream, body: &str) -> io::Result<()> {
+    stream.write_all(format!("OKAY{:04x}{}", body.len(), body).as_bytes())
+}
+
+fn write_fail(stream: &mut TcpStream, message: &str) -> io::Result<()> {
+    stream.write_all(format!("FAIL{:04x}{}", message.len(), message).as_bytes())
+}
@@ -0,0 +1,422 @@
+use crate::tv_remote::{RemoteIdentity, crypto_provider, pairing_secret};
+use crate::tv_remote_proto::{
+    CODE_LENGTH, ENCODING_HEXADECIMAL, FrameReader, KeyDirection, PairingMessage, ROLE_INPUT,
+    RemoteDeviceInfo, RemoteMessage, write_frame,
+};
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, UnixTime};
+use rustls::server::danger::{ClientCertVerified, ClientCertVerifier};
+use rustls::{
+    DigitallySignedStruct, DistinguishedName, ServerConfig, ServerConnection, SignatureScheme,
+    StreamOwned,
+};
+use std::error::Error;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const LAUNCHER: &str = "com.google.android.tvlauncher";
+const MAX_VOLUME: u32 = 15;
+const STATUS_BAD_SECRET: u64 = 402;
+
+type TlsStream = StreamOwned<ServerConnection, TcpStream>;
+
+struct TvState {
+    code: Option<String>,
+    secret: Option<Vec<u8>>,
+    paired: Vec<Vec<u8>>,
+    awake: bool,
+    volume: u32,
+    muted: bool,
+    foreground: String,
+    keys: Vec<(u32, KeyDirection)>,
+    app_links: Vec<String>,
+    pings_answered: u32,
+}
+
+impl Default for TvState {
+    fn default() -> Self {
+        Self {
+            code: None,
+            secret: None,
+            paired: Vec::new(),
+            awake: true,
+            volume: 7,
+            muted: false,
+            foreground: LAUNCHER.to_string(),
+            keys: Vec::new(),
+            app_links: Vec::new(),
+            pings_answered: 0,
+        }
+    }
+}
+
+impl TvState {
+    fn volume_message(&self) -> RemoteMessage {
+        RemoteMessage::SetVolumeLevel {
+            max: MAX_VOLUME,
+            level: self.volume,
+            muted: self.muted,
+        }
+    }
+
+    fn foreground_message(&self) -> RemoteMessage {
+        RemoteMessage::ImeKeyInject {
+            app_package: self.foreground.clone(),
+        }
+    }
+
+    // Applies a key like a TV would and returns the update it reports back.
+    fn press(&mut self, key_code: u32) -> Option<RemoteMessage> {
+        match key_code {
+            26 => self.awake = !self.awake,
+            223 => self.awake = false,
+            224 => self.awake = true,
+            _ if !self.awake => return None,
+            3 => {
+                self.foreground = LAUNCHER.to_string();
+                return Some(self.foreground_message());
+            }
+            24 => self.volume = (self.volume + 1).min(MAX_VOLUME),
+            25 => self.volume = self.volume.saturating_sub(1),
+            164 => self.muted = !self.muted,
+            _ => return None,
+        }
+        Some(match key_code {
+            26 | 223 | 224 => RemoteMessage::Start(self.awake),
+            _ => self.volume_message(),
+        })
+    }
+}
+
+/// TVs accept any client certificate during the handshake and only later
+/// check whether it was paired.
+#[derive(Debug)]
+struct AnyClientCertificate(Arc<CryptoProvider>);
+
+impl ClientCertVerifier for AnyClientCertificate {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// A stand-in for the Android TV Remote service of a TV: it pairs clients
+/// with an on-screen code, then accepts keys and app links from paired
+/// clients and reports power, volume and the foreground app back.
+/// The server stops when dropped.
+pub struct FakeTvRemote {
+    pairing_addr: SocketAddr,
+    remote_addr: SocketAddr,
+    state: Arc<Mutex<TvState>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl FakeTvRemote {
+    /// Listens on ephemeral loopback ports for pairing and remote control.
+    pub fn start() -> Result<Self, Box<dyn Error>> {
+        let identity = RemoteIdentity::generate("Fake TV")?;
+        let provider = crypto_provider();
+        let config = Arc::new(
+            ServerConfig::builder_with_provider(provider.clone())
+                .with_safe_default_protocol_versions()?
+                .with_client_cert_verifier(Arc::new(AnyClientCertificate(provider)))
+                .with_single_cert(vec![identity.certificate()], identity.private_key())?,
+        );
+        let state = Arc::new(Mutex::new(TvState::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let server_certificate = identity.certificate().to_vec();
+        let pairing_addr = listen(&config, &state, &stopped, move |stream, state| {
+            handle_pairing(stream, state, &server_certificate)
+        })?;
+        let remote_addr = listen(&config, &state, &stopped, handle_remote)?;
+        Ok(Self {
+            pairing_addr,
+            remote_addr,
+            state,
+            stopped,
+        })
+    }
+
+    pub fn pairing_addr(&self) -> SocketAddr {
+        self.pairing_addr
+    }
+
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
+    /// The code the TV is showing, once a client has asked to pair.
+    pub fn pairing_code(&self) -> Option<String> {
+        self.state().code.clone()
+    }
+
+    pub fn paired_clients(&self) -> usize {
+        self.state().paired.len()
+    }
+
+    pub fn is_awake(&self) -> bool {
+        self.state().awake
+    }
+
+    pub fn volume(&self) -> u32 {
+        self.state().volume
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.state().muted
+    }
+
+    pub fn foreground(&self) -> String {
+        self.state().foreground.clone()
+    }
+
+    pub fn keys(&self) -> Vec<(u32, KeyDirection)> {
+        self.state().keys.clone()
+    }
+
+    pub fn app_links(&self) -> Vec<String> {
+        self.state().app_links.clone()
+    }
+
+    pub fn pings_answered(&self) -> u32 {
+        self.state().pings_answered
+    }
+
+    fn state(&self) -> MutexGuard<'_, TvState> {
+        lock(&self.state)
+    }
+}
+
+impl Drop for FakeTvRemote {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake the accept loops so they see the flag.
+        let _ = TcpStream::connect(self.pairing_addr);
+        let _ = TcpStream::connect(self.remote_addr);
+    }
+}
+
+fn lock(state: &Mutex<TvState>) -> MutexGuard<'_, TvState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn listen(
+    config: &Arc<ServerConfig>,
+    state: &Arc<Mutex<TvState>>,
+    stopped: &Arc<AtomicBool>,
+    handler: impl Fn(TlsStream, &Mutex<TvState>) -> Result<(), Box<dyn Error>> + Clone + Send + 'static,
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let addr = listener.local_addr()?;
+    let config = config.clone();
+    let state = state.clone();
+    let stopped = stopped.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            let Ok(stream) = stream else {
+                continue;
+            };
+            let (config, state, handler) = (config.clone(), state.clone(), handler.clone());
+            thread::spawn(move || {
+                let _ = accept(stream, config).and_then(|stream| handler(stream, &state));
+            });
+        }
+    });
+    Ok(addr)
+}
+
+fn accept(socket: TcpStream, config: Arc<ServerConfig>) -> Result<TlsStream, Box<dyn Error>> {
+    let mut stream = StreamOwned::new(ServerConnection::new(config)?, socket);
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+    Ok(stream)
+}
+
+fn client_certificate(stream: &TlsStream) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(stream
+        .conn
+        .peer_certificates()
+        .and_then(|certificates| certificates.first())
+        .ok_or("Client did not present a certificate")?
+        .to_vec())
+}
+
+fn handle_pairing(
+    mut stream: TlsStream,
+    state: &Mutex<TvState>,
+    server_certificate: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    let client_certificate = client_certificate(&stream)?;
+    let mut reader = FrameReader::new();
+    loop {
+        let message = PairingMessage::decode(&reader.read_frame(&mut stream)?)?;
+        let reply = match message {
+            PairingMessage::Request { .. } => PairingMessage::RequestAck {
+                server_name: "Fake TV".to_string(),
+            },
+            PairingMessage::Options { .. } => PairingMessage::Options {
+                encoding: ENCODING_HEXADECIMAL,
+                symbol_length: CODE_LENGTH,
+                preferred_role: ROLE_INPUT,
+            },
+            PairingMessage::Configuration { .. } => {
+                let nonce = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)?
+                    .subsec_nanos()
+                    .to_be_bytes()[2..]
+                    .to_vec();
+                let secret = pairing_secret(&client_certificate, server_certificate, &nonce)?;
+                let mut state = lock(state);
+                state.code = Some(hex::encode_upper([&secret[..1], &nonce[..]].concat()));
+                state.secret = Some(secret);
+                PairingMessage::ConfigurationAck
+            }
+            PairingMessage::Secret(secret) => {
+                let mut state = lock(state);
+                if state.secret.as_ref() == Some(&secret) {
+                    state.code = None;
+                    state.secret = None;
+                    state.paired.push(client_certificate.clone());
+                    PairingMessage::SecretAck(secret)
+                } else {
+                    PairingMessage::Error(STATUS_BAD_SECRET)
+                }
+            }
+            message => return Err(format!("Unexpected pairing message {:?}", message).into()),
+        };
+        write_frame(&mut stream, &reply.encode())?;
+    }
+}
+
+fn send(stream: &mut TlsStream, message: RemoteMessage) -> io::Result<()> {
+    write_frame(stream, &message.encode())
+}
+
+fn handle_remote(mut stream: TlsStream, state: &Mutex<TvState>) -> Result<(), Box<dyn Error>> {
+    // Real TVs drop unpaired clients right after the handshake.
+    if !lock(state).paired.contains(&client_certificate(&stream)?) {
+        return Ok(());
+    }
+    let mut reader = FrameReader::new();
+    let mut read = |stream: &mut TlsStream| -> Result<RemoteMessage, Box<dyn Error>> {
+        RemoteMessage::decode(&reader.read_frame(stream)?)
+    };
+
+    send(
+        &mut stream,
+        RemoteMessage::Configure {
+            code: 622,
+            device_info: Some(RemoteDeviceInfo {
+                model: "Fake TV".to_string(),
+                vendor: "atvmate".to_string(),
+                package_name: "com.google.android.tv.remote.service".to_string(),
+                app_version: "5.2.473254133".to_string(),
+            }),
+        },
+    )?;
+    let RemoteMessage::Configure { .. } = read(&mut stream)? else {
+        return Err("Expected the client configuration".into());
+    };
+    send(&mut stream, RemoteMessage::SetActive(622))?;
+    let RemoteMessage::SetActive(_) = read(&mut stream)? else {
+        return Err("Expected the client to become active".into());
+    };
+    let updates = {
+        let state = lock(state);
+        [
+            RemoteMessage::PingRequest(1),
+            RemoteMessage::Start(state.awake),
+            state.volume_message(),
+            state.foreground_message(),
+        ]
+    };
+    for message in updates {
+        send(&mut stream, message)?;
+    }
+
+    loop {
+        let update = {
+            let message = read(&mut stream)?;
+            let mut state = lock(state);
+            match message {
+                RemoteMessage::PingResponse(_) => {
+                    state.pings_answered += 1;
+                    None
+                }
+                RemoteMessage::KeyInject {
+                    key_code,
+                    direction,
+                } => {
+                    state.keys.push((key_code, direction.clone()));
+                    match direction {
+                        KeyDirection::StartLong => None,
+                        _ => state.press(key_code),
+                    }
+                }
+                RemoteMessage::AppLinkLaunch(app_link) => {
+                    state.app_links.push(app_link.clone());
+                    match app_link.strip_prefix("market://launch?id=") {
+                        Some(package) if state.awake => {
+                            state.foreground = package.to_string();
+                            Some(state.foreground_message())
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }
+        };
+        if let Some(update) = update {
+            send(&mut stream, update)?;
+        }
+    }
+}
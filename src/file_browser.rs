@@ -0,0 +1,109 @@
+use adb_client::{ADBListItem, ADBListItemType, AdbStatResponse};
+use chrono::DateTime;
+use poem_openapi::{Enum, Object};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum FileKind {
+    File,
+    Directory,
+    Symlink,
+    Other,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct FileEntry {
+    pub name: String,
+    pub path: String,
+    pub kind: FileKind,
+    pub size: u64,
+    pub mode: u32,
+    /// `ls -l` style permissions, e.g. "drwxrwx--x".
+    pub permissions: String,
+    pub modified: Option<String>,
+}
+
+impl FileEntry {
+    pub fn from_list_item(directory: &str, item: ADBListItemType) -> Self {
+        let (kind, item): (FileKind, ADBListItem) = match item {
+            ADBListItemType::File(item) => (FileKind::File, item),
+            ADBListItemType::Directory(item) => (FileKind::Directory, item),
+            ADBListItemType::Symlink(item) => (FileKind::Symlink, item),
+        };
+        Self {
+            path: join_path(directory, &item.name),
+            name: item.name,
+            kind,
+            size: u64::from(item.size),
+            mode: item.permissions,
+            permissions: permissions_string(item.permissions),
+            modified: format_mtime(item.time),
+        }
+    }
+
+    pub fn from_stat(path: &str, stat: &AdbStatResponse) -> Self {
+        let kind = match stat.file_perm & S_IFMT {
+            S_IFDIR => FileKind::Directory,
+            S_IFREG => FileKind::File,
+            S_IFLNK => FileKind::Symlink,
+            _ => FileKind::Other,
+        };
+        let name = path.trim_end_matches('/').rsplit('/').next().unwrap_or("");
+        Self {
+            name: name.to_string(),
+            path: path.to_string(),
+            kind,
+            size: u64::from(stat.file_size),
+            mode: stat.file_perm,
+            permissions: permissions_string(stat.file_perm),
+            modified: format_mtime(stat.mod_time),
+        }
+    }
+}
+
+pub fn validate_remote_path(path: &str) -> Result<(), Box<dyn Error>> {
+    if !path.starts_with('/') {
+        return Err(format!("Path must be absolute: {}", path).into());
+    }
+    if path.chars().any(char::is_control) {
+        return Err("Path must not contain control characters".into());
+    }
+    if path.split('/').any(|segment| segment == "..") {
+        return Err(format!("Path must not contain '..': {}", path).into());
+    }
+    Ok(())
+}
+
+fn join_path(directory: &str, name: &str) -> String {
+    format!("{}/{}", directory.trim_end_matches('/'), name)
+}
+
+fn format_mtime(time: u32) -> Option<String> {
+    DateTime::from_timestamp(i64::from(time), 0).map(|time| time.to_rfc3339())
+}
+
+fn permissions_string(mode: u32) -> String {
+    let kind = match mode & S_IFMT {
+        S_IFDIR => 'd',
+        S_IFLNK => 'l',
+        S_IFREG => '-',
+        _ => '?',
+    };
+    let mut permissions = String::with_capacity(10);
+    permissions.push(kind);
+    for shift in [6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        permissions.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        permissions.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        permissions.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    permissions
+}
@@ -0,0 +1,80 @@
+use crate::atv_controller::ForegroundApp;
+use crate::events::DeviceEvent;
+use crate::global_device_manager::{DeviceHealth, GlobalDeviceManager};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Reads `ATVMATE_FOREGROUND_POLL_SECS`; the watcher is disabled when it is unset or zero.
+pub fn poll_interval_from_env() -> Result<Option<Duration>, Box<dyn Error>> {
+    let Ok(value) = std::env::var("ATVMATE_FOREGROUND_POLL_SECS") else {
+        return Ok(None);
+    };
+    let secs: u64 = value
+        .parse()
+        .map_err(|e| format!("Invalid ATVMATE_FOREGROUND_POLL_SECS '{}': {}", value, e))?;
+    Ok((secs > 0).then(|| Duration::from_secs(secs)))
+}
+
+pub fn start_foreground_watcher(
+    device_manager: Arc<GlobalDeviceManager>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut last_seen: HashMap<String, Option<ForegroundApp>> = HashMap::new();
+        loop {
+            ticker.tick().await;
+            let devices = device_manager.list_devices();
+            last_seen.retain(|device_id, _| devices.contains(device_id));
+
+            let polls: Vec<_> = devices
+                .into_iter()
+                .map(|device_id| {
+                    let device_manager = device_manager.clone();
+                    let target = device_id.clone();
+                    let handle =
+                        tokio::task::spawn_blocking(move || poll_device(&device_manager, &target));
+                    (device_id, handle)
+                })
+                .collect();
+
+            for (device_id, handle) in polls {
+                let Ok(Some(current)) = handle.await else {
+                    continue;
+                };
+                // The first observation only establishes a baseline.
+                let changed =
+                    matches!(last_seen.get(&device_id), Some(previous) if *previous != current);
+                last_seen.insert(device_id.clone(), current.clone());
+                if changed {
+                    let (package, activity) = match current {
+                        Some(app) => (Some(app.package), app.activity),
+                        None => (None, None),
+                    };
+                    device_manager
+                        .events()
+                        .publish(DeviceEvent::ForegroundChanged {
+                            device_id,
+                            package,
+                            activity,
+                        });
+                }
+            }
+        }
+    })
+}
+
+fn poll_device(
+    device_manager: &GlobalDeviceManager,
+    device_id: &str,
+) -> Option<Option<ForegroundApp>> {
+    if device_manager.device_health(device_id).ok()? != DeviceHealth::Online {
+        return None;
+    }
+    let controller = device_manager.get_controller(device_id).ok()?;
+    // Skip busy controllers rather than queueing behind a running macro.
+    let mut ctrl = controller.try_lock().ok()?;
+    ctrl.foreground_app().ok()
+}
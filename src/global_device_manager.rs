@@ -1,29 +1,72 @@
 use crate::adb_service::ADBService;
 use crate::atv_controller::ATVController;
-use crate::device::{ADBDevice, DiscoveredDevice};
+use crate::device::{ADBDevice, DeviceConnection, DiscoveredDevice};
 use crate::device_manager::DeviceManager;
+use crate::events::{DeviceEvent, EventBus};
+use crate::metrics::metrics;
+use crate::reboot::RebootMode;
+use crate::transport::{AdbTransport, DeviceTransport};
+use crate::tv_remote::{RemoteIdentity, RemoteTransport};
+use crate::wake_on_lan::WakeOnLan;
 use adb_client::usb::find_all_connected_adb_devices;
+use poem_openapi::Enum;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::error::Error;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const REBOOT_POLL_INTERVAL: Duration = Duration::from_secs(3);
+const WAKE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum DeviceHealth {
+    Online,
+    Offline,
+    Rebooting,
+}
+
+struct ManagedDevice {
+    controller: Arc<Mutex<ATVController>>,
+    health: DeviceHealth,
+}
 
 pub struct GlobalDeviceManager {
-    devices: Arc<Mutex<HashMap<String, Arc<Mutex<ATVController>>>>>,
+    devices: Arc<Mutex<HashMap<String, ManagedDevice>>>,
+    events: EventBus,
+    wake_on_lan: WakeOnLan,
 }
 
 impl GlobalDeviceManager {
     pub fn new() -> Self {
         Self {
             devices: Arc::new(Mutex::new(HashMap::new())),
+            events: EventBus::new(),
+            wake_on_lan: WakeOnLan::new(),
         }
     }
 
+    pub fn with_wake_on_lan(mut self, wake_on_lan: WakeOnLan) -> Self {
+        self.wake_on_lan = wake_on_lan;
+        self
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn wake_on_lan(&self) -> &WakeOnLan {
+        &self.wake_on_lan
+    }
+
     pub fn add_device(&self, ip: &str, port: u16) -> Result<(), Box<dyn Error>> {
         let ip_addr: Ipv4Addr = ip.parse()?;
         let address = SocketAddr::new(IpAddr::V4(ip_addr), port);
-        let device = ADBDevice::tcp(address)?;
-        self.insert_device(ip.to_string(), device)
+        let transport = AdbTransport::connect(DeviceConnection::Tcp(address))?;
+        self.insert_device(ip.to_string(), Box::new(transport))
     }
 
     pub fn add_usb_device(
@@ -33,9 +76,13 @@ impl GlobalDeviceManager {
     ) -> Result<String, Box<dyn Error>> {
         let device_id = match (vid, pid) {
             (Some(vendor_id), Some(product_id)) => {
-                let device = ADBDevice::usb(Some(vendor_id), Some(product_id))?;
+                let connection = DeviceConnection::Usb {
+                    vendor_id: Some(vendor_id),
+                    product_id: Some(product_id),
+                };
+                let transport = AdbTransport::connect(connection)?;
                 let device_id = format!("usb:{vendor_id:04x}:{product_id:04x}");
-                self.insert_device(device_id.clone(), device)?;
+                self.insert_device(device_id.clone(), Box::new(transport))?;
                 device_id
             }
             (None, None) => {
@@ -45,7 +92,13 @@ impl GlobalDeviceManager {
                     .ok_or_else(|| "No USB ADB devices found".to_string())?;
                 let device = ADBDevice::usb(None, None)?;
                 let device_id = format!("usb:{:04x}:{:04x}", info.vendor_id, info.product_id);
-                self.insert_device(device_id.clone(), device)?;
+                // Pin reconnects to the device that autodetection picked.
+                let connection = DeviceConnection::Usb {
+                    vendor_id: Some(info.vendor_id),
+                    product_id: Some(info.product_id),
+                };
+                let transport = AdbTransport::new(connection, device);
+                self.insert_device(device_id.clone(), Box::new(transport))?;
                 device_id
             }
             _ => {
@@ -62,14 +115,63 @@ impl GlobalDeviceManager {
         serial: &str,
     ) -> Result<String, Box<dyn Error>> {
         let device_id = format!("server:{serial}");
-        let device = ADBDevice::server(serial.to_string(), server_addr);
-        self.insert_device(device_id.clone(), device)?;
+        let connection = DeviceConnection::Server {
+            serial: serial.to_string(),
+            server_addr,
+        };
+        let transport = AdbTransport::connect(connection)?;
+        self.insert_device(device_id.clone(), Box::new(transport))?;
         Ok(device_id)
     }
 
+    /// Adds a TV controlled through the Android TV Remote service. The TV
+    /// must already be paired with `identity`.
+    pub fn add_remote_device(
+        &self,
+        addr: SocketAddr,
+        identity: &RemoteIdentity,
+    ) -> Result<String, Box<dyn Error>> {
+        let device_id = format!("remote:{}", addr.ip());
+        let transport = RemoteTransport::connect(addr, identity)?;
+        self.insert_device(device_id.clone(), Box::new(transport))?;
+        Ok(device_id)
+    }
+
+    /// Adds a device reached through any transport, e.g. a mock in tests.
+    pub fn attach_device(
+        &self,
+        device_id: &str,
+        transport: Box<dyn DeviceTransport>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.insert_device(device_id.to_string(), transport)
+    }
+
     pub fn discover_devices(&self) -> Vec<DiscoveredDevice> {
+        let mut discovered_devices = self.discover_server_devices(None);
+
+        if let Ok(devices) = find_all_connected_adb_devices() {
+            for device in devices {
+                let identifier = format!("{:04x}:{:04x}", device.vendor_id, device.product_id);
+                discovered_devices.push(DiscoveredDevice {
+                    id: format!("usb:{identifier}"),
+                    identifier,
+                    connection_type: "usb".to_string(),
+                    state: "available".to_string(),
+                });
+            }
+        }
+
+        discovered_devices
+    }
+
+    /// Lists devices known to an ADB server, the default local one unless
+    /// `server_addr` is given.
+    pub fn discover_server_devices(
+        &self,
+        server_addr: Option<SocketAddrV4>,
+    ) -> Vec<DiscoveredDevice> {
         let mut discovered_devices = Vec::new();
-        let mut adb_service = ADBService::default();
+        let mut adb_service = server_addr.map(ADBService::from_addr).unwrap_or_default();
 
         if let Ok(devices) = adb_service.get_devices() {
             for device in devices {
@@ -88,18 +190,6 @@ impl GlobalDeviceManager {
             }
         }
 
-        if let Ok(devices) = find_all_connected_adb_devices() {
-            for device in devices {
-                let identifier = format!("{:04x}:{:04x}", device.vendor_id, device.product_id);
-                discovered_devices.push(DiscoveredDevice {
-                    id: format!("usb:{identifier}"),
-                    identifier,
-                    connection_type: "usb".to_string(),
-                    state: "available".to_string(),
-                });
-            }
-        }
-
         discovered_devices
     }
 
@@ -109,6 +199,10 @@ impl GlobalDeviceManager {
             .lock()
             .map_err(|e| format!("Failed to lock devices: {}", e))?;
         if devices.remove(device_id).is_some() {
+            drop(devices);
+            self.events.publish(DeviceEvent::DeviceRemoved {
+                device_id: device_id.to_string(),
+            });
             Ok(())
         } else {
             Err(format!("Device {} not found", device_id).into())
@@ -125,22 +219,211 @@ impl GlobalDeviceManager {
             .map_err(|e| format!("Failed to lock devices: {}", e))?;
         devices
             .get(device_id)
-            .cloned()
+            .map(|device| device.controller.clone())
             .ok_or_else(|| format!("Device {} not found", device_id).into())
     }
 
+    pub fn device_health(&self, device_id: &str) -> Result<DeviceHealth, Box<dyn Error>> {
+        let devices = self
+            .devices
+            .lock()
+            .map_err(|e| format!("Failed to lock devices: {}", e))?;
+        devices
+            .get(device_id)
+            .map(|device| device.health)
+            .ok_or_else(|| format!("Device {} not found", device_id).into())
+    }
+
+    pub fn set_device_health(&self, device_id: &str, health: DeviceHealth, reason: &str) {
+        let mut devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(device) = devices.get_mut(device_id) else {
+            return;
+        };
+        if device.health == health {
+            return;
+        }
+        device.health = health;
+        drop(devices);
+
+        let device_id = device_id.to_string();
+        self.events.publish(match health {
+            DeviceHealth::Online => DeviceEvent::DeviceOnline { device_id },
+            DeviceHealth::Offline => DeviceEvent::DeviceOffline {
+                device_id,
+                reason: reason.to_string(),
+            },
+            DeviceHealth::Rebooting => DeviceEvent::DeviceRebooting {
+                device_id,
+                mode: reason.to_string(),
+            },
+        });
+    }
+
+    pub fn reconnect_device(&self, device_id: &str) -> Result<(), Box<dyn Error>> {
+        let controller = self.get_controller(device_id)?;
+        let mut ctrl = controller
+            .lock()
+            .map_err(|e| format!("Failed to lock controller: {}", e))?;
+        let result = ctrl.reconnect();
+        metrics().record_reconnect(device_id, result.is_ok());
+        result
+    }
+
+    /// Reboots the device and marks it offline until it is reconnected, either
+    /// by `wait_for_reboot` or by the health monitor.
+    pub fn reboot_device(&self, device_id: &str, mode: RebootMode) -> Result<(), Box<dyn Error>> {
+        let controller = self.get_controller(device_id)?;
+        let previous = self.device_health(device_id)?;
+        self.set_device_health(device_id, DeviceHealth::Rebooting, mode.describe());
+        let result = controller
+            .lock()
+            .map_err(|e| format!("Failed to lock controller: {}", e))
+            .and_then(|mut ctrl| ctrl.reboot(mode).map_err(|e| e.to_string()));
+        match result {
+            Ok(_) => {
+                self.set_device_health(device_id, DeviceHealth::Offline, mode.describe());
+                Ok(())
+            }
+            Err(e) => {
+                self.set_device_health(device_id, previous, &e);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Blocks until the device has rebooted and finished booting, reconnecting
+    /// as needed. Returns how long the device took to come back.
+    pub fn wait_for_reboot(
+        &self,
+        device_id: &str,
+        rebooted_at: Instant,
+        timeout: Duration,
+    ) -> Result<Duration, Box<dyn Error>> {
+        let controller = self.get_controller(device_id)?;
+        loop {
+            std::thread::sleep(REBOOT_POLL_INTERVAL);
+            let boot_state = controller
+                .lock()
+                .map_err(|e| format!("Failed to lock controller: {}", e))?
+                .boot_state();
+            match boot_state {
+                // An uptime longer than the time since the reboot means the
+                // device has not gone down yet.
+                Ok(state) if state.boot_completed && state.uptime < rebooted_at.elapsed() => {
+                    self.set_device_health(device_id, DeviceHealth::Online, "");
+                    return Ok(rebooted_at.elapsed());
+                }
+                Ok(_) => {}
+                Err(_) => {
+                    let _ = self.reconnect_device(device_id);
+                }
+            }
+            if rebooted_at.elapsed() >= timeout {
+                return Err(format!(
+                    "Device {} did not come back within {}s",
+                    device_id,
+                    timeout.as_secs()
+                )
+                .into());
+            }
+        }
+    }
+
+    /// Asks the device for its MAC address and remembers it, so the device can
+    /// still be woken once it drops off the network.
+    pub fn learn_mac_address(&self, device_id: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let controller = self.get_controller(device_id)?;
+        let mac = controller
+            .lock()
+            .map_err(|e| format!("Failed to lock controller: {}", e))?
+            .mac_address()?;
+        if let Some(mac) = &mac {
+            self.wake_on_lan.record(device_id, mac)?;
+        }
+        Ok(mac)
+    }
+
+    /// Sends a Wake-on-LAN packet and waits for ADB to come back. Returns how
+    /// long that took.
+    pub fn wake_device(
+        &self,
+        device_id: &str,
+        timeout: Duration,
+    ) -> Result<Duration, Box<dyn Error>> {
+        self.wake_on_lan.send(device_id, None)?;
+        self.wait_for_adb(device_id, timeout)
+    }
+
+    /// Blocks until the device answers again, reconnecting on every attempt.
+    pub fn wait_for_adb(
+        &self,
+        device_id: &str,
+        timeout: Duration,
+    ) -> Result<Duration, Box<dyn Error>> {
+        let controller = self.get_controller(device_id)?;
+        let started = Instant::now();
+        loop {
+            std::thread::sleep(WAKE_POLL_INTERVAL);
+            let online = self.reconnect_device(device_id).and_then(|_| {
+                controller
+                    .lock()
+                    .map_err(|e| format!("Failed to lock controller: {}", e))?
+                    .ping()
+            });
+            if online.is_ok() {
+                self.set_device_health(device_id, DeviceHealth::Online, "");
+                return Ok(started.elapsed());
+            }
+            if started.elapsed() >= timeout {
+                return Err(format!(
+                    "Device {} did not come back within {}s",
+                    device_id,
+                    timeout.as_secs()
+                )
+                .into());
+            }
+        }
+    }
+
+    /// Opens an extra connection for long-running commands so they don't hold
+    /// the controller lock. USB devices can only be claimed once.
+    pub fn open_connection(
+        &self,
+        device_id: &str,
+    ) -> Result<Box<dyn DeviceTransport>, Box<dyn Error>> {
+        let controller = self.get_controller(device_id)?;
+        let ctrl = controller
+            .lock()
+            .map_err(|e| format!("Failed to lock controller: {}", e))?;
+        ctrl.open_connection()
+            .map_err(|e| format!("Device {}: {}", device_id, e).into())
+    }
+
     pub fn list_devices(&self) -> Vec<String> {
         let devices = self.devices.lock().unwrap_or_else(|e| e.into_inner());
         devices.keys().cloned().collect()
     }
 
-    fn insert_device(&self, id: String, device: ADBDevice) -> Result<(), Box<dyn Error>> {
-        let controller = ATVController::new(device);
+    fn insert_device(
+        &self,
+        id: String,
+        transport: Box<dyn DeviceTransport>,
+    ) -> Result<(), Box<dyn Error>> {
+        let controller = ATVController::new(id.clone(), transport, self.events.clone());
         let mut devices = self
             .devices
             .lock()
             .map_err(|e| format!("Failed to lock devices: {}", e))?;
-        devices.insert(id, Arc::new(Mutex::new(controller)));
+        devices.insert(
+            id.clone(),
+            ManagedDevice {
+                controller: Arc::new(Mutex::new(controller)),
+                health: DeviceHealth::Online,
+            },
+        );
+        drop(devices);
+        self.events
+            .publish(DeviceEvent::DeviceAdded { device_id: id });
         Ok(())
     }
 }
@@ -0,0 +1,88 @@
+use crate::global_device_manager::GlobalDeviceManager;
+use crate::storage::{load_json, save_json};
+use poem_openapi::Object;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+pub const ALL_DEVICES_GROUP: &str = "all";
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct DeviceGroup {
+    pub name: String,
+    pub devices: Vec<String>,
+}
+
+pub struct GroupManager {
+    path: PathBuf,
+    groups: Mutex<BTreeMap<String, DeviceGroup>>,
+}
+
+impl GroupManager {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let path = path.into();
+        let groups = load_json(&path)?;
+        Ok(Self {
+            path,
+            groups: Mutex::new(groups),
+        })
+    }
+
+    pub fn list_groups(&self) -> Vec<DeviceGroup> {
+        let groups = self.groups.lock().unwrap_or_else(|e| e.into_inner());
+        groups.values().cloned().collect()
+    }
+
+    pub fn save_group(&self, group: DeviceGroup) -> Result<(), Box<dyn Error>> {
+        let valid_name = !group.name.is_empty()
+            && group
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            return Err(format!("Invalid group name: {}", group.name).into());
+        }
+        if group.name == ALL_DEVICES_GROUP {
+            return Err(format!("Group name '{}' is reserved", ALL_DEVICES_GROUP).into());
+        }
+        let mut groups = self
+            .groups
+            .lock()
+            .map_err(|e| format!("Failed to lock groups: {}", e))?;
+        groups.insert(group.name.clone(), group);
+        save_json(&self.path, &*groups)
+    }
+
+    pub fn remove_group(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let mut groups = self
+            .groups
+            .lock()
+            .map_err(|e| format!("Failed to lock groups: {}", e))?;
+        if groups.remove(name).is_none() {
+            return Err(format!("Group {} not found", name).into());
+        }
+        save_json(&self.path, &*groups)
+    }
+
+    pub fn resolve_devices(
+        &self,
+        name: &str,
+        device_manager: &GlobalDeviceManager,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        if name == ALL_DEVICES_GROUP {
+            let mut devices = device_manager.list_devices();
+            devices.sort();
+            return Ok(devices);
+        }
+        let groups = self
+            .groups
+            .lock()
+            .map_err(|e| format!("Failed to lock groups: {}", e))?;
+        groups
+            .get(name)
+            .map(|group| group.devices.clone())
+            .ok_or_else(|| format!("Group {} not found", name).into())
+    }
+}
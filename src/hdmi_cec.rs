@@ -0,0 +1,64 @@
+use poem_openapi::{Enum, Object};
+use serde::{Deserialize, Serialize};
+
+pub const CEC_ENABLED_SETTING: &str = "hdmi_control_enabled";
+pub const CEC_AUTO_WAKEUP_SETTING: &str = "hdmi_control_auto_wakeup_enabled";
+pub const CEC_AUTO_DEVICE_OFF_SETTING: &str = "hdmi_control_auto_device_off_enabled";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum TvPowerStatus {
+    On,
+    Standby,
+    TransientToOn,
+    TransientToStandby,
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum CecAction {
+    /// Wakes this box, then uses One Touch Play to turn the TV on and select this input.
+    TvOn,
+    TvOff,
+    SwitchInput,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct CecStatus {
+    pub tv_power: TvPowerStatus,
+    pub enabled: Option<bool>,
+    /// The TV wakes this box when it is turned on.
+    pub auto_wakeup: Option<bool>,
+    /// This box puts the TV into standby when it goes to sleep.
+    pub auto_device_off: Option<bool>,
+}
+
+// The TV is always logical address 0 in `dumpsys hdmi_control`, e.g.
+//   CEC: logical_address: 0x00 device_type: 0 ... display_name: TV power_status: 0 physical_address: 0x0000
+pub fn parse_tv_power(output: &str) -> TvPowerStatus {
+    let status = output
+        .lines()
+        .filter(|line| line.contains("logical_address: 0x00"))
+        .find_map(|line| {
+            let (_, rest) = line.split_once("power_status: ")?;
+            rest.split_whitespace().next()?.parse::<i32>().ok()
+        });
+    match status {
+        Some(0) => TvPowerStatus::On,
+        Some(1) => TvPowerStatus::Standby,
+        Some(2) => TvPowerStatus::TransientToOn,
+        Some(3) => TvPowerStatus::TransientToStandby,
+        _ => TvPowerStatus::Unknown,
+    }
+}
+
+pub fn parse_setting_flag(value: &str) -> Option<bool> {
+    match value.trim() {
+        "1" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,76 @@
+use crate::global_device_manager::{DeviceHealth, GlobalDeviceManager};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+pub fn start_health_monitor(
+    device_manager: Arc<GlobalDeviceManager>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let checks: Vec<_> = device_manager
+                .list_devices()
+                .into_iter()
+                .map(|device_id| {
+                    let device_manager = device_manager.clone();
+                    tokio::task::spawn_blocking(move || check_device(&device_manager, &device_id))
+                })
+                .collect();
+            for check in checks {
+                let _ = check.await;
+            }
+        }
+    })
+}
+
+fn check_device(device_manager: &GlobalDeviceManager, device_id: &str) {
+    // Reboots manage their own health transitions.
+    if device_manager.device_health(device_id).ok() == Some(DeviceHealth::Rebooting) {
+        return;
+    }
+    let Ok(controller) = device_manager.get_controller(device_id) else {
+        return;
+    };
+    let ping = match controller.try_lock() {
+        Ok(mut ctrl) => ctrl.ping(),
+        // A busy controller is talking to the device right now, so leave it alone.
+        Err(_) => return,
+    };
+
+    let error = match ping {
+        Ok(_) => {
+            device_manager.set_device_health(device_id, DeviceHealth::Online, "");
+            learn_mac_address(device_manager, device_id);
+            return;
+        }
+        Err(e) => e.to_string(),
+    };
+    device_manager.set_device_health(device_id, DeviceHealth::Offline, &error);
+
+    let reconnected = device_manager.reconnect_device(device_id).and_then(|_| {
+        let mut ctrl = controller
+            .lock()
+            .map_err(|e| format!("Failed to lock controller: {}", e))?;
+        ctrl.ping()
+    });
+    if reconnected.is_ok() {
+        device_manager.set_device_health(device_id, DeviceHealth::Online, "");
+        learn_mac_address(device_manager, device_id);
+    }
+}
+
+// Wake-on-LAN needs the MAC once the device is gone, so learn it while it is
+// reachable. Devices without one, like TV Remote connections, are retried.
+fn learn_mac_address(device_manager: &GlobalDeviceManager, device_id: &str) {
+    if device_manager
+        .wake_on_lan()
+        .mac_address(device_id)
+        .is_none()
+    {
+        let _ = device_manager.learn_mac_address(device_id);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::atv_controller::{shell_quote, validate_package_name};
+use poem_openapi::{Enum, Object, Union};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum IntentKind {
+    #[default]
+    Start,
+    Broadcast,
+    StartService,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct StringExtra {
+    pub key: String,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct IntExtra {
+    pub key: String,
+    pub value: i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct LongExtra {
+    pub key: String,
+    pub value: i64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct FloatExtra {
+    pub key: String,
+    pub value: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct BoolExtra {
+    pub key: String,
+    pub value: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct StringArrayExtra {
+    pub key: String,
+    pub values: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct IntArrayExtra {
+    pub key: String,
+    pub values: Vec<i32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Union)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[oai(discriminator_name = "type", rename_all = "snake_case")]
+pub enum IntentExtra {
+    String(StringExtra),
+    Int(IntExtra),
+    Long(LongExtra),
+    Float(FloatExtra),
+    Bool(BoolExtra),
+    StringArray(StringArrayExtra),
+    IntArray(IntArrayExtra),
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Object)]
+pub struct IntentRequest {
+    #[serde(default)]
+    #[oai(default)]
+    pub kind: IntentKind,
+    pub action: Option<String>,
+    pub data: Option<String>,
+    pub mime_type: Option<String>,
+    pub package: Option<String>,
+    /// Component as "package/.Activity" or "package/fully.qualified.Class".
+    pub component: Option<String>,
+    #[serde(default)]
+    #[oai(default)]
+    pub categories: Vec<String>,
+    /// Raw `Intent` flag bits, e.g. 268435456 for FLAG_ACTIVITY_NEW_TASK.
+    pub flags: Option<u32>,
+    #[serde(default)]
+    #[oai(default)]
+    pub extras: Vec<IntentExtra>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct UrlTarget {
+    pub url: String,
+    pub package: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct YoutubeTarget {
+    pub video_id: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct NetflixTarget {
+    pub title_id: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct PlexTarget {
+    /// A `plex://` deep link.
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct KodiTarget {
+    /// Any media URL Kodi can play, e.g. `http://`, `smb://` or `nfs://`.
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Union)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[oai(discriminator_name = "type", rename_all = "snake_case")]
+pub enum IntentTarget {
+    Custom(IntentRequest),
+    Url(UrlTarget),
+    Youtube(YoutubeTarget),
+    Netflix(NetflixTarget),
+    Plex(PlexTarget),
+    Kodi(KodiTarget),
+}
+
+const VIEW_ACTION: &str = "android.intent.action.VIEW";
+
+impl IntentTarget {
+    pub fn into_request(self) -> Result<IntentRequest, Box<dyn Error>> {
+        let view = |data: String, package: Option<&str>| IntentRequest {
+            action: Some(VIEW_ACTION.to_string()),
+            data: Some(data),
+            package: package.map(str::to_string),
+            ..Default::default()
+        };
+        let request = match self {
+            IntentTarget::Custom(request) => request,
+            IntentTarget::Url(target) => view(target.url, target.package.as_deref()),
+            IntentTarget::Youtube(target) => {
+                if !is_identifier(&target.video_id) {
+                    return Err(format!("Invalid YouTube video id: {}", target.video_id).into());
+                }
+                view(
+                    format!("https://www.youtube.com/watch?v={}", target.video_id),
+                    Some("com.google.android.youtube.tv"),
+                )
+            }
+            IntentTarget::Netflix(target) => {
+                if target.title_id.is_empty()
+                    || !target.title_id.chars().all(|c| c.is_ascii_digit())
+                {
+                    return Err(format!("Invalid Netflix title id: {}", target.title_id).into());
+                }
+                IntentRequest {
+                    component: Some("com.netflix.ninja/.MainActivity".to_string()),
+                    // Netflix ignores deep links that do not carry a source.
+                    extras: vec![IntentExtra::String(StringExtra {
+                        key: "source".to_string(),
+                        value: "30".to_string(),
+                    })],
+                    ..view(
+                        format!("https://www.netflix.com/watch/{}", target.title_id),
+                        None,
+                    )
+                }
+            }
+            IntentTarget::Plex(target) => {
+                if !target.url.starts_with("plex://") {
+                    return Err(format!("Plex links must use plex://, got {}", target.url).into());
+                }
+                view(target.url, Some("com.plexapp.android"))
+            }
+            IntentTarget::Kodi(target) => view(target.url, Some("org.xbmc.kodi")),
+        };
+        Ok(request)
+    }
+}
+
+impl IntentRequest {
+    /// Package the intent is addressed to, if it names one.
+    pub fn target_package(&self) -> Option<&str> {
+        self.package.as_deref().or_else(|| {
+            self.component
+                .as_deref()
+                .and_then(|component| component.split_once('/'))
+                .map(|(package, _)| package)
+        })
+    }
+
+    pub fn to_command(&self) -> Result<String, Box<dyn Error>> {
+        let mut args = vec![match self.kind {
+            IntentKind::Start => "am start".to_string(),
+            IntentKind::Broadcast => "am broadcast".to_string(),
+            IntentKind::StartService => "am startservice".to_string(),
+        }];
+
+        if let Some(action) = &self.action {
+            validate_name("action", action)?;
+            args.push(format!("-a {}", shell_quote(action)));
+        }
+        if let Some(data) = &self.data {
+            validate_text("data URI", data)?;
+            args.push(format!("-d {}", shell_quote(data)));
+        }
+        if let Some(mime_type) = &self.mime_type {
+            let valid = mime_type.split_once('/').is_some()
+                && mime_type
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "/.+-_*".contains(c));
+            if !valid {
+                return Err(format!("Invalid MIME type: {}", mime_type).into());
+            }
+            args.push(format!("-t {}", shell_quote(mime_type)));
+        }
+        for category in &self.categories {
+            validate_name("category", category)?;
+            args.push(format!("-c {}", shell_quote(category)));
+        }
+        if let Some(flags) = self.flags {
+            args.push(format!("-f 0x{:08x}", flags));
+        }
+        for extra in &self.extras {
+            args.push(extra_argument(extra)?);
+        }
+        match (&self.component, &self.package) {
+            (Some(component), _) => {
+                validate_component(component)?;
+                args.push(format!("-n {}", shell_quote(component)));
+            }
+            (None, Some(package)) => {
+                validate_package_name(package)?;
+                args.push(format!("-p {}", shell_quote(package)));
+            }
+            (None, None) => {}
+        }
+
+        if args.len() == 1 {
+            return Err("An intent needs at least an action, data URI or component".into());
+        }
+        Ok(args.join(" "))
+    }
+}
+
+fn extra_argument(extra: &IntentExtra) -> Result<String, Box<dyn Error>> {
+    let (flag, key, value) = match extra {
+        IntentExtra::String(extra) => {
+            validate_text("extra value", &extra.value)?;
+            ("--es", &extra.key, shell_quote(&extra.value))
+        }
+        IntentExtra::Int(extra) => ("--ei", &extra.key, extra.value.to_string()),
+        IntentExtra::Long(extra) => ("--el", &extra.key, extra.value.to_string()),
+        IntentExtra::Float(extra) => {
+            if !extra.value.is_finite() {
+                return Err(format!("Invalid float extra {}", extra.key).into());
+            }
+            ("--ef", &extra.key, extra.value.to_string())
+        }
+        IntentExtra::Bool(extra) => ("--ez", &extra.key, extra.value.to_string()),
+        IntentExtra::StringArray(extra) => {
+            for value in &extra.values {
+                validate_text("extra value", value)?;
+            }
+            // am splits string arrays on commas, so escape the ones inside values.
+            let joined = extra
+                .values
+                .iter()
+                .map(|value| value.replace(',', "\\,"))
+                .collect::<Vec<_>>()
+                .join(",");
+            ("--esa", &extra.key, shell_quote(&joined))
+        }
+        IntentExtra::IntArray(extra) => {
+            let joined = extra
+                .values
+                .iter()
+                .map(i32::to_string)
+                .collect::<Vec<_>>()
+                .join(",");
+            ("--eia", &extra.key, joined)
+        }
+    };
+    validate_name("extra key", key)?;
+    Ok(format!("{} {} {}", flag, shell_quote(key), value))
+}
+
+fn is_identifier(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn validate_name(what: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid {}: {}", what, value).into())
+    }
+}
+
+fn validate_text(what: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    if value.chars().any(char::is_control) {
+        return Err(format!("Invalid {}: control characters are not allowed", what).into());
+    }
+    Ok(())
+}
+
+pub fn validate_component(component: &str) -> Result<(), Box<dyn Error>> {
+    let (package, class) = component
+        .split_once('/')
+        .ok_or_else(|| format!("Invalid component: {}", component))?;
+    validate_package_name(package)?;
+    let valid_class = !class.is_empty()
+        && class
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '$');
+    if !valid_class {
+        return Err(format!("Invalid component: {}", component).into());
+    }
+    Ok(())
+}
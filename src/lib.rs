@@ -1,8 +1,41 @@
 pub mod adb_service;
 pub mod atv_controller;
+pub mod backup;
+pub mod command;
 pub mod device;
 pub mod device_manager;
 pub mod error;
+pub mod events;
+pub mod fake_adb_server;
+pub mod fake_tv_remote;
+pub mod file_browser;
+pub mod foreground_watcher;
 pub mod global_device_manager;
+pub mod group_manager;
+pub mod hdmi_cec;
+pub mod health_monitor;
+pub mod intent;
+pub mod logcat;
+pub mod macro_manager;
+pub mod media_session;
+pub mod metrics;
+pub mod mock_transport;
+pub mod mqtt_bridge;
+pub mod network_diagnostics;
+pub mod packages;
+pub mod power;
+pub mod provisioning;
+pub mod reboot;
+pub mod resource_monitor;
+pub mod scheduler;
+pub mod session_recorder;
+pub mod settings;
+pub mod storage;
 pub mod tcpip_config;
+pub mod transport;
+pub mod tv_remote;
+pub mod tv_remote_proto;
+pub mod volume;
+pub mod wake_on_lan;
 pub mod web_service;
+pub mod webhook_manager;
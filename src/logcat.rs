@@ -0,0 +1,217 @@
+use crate::atv_controller::shell_quote;
+use crate::transport::DeviceTransport;
+use poem_openapi::{Enum, Object};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::{self, Write};
+use tokio::sync::mpsc;
+
+pub const DEFAULT_DUMP_LINES: u32 = 200;
+pub const MAX_DUMP_LINES: u32 = 5000;
+const STREAM_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum LogBuffer {
+    Main,
+    System,
+    Crash,
+    Events,
+    Radio,
+    All,
+}
+
+impl LogBuffer {
+    fn as_arg(&self) -> &'static str {
+        match self {
+            LogBuffer::Main => "main",
+            LogBuffer::System => "system",
+            LogBuffer::Crash => "crash",
+            LogBuffer::Events => "events",
+            LogBuffer::Radio => "radio",
+            LogBuffer::All => "all",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum LogPriority {
+    Verbose,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+    Unknown,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct LogRecord {
+    pub timestamp: String,
+    pub pid: u32,
+    pub tid: u32,
+    pub priority: LogPriority,
+    pub tag: String,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LogcatFilter {
+    pub buffers: Vec<LogBuffer>,
+    /// logcat filterspecs such as "ActivityManager:I"; everything else is silenced.
+    pub specs: Vec<String>,
+    pub pid: Option<u32>,
+    pub regex: Option<Regex>,
+}
+
+impl LogcatFilter {
+    pub fn matches(&self, record: &LogRecord) -> bool {
+        self.regex
+            .as_ref()
+            .is_none_or(|regex| regex.is_match(&record.tag) || regex.is_match(&record.message))
+    }
+}
+
+pub enum LogcatMode {
+    Dump(u32),
+    Follow,
+}
+
+pub fn compile_regex(pattern: &str) -> Result<Regex, Box<dyn Error>> {
+    Regex::new(pattern).map_err(|e| format!("Invalid regex '{}': {}", pattern, e).into())
+}
+
+pub fn logcat_command(filter: &LogcatFilter, mode: LogcatMode) -> Result<String, Box<dyn Error>> {
+    let mut args = vec!["logcat -v threadtime".to_string()];
+    for buffer in &filter.buffers {
+        args.push(format!("-b {}", buffer.as_arg()));
+    }
+    if let Some(pid) = filter.pid {
+        args.push(format!("--pid={}", pid));
+    }
+    match mode {
+        LogcatMode::Dump(lines) => args.push(format!("-d -t {}", lines.clamp(1, MAX_DUMP_LINES))),
+        // Start at the newest line instead of replaying the whole buffer.
+        LogcatMode::Follow => args.push("-T 1".to_string()),
+    }
+    for spec in &filter.specs {
+        validate_spec(spec)?;
+        args.push(shell_quote(spec));
+    }
+    if !filter.specs.is_empty() && !filter.specs.iter().any(|spec| spec.starts_with("*:")) {
+        args.push("'*:S'".to_string());
+    }
+    Ok(args.join(" "))
+}
+
+fn validate_spec(spec: &str) -> Result<(), Box<dyn Error>> {
+    let valid = spec.rsplit_once(':').is_some_and(|(tag, priority)| {
+        !tag.is_empty()
+            && !tag.chars().any(|c| c.is_whitespace() || c.is_control())
+            && matches!(priority, "V" | "D" | "I" | "W" | "E" | "F" | "S")
+    });
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid filter '{}', expected TAG:PRIORITY (V/D/I/W/E/F/S)",
+            spec
+        )
+        .into())
+    }
+}
+
+// e.g. "10-19 05:46:23.180  1234  1250 I ActivityManager: Start proc 4321:com.example/u0a91"
+pub fn parse_threadtime_line(line: &str) -> Option<LogRecord> {
+    let (date, rest) = take_token(line)?;
+    let (time, rest) = take_token(rest)?;
+    let (pid, rest) = take_token(rest)?;
+    let (tid, rest) = take_token(rest)?;
+    let (priority, rest) = take_token(rest)?;
+    let priority = match priority {
+        "V" => LogPriority::Verbose,
+        "D" => LogPriority::Debug,
+        "I" => LogPriority::Info,
+        "W" => LogPriority::Warn,
+        "E" => LogPriority::Error,
+        "F" | "A" => LogPriority::Fatal,
+        _ => LogPriority::Unknown,
+    };
+    let (tag, message) = match rest.split_once(": ") {
+        Some((tag, message)) => (tag, message),
+        None => (rest.strip_suffix(':').unwrap_or(rest), ""),
+    };
+    Some(LogRecord {
+        timestamp: format!("{} {}", date, time),
+        pid: pid.parse().ok()?,
+        tid: tid.parse().ok()?,
+        priority,
+        tag: tag.trim().to_string(),
+        message: message.trim_end().to_string(),
+    })
+}
+
+fn take_token(input: &str) -> Option<(&str, &str)> {
+    let input = input.trim_start();
+    let end = input.find(char::is_whitespace)?;
+    Some((&input[..end], input[end..].trim_start()))
+}
+
+/// Runs logcat on its own connection and forwards parsed records until the
+/// receiver is dropped.
+pub fn follow_logcat(
+    mut device: Box<dyn DeviceTransport>,
+    filter: LogcatFilter,
+) -> Result<mpsc::Receiver<LogRecord>, Box<dyn Error>> {
+    let command = logcat_command(&filter, LogcatMode::Follow)?;
+    let (sender, receiver) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+    tokio::task::spawn_blocking(move || {
+        let mut sink = LogcatSink {
+            pending: Vec::new(),
+            filter,
+            sender,
+        };
+        if let Err(e) = device.shell_command(&command, &mut sink)
+            && !sink.sender.is_closed()
+        {
+            eprintln!("Logcat stream ended: {}", e);
+        }
+    });
+    Ok(receiver)
+}
+
+struct LogcatSink {
+    pending: Vec<u8>,
+    filter: LogcatFilter,
+    sender: mpsc::Sender<LogRecord>,
+}
+
+impl Write for LogcatSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(record) = parse_threadtime_line(line.trim_end()) else {
+                continue;
+            };
+            if !self.filter.matches(&record) {
+                continue;
+            }
+            // Failing the write makes the shell command return, which closes
+            // the connection once the client has gone away.
+            self.sender
+                .blocking_send(record)
+                .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "logcat client closed"))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+use crate::atv_controller::{ATVController, validate_package_name};
+use crate::storage::{load_json, save_json};
+use poem_openapi::{Object, Union};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+const DEFAULT_FOREGROUND_TIMEOUT_MS: u64 = 10_000;
+const FOREGROUND_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct KeyStep {
+    pub key: String,
+}
+
+/// A raw Android keycode, for keys that have no name in the key map.
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct KeycodeStep {
+    pub keycode: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct LongPressStep {
+    pub key: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct TextStep {
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct TapStep {
+    pub x: u32,
+    pub y: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct SwipeStep {
+    pub from_x: u32,
+    pub from_y: u32,
+    pub to_x: u32,
+    pub to_y: u32,
+    pub duration_ms: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct LaunchAppStep {
+    pub package: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct WaitStep {
+    pub ms: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct WaitForegroundStep {
+    pub package: String,
+    pub timeout_ms: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Union)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[oai(discriminator_name = "type", rename_all = "snake_case")]
+pub enum MacroStep {
+    Key(KeyStep),
+    Keycode(KeycodeStep),
+    LongPress(LongPressStep),
+    Text(TextStep),
+    Tap(TapStep),
+    Swipe(SwipeStep),
+    LaunchApp(LaunchAppStep),
+    Wait(WaitStep),
+    WaitForeground(WaitForegroundStep),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct MacroDefinition {
+    pub name: String,
+    pub description: Option<String>,
+    pub steps: Vec<MacroStep>,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct MacroRunReport {
+    pub name: String,
+    pub steps_total: u32,
+    pub steps_completed: u32,
+    pub cancelled: bool,
+}
+
+struct RunningMacro {
+    name: String,
+    cancel: watch::Sender<bool>,
+}
+
+pub struct MacroManager {
+    path: PathBuf,
+    macros: Mutex<BTreeMap<String, MacroDefinition>>,
+    running: Mutex<HashMap<String, RunningMacro>>,
+}
+
+impl MacroManager {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let path = path.into();
+        let macros = load_json(&path)?;
+        Ok(Self {
+            path,
+            macros: Mutex::new(macros),
+            running: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn list_macros(&self) -> Vec<MacroDefinition> {
+        let macros = self.macros.lock().unwrap_or_else(|e| e.into_inner());
+        macros.values().cloned().collect()
+    }
+
+    pub fn get_macro(&self, name: &str) -> Result<MacroDefinition, Box<dyn Error>> {
+        let macros = self
+            .macros
+            .lock()
+            .map_err(|e| format!("Failed to lock macros: {}", e))?;
+        macros
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Macro {} not found", name).into())
+    }
+
+    pub fn save_macro(&self, definition: MacroDefinition) -> Result<(), Box<dyn Error>> {
+        validate_macro(&definition)?;
+        let mut macros = self
+            .macros
+            .lock()
+            .map_err(|e| format!("Failed to lock macros: {}", e))?;
+        macros.insert(definition.name.clone(), definition);
+        save_json(&self.path, &*macros)
+    }
+
+    pub fn remove_macro(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let mut macros = self
+            .macros
+            .lock()
+            .map_err(|e| format!("Failed to lock macros: {}", e))?;
+        if macros.remove(name).is_none() {
+            return Err(format!("Macro {} not found", name).into());
+        }
+        save_json(&self.path, &*macros)
+    }
+
+    pub async fn run_macro(
+        &self,
+        controller: Arc<Mutex<ATVController>>,
+        device_id: &str,
+        name: &str,
+    ) -> Result<MacroRunReport, Box<dyn Error + Send + Sync>> {
+        let definition = self.get_macro(name).map_err(|e| e.to_string())?;
+        self.run_definition(controller, device_id, &definition)
+            .await
+    }
+
+    /// Runs steps that are not necessarily saved, e.g. a replayed session.
+    pub async fn run_definition(
+        &self,
+        controller: Arc<Mutex<ATVController>>,
+        device_id: &str,
+        definition: &MacroDefinition,
+    ) -> Result<MacroRunReport, Box<dyn Error + Send + Sync>> {
+        let name = definition.name.as_str();
+        let mut cancel = self.start_run(device_id, name)?;
+        let _guard = RunGuard {
+            running: &self.running,
+            device_id,
+        };
+
+        let mut report = MacroRunReport {
+            name: definition.name.clone(),
+            steps_total: definition.steps.len() as u32,
+            steps_completed: 0,
+            cancelled: false,
+        };
+
+        for (index, step) in definition.steps.iter().enumerate() {
+            if *cancel.borrow() {
+                report.cancelled = true;
+                break;
+            }
+            let completed = execute_step(&controller, step, &mut cancel)
+                .await
+                .map_err(|e| format!("Step {} of macro {} failed: {}", index + 1, name, e))?;
+            if !completed {
+                report.cancelled = true;
+                break;
+            }
+            report.steps_completed += 1;
+        }
+
+        Ok(report)
+    }
+
+    pub fn cancel_macro(&self, device_id: &str, name: &str) -> Result<(), Box<dyn Error>> {
+        let running = self
+            .running
+            .lock()
+            .map_err(|e| format!("Failed to lock running macros: {}", e))?;
+        match running.get(device_id) {
+            Some(run) if run.name == name => {
+                run.cancel.send_replace(true);
+                Ok(())
+            }
+            _ => Err(format!("Macro {} is not running on device {}", name, device_id).into()),
+        }
+    }
+
+    fn start_run(
+        &self,
+        device_id: &str,
+        name: &str,
+    ) -> Result<watch::Receiver<bool>, Box<dyn Error + Send + Sync>> {
+        let mut running = self
+            .running
+            .lock()
+            .map_err(|e| format!("Failed to lock running macros: {}", e))?;
+        if let Some(run) = running.get(device_id) {
+            return Err(format!(
+                "Macro {} is already running on device {}",
+                run.name, device_id
+            )
+            .into());
+        }
+        let (cancel, receiver) = watch::channel(false);
+        running.insert(
+            device_id.to_string(),
+            RunningMacro {
+                name: name.to_string(),
+                cancel,
+            },
+        );
+        Ok(receiver)
+    }
+}
+
+// Clears the device's running entry even when the run future is dropped midway.
+struct RunGuard<'a> {
+    running: &'a Mutex<HashMap<String, RunningMacro>>,
+    device_id: &'a str,
+}
+
+impl Drop for RunGuard<'_> {
+    fn drop(&mut self) {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        running.remove(self.device_id);
+    }
+}
+
+fn validate_macro(definition: &MacroDefinition) -> Result<(), Box<dyn Error>> {
+    let valid_name = !definition.name.is_empty()
+        && definition
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        return Err(format!("Invalid macro name: {}", definition.name).into());
+    }
+    for step in &definition.steps {
+        match step {
+            MacroStep::Key(KeyStep { key }) | MacroStep::LongPress(LongPressStep { key })
+                if ATVController::keycode_for(key).is_none() =>
+            {
+                return Err(format!("Unknown key: {}", key).into());
+            }
+            MacroStep::LaunchApp(LaunchAppStep { package })
+            | MacroStep::WaitForeground(WaitForegroundStep { package, .. }) => {
+                validate_package_name(package)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Returns Ok(false) when the step was interrupted by a cancellation.
+async fn execute_step(
+    controller: &Mutex<ATVController>,
+    step: &MacroStep,
+    cancel: &mut watch::Receiver<bool>,
+) -> Result<bool, String> {
+    match step {
+        MacroStep::Wait(step) => Ok(wait(cancel, Duration::from_millis(step.ms)).await),
+        MacroStep::WaitForeground(step) => {
+            let timeout =
+                Duration::from_millis(step.timeout_ms.unwrap_or(DEFAULT_FOREGROUND_TIMEOUT_MS));
+            let deadline = Instant::now() + timeout;
+            loop {
+                let foreground = with_controller(controller, |ctrl| ctrl.foreground_package())?;
+                if foreground.as_deref() == Some(step.package.as_str()) {
+                    return Ok(true);
+                }
+                if Instant::now() >= deadline {
+                    return Err(format!(
+                        "Timed out waiting for {} to reach the foreground",
+                        step.package
+                    ));
+                }
+                if !wait(cancel, FOREGROUND_POLL_INTERVAL).await {
+                    return Ok(false);
+                }
+            }
+        }
+        MacroStep::Key(step) => {
+            with_controller(controller, |ctrl| ctrl.press_key(&step.key)).map(|_| true)
+        }
+        MacroStep::Keycode(step) => {
+            with_controller(controller, |ctrl| ctrl.send_keyevent(step.keycode)).map(|_| true)
+        }
+        MacroStep::LongPress(step) => {
+            with_controller(controller, |ctrl| ctrl.long_press_key(&step.key)).map(|_| true)
+        }
+        MacroStep::Text(step) => {
+            with_controller(controller, |ctrl| ctrl.input_text(&step.text)).map(|_| true)
+        }
+        MacroStep::Tap(step) => {
+            with_controller(controller, |ctrl| ctrl.tap(step.x, step.y)).map(|_| true)
+        }
+        MacroStep::Swipe(step) => with_controller(controller, |ctrl| {
+            ctrl.swipe(
+                step.from_x,
+                step.from_y,
+                step.to_x,
+                step.to_y,
+                step.duration_ms,
+            )
+        })
+        .map(|_| true),
+        MacroStep::LaunchApp(step) => {
+            with_controller(controller, |ctrl| ctrl.launch_app(&step.package)).map(|_| true)
+        }
+    }
+}
+
+fn with_controller<T>(
+    controller: &Mutex<ATVController>,
+    action: impl FnOnce(&mut ATVController) -> Result<T, Box<dyn Error>>,
+) -> Result<T, String> {
+    let mut ctrl = controller
+        .lock()
+        .map_err(|e| format!("Failed to lock controller: {}", e))?;
+    action(&mut ctrl).map_err(|e| e.to_string())
+}
+
+// Returns false if the wait was cut short by a cancellation.
+async fn wait(cancel: &mut watch::Receiver<bool>, duration: Duration) -> bool {
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => true,
+        _ = cancel.wait_for(|cancelled| *cancelled) => false,
+    }
+}
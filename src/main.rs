@@ -1,14 +1,79 @@
-use atvmate::{global_device_manager::GlobalDeviceManager, web_service::ApiService};
-use poem::{Route, Server, endpoint::StaticFilesEndpoint, listener::TcpListener};
+use atvmate::{
+    foreground_watcher::{poll_interval_from_env, start_foreground_watcher},
+    global_device_manager::GlobalDeviceManager,
+    group_manager::GroupManager,
+    health_monitor::{DEFAULT_HEALTH_CHECK_INTERVAL, start_health_monitor},
+    macro_manager::MacroManager,
+    metrics::{HttpMetrics, metrics_endpoint},
+    mqtt_bridge::{MqttBridge, MqttConfig},
+    provisioning::ProfileStore,
+    resource_monitor::{ResourceMonitor, stats_interval_from_env},
+    scheduler::Scheduler,
+    session_recorder::SessionStore,
+    tv_remote::RemotePairing,
+    wake_on_lan::{WakeOnLan, broadcast_from_env},
+    web_service::ApiService,
+    webhook_manager::WebhookManager,
+};
+use poem::{EndpointExt, Route, Server, endpoint::StaticFilesEndpoint, listener::TcpListener};
 use poem_openapi::OpenApiService;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Starting ATV Web Service...");
 
-    let device_manager = Arc::new(GlobalDeviceManager::new());
-    let api_service = ApiService::new(device_manager.clone());
+    let data_dir =
+        PathBuf::from(std::env::var("ATVMATE_DATA_DIR").unwrap_or_else(|_| "data".to_string()));
+
+    let mut wake_on_lan = WakeOnLan::load(data_dir.join("mac_addresses.json"))?;
+    if let Some(broadcast) = broadcast_from_env()? {
+        wake_on_lan = wake_on_lan.with_broadcast(broadcast);
+    }
+    let device_manager = Arc::new(GlobalDeviceManager::new().with_wake_on_lan(wake_on_lan));
+    let macro_manager = Arc::new(MacroManager::load(data_dir.join("macros.json"))?);
+    let group_manager = Arc::new(GroupManager::load(data_dir.join("groups.json"))?);
+    let scheduler = Arc::new(Scheduler::load(
+        data_dir.join("schedules.json"),
+        device_manager.clone(),
+        macro_manager.clone(),
+        group_manager.clone(),
+    )?);
+    let webhook_manager = Arc::new(WebhookManager::load(data_dir.join("webhooks.json"))?);
+    let session_store = Arc::new(SessionStore::new(data_dir.join("sessions")));
+    let remote_pairing = Arc::new(RemotePairing::new(data_dir.join("tv_remote_identity.json")));
+    let profile_store = Arc::new(ProfileStore::new(data_dir.join("profiles")));
+    scheduler.clone().start();
+    webhook_manager.clone().start(device_manager.events());
+    start_health_monitor(device_manager.clone(), DEFAULT_HEALTH_CHECK_INTERVAL);
+    if let Some(interval) = poll_interval_from_env()? {
+        start_foreground_watcher(device_manager.clone(), interval);
+    }
+    let resource_monitor = Arc::new(ResourceMonitor::new(device_manager.clone()));
+    if let Some(interval) = stats_interval_from_env()? {
+        resource_monitor.clone().start(interval);
+    }
+
+    if let Some(mqtt_config) = MqttConfig::from_env()? {
+        println!(
+            "Connecting to MQTT broker at {}:{}",
+            mqtt_config.host, mqtt_config.port
+        );
+        MqttBridge::start(mqtt_config, device_manager.clone(), macro_manager.clone());
+    }
+
+    let api_service = ApiService::new(
+        device_manager.clone(),
+        macro_manager.clone(),
+        group_manager,
+        scheduler,
+        webhook_manager,
+        resource_monitor,
+        session_store,
+        remote_pairing,
+        profile_store,
+    );
 
     let api_service = OpenApiService::new(api_service, "ATV Remote Control", "1.0")
         .server("http://127.0.0.1:8000");
@@ -19,13 +84,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .nest("/api", api_service)
         .nest("/docs", ui)
         .at("/api-docs/openapi.json", spec)
+        .at("/metrics", metrics_endpoint(device_manager.clone()))
         .nest(
             "/",
             StaticFilesEndpoint::new("frontend/dist").index_file("index.html"),
-        );
+        )
+        .with(HttpMetrics);
 
     println!("Server running at http://127.0.0.1:8000");
     println!("API documentation available at http://127.0.0.1:8000/docs");
+    println!("Prometheus metrics available at http://127.0.0.1:8000/metrics");
 
     Server::new(TcpListener::bind("0.0.0.0:8000"))
         .run(app)
@@ -0,0 +1,259 @@
+use crate::atv_controller::validate_package_name;
+use poem_openapi::{Enum, Object};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum PlaybackStatus {
+    None,
+    Stopped,
+    Paused,
+    Playing,
+    FastForwarding,
+    Rewinding,
+    Buffering,
+    Error,
+    Connecting,
+    SkippingToPrevious,
+    SkippingToNext,
+    SkippingToQueueItem,
+    Unknown,
+}
+
+impl PlaybackStatus {
+    fn from_code(code: u32) -> Self {
+        match code {
+            0 => PlaybackStatus::None,
+            1 => PlaybackStatus::Stopped,
+            2 => PlaybackStatus::Paused,
+            3 => PlaybackStatus::Playing,
+            4 => PlaybackStatus::FastForwarding,
+            5 => PlaybackStatus::Rewinding,
+            6 => PlaybackStatus::Buffering,
+            7 => PlaybackStatus::Error,
+            8 => PlaybackStatus::Connecting,
+            9 => PlaybackStatus::SkippingToPrevious,
+            10 => PlaybackStatus::SkippingToNext,
+            11 => PlaybackStatus::SkippingToQueueItem,
+            _ => PlaybackStatus::Unknown,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum MediaAction {
+    Play,
+    Pause,
+    PlayPause,
+    Stop,
+    Next,
+    Previous,
+    FastForward,
+    Rewind,
+    SeekForward,
+    SeekBackward,
+}
+
+impl MediaAction {
+    // Keys understood by `cmd media_session dispatch`, which sends them
+    // straight to the media button session instead of the focused window.
+    pub fn dispatch_key(&self) -> Option<&'static str> {
+        match self {
+            MediaAction::Play => Some("play"),
+            MediaAction::Pause => Some("pause"),
+            MediaAction::PlayPause => Some("play-pause"),
+            MediaAction::Stop => Some("stop"),
+            MediaAction::Next => Some("next"),
+            MediaAction::Previous => Some("previous"),
+            MediaAction::FastForward => Some("fast-forward"),
+            MediaAction::Rewind => Some("rewind"),
+            MediaAction::SeekForward | MediaAction::SeekBackward => None,
+        }
+    }
+
+    pub fn keycode(&self) -> u32 {
+        match self {
+            MediaAction::Play => 126,
+            MediaAction::Pause => 127,
+            MediaAction::PlayPause => 85,
+            MediaAction::Stop => 86,
+            MediaAction::Next => 87,
+            MediaAction::Previous => 88,
+            MediaAction::FastForward => 90,
+            MediaAction::Rewind => 89,
+            MediaAction::SeekForward => 272,
+            MediaAction::SeekBackward => 273,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct MediaSession {
+    pub package: String,
+    pub tag: String,
+    pub active: bool,
+    pub state: PlaybackStatus,
+    /// Position extrapolated to the time of the query while playing.
+    pub position_ms: Option<u64>,
+    pub speed: Option<f64>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Object)]
+pub struct MediaStatus {
+    /// Package of the session that receives media keys.
+    pub media_button_package: Option<String>,
+    pub sessions: Vec<MediaSession>,
+}
+
+struct RawPlaybackState {
+    state: u32,
+    position: i64,
+    speed: f64,
+    updated: i64,
+}
+
+/// Parses the output of `cat /proc/uptime; dumpsys media_session`.
+pub fn parse_media_status(output: &str) -> MediaStatus {
+    let uptime_ms = output
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().next())
+        .and_then(|secs| secs.parse::<f64>().ok())
+        .map(|secs| (secs * 1000.0) as i64);
+
+    let mut status = MediaStatus::default();
+    let mut current: Option<MediaSession> = None;
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Media button session is ") {
+            status.media_button_package = parse_session_header(rest).map(|(package, _)| package);
+            continue;
+        }
+        if let Some((package, tag)) = parse_session_header(line) {
+            status.sessions.extend(current.take());
+            current = Some(MediaSession {
+                package,
+                tag,
+                active: false,
+                state: PlaybackStatus::None,
+                position_ms: None,
+                speed: None,
+                title: None,
+                artist: None,
+                album: None,
+            });
+            continue;
+        }
+        let Some(session) = current.as_mut() else {
+            continue;
+        };
+        if let Some(package) = line.strip_prefix("package=") {
+            session.package = package.to_string();
+        } else if let Some(active) = line.strip_prefix("active=") {
+            session.active = active == "true";
+        } else if let Some(state) = line.strip_prefix("state=PlaybackState {") {
+            if let Some(raw) = parse_playback_state(state) {
+                session.state = PlaybackStatus::from_code(raw.state);
+                session.speed = Some(raw.speed);
+                session.position_ms = estimate_position(&raw, uptime_ms);
+            }
+        } else if let Some(metadata) = line.strip_prefix("metadata:") {
+            let (title, artist, album) = parse_description(metadata);
+            session.title = title;
+            session.artist = artist;
+            session.album = album;
+        }
+    }
+    status.sessions.extend(current);
+    status
+}
+
+// e.g. "YouTube com.google.android.youtube.tv/YouTube (userId=0)"
+// or "MediaSession com.example/MediaSession/12 (userId=0)" on Android 12+.
+fn parse_session_header(line: &str) -> Option<(String, String)> {
+    let (session, _) = line.strip_suffix(')')?.rsplit_once(" (userId=")?;
+    let component = session
+        .split_whitespace()
+        .find(|token| token.contains('/'))?;
+    let (package, tag) = component.split_once('/')?;
+    validate_package_name(package).ok()?;
+    Some((package.to_string(), tag.to_string()))
+}
+
+// e.g. "state=3, position=44000, buffered position=0, speed=1.0, updated=1103405, ..."
+// Android 13+ prints the state as "PLAYING(3)".
+fn parse_playback_state(fields: &str) -> Option<RawPlaybackState> {
+    let mut state = None;
+    let mut position = 0;
+    let mut speed = 0.0;
+    let mut updated = 0;
+    for field in fields.split(", ") {
+        let Some((key, value)) = field.split_once('=') else {
+            continue;
+        };
+        match key {
+            "state" => {
+                let code = value
+                    .split_once('(')
+                    .map_or(value, |(_, code)| code.trim_end_matches(')'));
+                state = code.parse().ok();
+            }
+            "position" => position = value.parse().unwrap_or(0),
+            "speed" => speed = value.parse().unwrap_or(0.0),
+            "updated" => updated = value.parse().unwrap_or(0),
+            _ => {}
+        }
+    }
+    Some(RawPlaybackState {
+        state: state?,
+        position,
+        speed,
+        updated,
+    })
+}
+
+fn estimate_position(raw: &RawPlaybackState, uptime_ms: Option<i64>) -> Option<u64> {
+    if raw.position < 0 {
+        return None;
+    }
+    let mut position = raw.position;
+    // Positions are reported as of `updated` (elapsed realtime), so advance
+    // them by the time that has passed since while playback is running.
+    if raw.state == 3
+        && raw.updated > 0
+        && let Some(now) = uptime_ms
+        && now > raw.updated
+    {
+        position += ((now - raw.updated) as f64 * raw.speed) as i64;
+    }
+    Some(position.max(0) as u64)
+}
+
+// e.g. " size=7, description=Title, Artist, Album"; fields the app did not set print as "null".
+fn parse_description(metadata: &str) -> (Option<String>, Option<String>, Option<String>) {
+    let Some((_, description)) = metadata.split_once("description=") else {
+        return (None, None, None);
+    };
+    let field = |value: Option<&str>| {
+        value
+            .map(str::trim)
+            .filter(|value| !value.is_empty() && *value != "null")
+            .map(str::to_string)
+    };
+    // Split from the right so commas in titles survive.
+    let mut parts = description.rsplitn(3, ", ");
+    let album = parts.next();
+    let artist = parts.next();
+    let title = parts.next();
+    match (title, artist) {
+        (Some(_), _) => (field(title), field(artist), field(album)),
+        (None, Some(_)) => (field(artist), field(album), None),
+        (None, None) => (field(album), None, None),
+    }
+}
@@ -0,0 +1,248 @@
+use crate::global_device_manager::{DeviceHealth, GlobalDeviceManager};
+use adb_client::RustADBError;
+use poem::http::StatusCode;
+use poem::{Endpoint, IntoResponse, Middleware, PathPattern, Request, Response};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+pub struct Metrics {
+    registry: Registry,
+    devices_registered: IntGauge,
+    device_online: IntGaugeVec,
+    device_commands: IntCounterVec,
+    device_command_duration: HistogramVec,
+    adb_errors: IntCounterVec,
+    reconnect_attempts: IntCounterVec,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("atvmate".to_string()), None)
+            .expect("metric prefix is valid");
+
+        let devices_registered =
+            IntGauge::new("devices_registered", "Number of registered devices").unwrap();
+        let device_online = IntGaugeVec::new(
+            Opts::new(
+                "device_online",
+                "Whether the device is online (1) or offline (0)",
+            ),
+            &["device"],
+        )
+        .unwrap();
+        let device_commands = IntCounterVec::new(
+            Opts::new("device_commands_total", "Commands sent to devices"),
+            &["device", "command", "result"],
+        )
+        .unwrap();
+        let device_command_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "device_command_duration_seconds",
+                "Time taken by commands sent to devices",
+            )
+            .buckets(vec![0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]),
+            &["device", "command"],
+        )
+        .unwrap();
+        let adb_errors = IntCounterVec::new(
+            Opts::new("adb_errors_total", "ADB errors by kind"),
+            &["kind"],
+        )
+        .unwrap();
+        let reconnect_attempts = IntCounterVec::new(
+            Opts::new("reconnect_attempts_total", "Device reconnect attempts"),
+            &["device", "result"],
+        )
+        .unwrap();
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled"),
+            &["method", "path", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests",
+            ),
+            &["method", "path"],
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(devices_registered.clone()))
+            .unwrap();
+        registry.register(Box::new(device_online.clone())).unwrap();
+        registry
+            .register(Box::new(device_commands.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(device_command_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(adb_errors.clone())).unwrap();
+        registry
+            .register(Box::new(reconnect_attempts.clone()))
+            .unwrap();
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            devices_registered,
+            device_online,
+            device_commands,
+            device_command_duration,
+            adb_errors,
+            reconnect_attempts,
+            http_requests,
+            http_request_duration,
+        }
+    }
+
+    pub fn record_device_command(
+        &self,
+        device_id: &str,
+        command: &str,
+        duration: Duration,
+        success: bool,
+    ) {
+        let result = if success { "success" } else { "error" };
+        self.device_commands
+            .with_label_values(&[device_id, command, result])
+            .inc();
+        self.device_command_duration
+            .with_label_values(&[device_id, command])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn record_adb_error(&self, error: &RustADBError) {
+        self.adb_errors
+            .with_label_values(&[adb_error_kind(error).as_str()])
+            .inc();
+    }
+
+    pub fn record_reconnect(&self, device_id: &str, success: bool) {
+        let result = if success { "success" } else { "error" };
+        self.reconnect_attempts
+            .with_label_values(&[device_id, result])
+            .inc();
+    }
+
+    pub fn record_http_request(
+        &self,
+        method: &str,
+        path: &str,
+        status: StatusCode,
+        duration: Duration,
+    ) {
+        self.http_requests
+            .with_label_values(&[method, path, status.as_str()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[method, path])
+            .observe(duration.as_secs_f64());
+    }
+
+    pub fn render(&self, device_manager: &GlobalDeviceManager) -> String {
+        let devices = device_manager.list_devices();
+        self.devices_registered.set(devices.len() as i64);
+        // Rebuild the gauge so removed devices stop being reported.
+        self.device_online.reset();
+        for device_id in devices {
+            if let Ok(health) = device_manager.device_health(&device_id) {
+                let online = i64::from(health == DeviceHealth::Online);
+                self.device_online
+                    .with_label_values(&[device_id.as_str()])
+                    .set(online);
+            }
+        }
+
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            eprintln!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+}
+
+pub fn metrics_endpoint(device_manager: Arc<GlobalDeviceManager>) -> impl Endpoint {
+    poem::endpoint::make_sync(move |_| {
+        metrics()
+            .render(&device_manager)
+            .with_content_type("text/plain; version=0.0.4")
+    })
+}
+
+// Shell commands become labels such as "input_keyevent" or "dumpsys_window";
+// arguments are dropped to keep label cardinality bounded.
+pub fn command_kind(command: &str) -> String {
+    let mut words = command.split_whitespace();
+    let Some(program) = words.next() else {
+        return "empty".to_string();
+    };
+    match (program, words.next()) {
+        ("input" | "cmd" | "am" | "pm" | "settings" | "dumpsys", Some(sub))
+            if sub.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+        {
+            format!("{}_{}", program, sub)
+        }
+        _ => program.to_string(),
+    }
+}
+
+fn adb_error_kind(error: &RustADBError) -> String {
+    let debug = format!("{:?}", error);
+    debug
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+pub struct HttpMetrics;
+
+impl<E: Endpoint> Middleware<E> for HttpMetrics {
+    type Output = HttpMetricsEndpoint<E>;
+
+    fn transform(&self, ep: E) -> Self::Output {
+        HttpMetricsEndpoint { inner: ep }
+    }
+}
+
+pub struct HttpMetricsEndpoint<E> {
+    inner: E,
+}
+
+impl<E: Endpoint> Endpoint for HttpMetricsEndpoint<E> {
+    type Output = Response;
+
+    async fn call(&self, req: Request) -> poem::Result<Self::Output> {
+        let method = req.method().to_string();
+        let started = Instant::now();
+        let result = self.inner.call(req).await.map(IntoResponse::into_response);
+
+        let (status, pattern) = match &result {
+            Ok(response) => (response.status(), response.data::<PathPattern>()),
+            Err(error) => (error.status(), error.data::<PathPattern>()),
+        };
+        // Label by route pattern, not the raw path, so device ids do not
+        // create a new series per request.
+        let path = pattern.map_or("unmatched", |pattern| &pattern.0);
+        metrics().record_http_request(&method, path, status, started.elapsed());
+
+        result
+    }
+}
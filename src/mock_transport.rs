@@ -0,0 +1,268 @@
+use crate::transport::DeviceTransport;
+use adb_client::{ADBListItem, ADBListItemType, AdbStatResponse, RebootType, Result, RustADBError};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// A request the mock received, in the order it arrived.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MockCall {
+    Shell(String),
+    Exec(String),
+    Stat(String),
+    List(String),
+    Pull(String),
+    Push { path: String, data: Vec<u8> },
+    Reboot(String),
+    Reconnect,
+    OpenConnection,
+}
+
+enum Reply {
+    Output(String),
+    Error(String),
+}
+
+struct Rule {
+    pattern: String,
+    reply: Reply,
+    once: bool,
+}
+
+#[derive(Default)]
+struct MockState {
+    rules: Vec<Rule>,
+    calls: Vec<MockCall>,
+    files: BTreeMap<String, Vec<u8>>,
+    offline: bool,
+}
+
+/// An in-memory device that records every request and answers shell
+/// commands from scripted replies. Clones share the same state, so a test
+/// can keep one clone to script and inspect the device it handed out.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers every command containing `pattern` with `output`. When several
+    /// replies match, the one added last wins.
+    pub fn respond(&self, pattern: &str, output: &str) -> &Self {
+        self.add_rule(pattern, Reply::Output(output.to_string()), false)
+    }
+
+    /// Answers the next command containing `pattern` with `output`. One-off
+    /// replies are used in the order they were added, before any `respond`.
+    pub fn respond_once(&self, pattern: &str, output: &str) -> &Self {
+        self.add_rule(pattern, Reply::Output(output.to_string()), true)
+    }
+
+    /// Fails every command containing `pattern` with `message`.
+    pub fn fail(&self, pattern: &str, message: &str) -> &Self {
+        self.add_rule(pattern, Reply::Error(message.to_string()), false)
+    }
+
+    pub fn add_file(&self, path: &str, data: impl Into<Vec<u8>>) -> &Self {
+        self.state().files.insert(path.to_string(), data.into());
+        self
+    }
+
+    pub fn file(&self, path: &str) -> Option<Vec<u8>> {
+        self.state().files.get(path).cloned()
+    }
+
+    /// While offline every request fails as if the device had disappeared.
+    pub fn set_offline(&self, offline: bool) {
+        self.state().offline = offline;
+    }
+
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.state().calls.clone()
+    }
+
+    pub fn shell_commands(&self) -> Vec<String> {
+        self.state()
+            .calls
+            .iter()
+            .filter_map(|call| match call {
+                MockCall::Shell(command) => Some(command.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn clear_calls(&self) {
+        self.state().calls.clear();
+    }
+
+    fn add_rule(&self, pattern: &str, reply: Reply, once: bool) -> &Self {
+        self.state().rules.push(Rule {
+            pattern: pattern.to_string(),
+            reply,
+            once,
+        });
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records the call, failing instead when the device is offline.
+    fn receive(&self, call: MockCall) -> Result<MutexGuard<'_, MockState>> {
+        let mut state = self.state();
+        if state.offline {
+            return Err(RustADBError::IOError(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "device offline",
+            )));
+        }
+        state.calls.push(call);
+        Ok(state)
+    }
+
+    fn reply(&self, call: MockCall, command: &str) -> Result<String> {
+        let mut state = self.receive(call)?;
+        let matches = |rule: &Rule| command.contains(&rule.pattern);
+        let reply = match state
+            .rules
+            .iter()
+            .position(|rule| rule.once && matches(rule))
+        {
+            Some(index) => Some(state.rules.remove(index).reply),
+            None => state
+                .rules
+                .iter()
+                .rev()
+                .find(|rule| !rule.once && matches(rule))
+                .map(|rule| match &rule.reply {
+                    Reply::Output(output) => Reply::Output(output.clone()),
+                    Reply::Error(message) => Reply::Error(message.clone()),
+                }),
+        };
+        match reply {
+            Some(Reply::Output(output)) => Ok(output),
+            Some(Reply::Error(message)) => Err(RustADBError::ADBRequestFailed(message)),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+impl DeviceTransport for MockTransport {
+    fn shell_command(&mut self, command: &str, output: &mut dyn Write) -> Result<()> {
+        let reply = self.reply(MockCall::Shell(command.to_string()), command)?;
+        output.write_all(reply.as_bytes())?;
+        Ok(())
+    }
+
+    fn exec(
+        &mut self,
+        command: &str,
+        input: &mut dyn Read,
+        mut output: Box<dyn Write + Send>,
+    ) -> Result<()> {
+        io::copy(input, &mut io::sink())?;
+        let reply = self.reply(MockCall::Exec(command.to_string()), command)?;
+        output.write_all(reply.as_bytes())?;
+        Ok(())
+    }
+
+    fn stat(&mut self, path: &str) -> Result<AdbStatResponse> {
+        let state = self.receive(MockCall::Stat(path.to_string()))?;
+        let (file_perm, file_size) = match state.files.get(path) {
+            Some(data) => (S_IFREG | 0o644, data.len() as u32),
+            None if is_directory(&state.files, path) => (S_IFDIR | 0o755, 0),
+            // Like the sync protocol, report missing files as all zeroes.
+            None => (0, 0),
+        };
+        Ok(AdbStatResponse {
+            file_perm,
+            file_size,
+            mod_time: 0,
+        })
+    }
+
+    fn list(&mut self, path: &str) -> Result<Vec<ADBListItemType>> {
+        let state = self.receive(MockCall::List(path.to_string()))?;
+        let prefix = directory_prefix(path);
+        let mut entries = BTreeSet::new();
+        for (file, data) in &state.files {
+            let Some(rest) = file.strip_prefix(&prefix) else {
+                continue;
+            };
+            match rest.split_once('/') {
+                Some((directory, _)) => entries.insert((directory.to_string(), None)),
+                None => entries.insert((rest.to_string(), Some(data.len() as u32))),
+            };
+        }
+        Ok(entries
+            .into_iter()
+            .map(|(name, size)| match size {
+                Some(size) => ADBListItemType::File(ADBListItem {
+                    name,
+                    time: 0,
+                    permissions: S_IFREG | 0o644,
+                    size,
+                }),
+                None => ADBListItemType::Directory(ADBListItem {
+                    name,
+                    time: 0,
+                    permissions: S_IFDIR | 0o755,
+                    size: 0,
+                }),
+            })
+            .collect())
+    }
+
+    fn pull(&mut self, source: &str, output: &mut dyn Write) -> Result<()> {
+        let state = self.receive(MockCall::Pull(source.to_string()))?;
+        let data = state
+            .files
+            .get(source)
+            .ok_or_else(|| RustADBError::ADBRequestFailed(format!("No such file: {}", source)))?;
+        output.write_all(data)?;
+        Ok(())
+    }
+
+    fn push(&mut self, input: &mut dyn Read, path: &str) -> Result<()> {
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        let mut state = self.receive(MockCall::Push {
+            path: path.to_string(),
+            data: data.clone(),
+        })?;
+        state.files.insert(path.to_string(), data);
+        Ok(())
+    }
+
+    fn reboot(&mut self, reboot_type: RebootType) -> Result<()> {
+        self.receive(MockCall::Reboot(format!("{:?}", reboot_type)))
+            .map(drop)
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        self.receive(MockCall::Reconnect).map(drop)
+    }
+
+    fn open_connection(&self) -> Result<Box<dyn DeviceTransport>> {
+        drop(self.receive(MockCall::OpenConnection)?);
+        Ok(Box::new(self.clone()))
+    }
+}
+
+fn directory_prefix(path: &str) -> String {
+    format!("{}/", path.trim_end_matches('/'))
+}
+
+fn is_directory(files: &BTreeMap<String, Vec<u8>>, path: &str) -> bool {
+    let prefix = directory_prefix(path);
+    prefix == "/" || files.keys().any(|file| file.starts_with(&prefix))
+}
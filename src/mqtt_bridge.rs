@@ -0,0 +1,467 @@
+use crate::command::{DeviceCommand, PowerState, execute_command};
+use crate::events::DeviceEvent;
+use crate::global_device_manager::{DeviceHealth, GlobalDeviceManager};
+use crate::macro_manager::MacroManager;
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+const DEFAULT_MQTT_PORT: u16 = 1883;
+const DEFAULT_TOPIC_PREFIX: &str = "atvmate";
+const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+const DEFAULT_STATE_INTERVAL: Duration = Duration::from_secs(10);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+const DISCOVERY_BUTTONS: &[(&str, &str)] = &[
+    ("power", "Power"),
+    ("home", "Home"),
+    ("back", "Back"),
+    ("menu", "Menu"),
+    ("dpad_up", "Up"),
+    ("dpad_down", "Down"),
+    ("dpad_left", "Left"),
+    ("dpad_right", "Right"),
+    ("dpad_center", "Select"),
+    ("play_pause", "Play/Pause"),
+    ("volume_up", "Volume Up"),
+    ("volume_down", "Volume Down"),
+    ("volume_mute", "Mute"),
+];
+
+#[derive(Clone, Debug)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub client_id: String,
+    pub topic_prefix: String,
+    pub discovery_prefix: Option<String>,
+    pub state_interval: Duration,
+}
+
+impl MqttConfig {
+    /// Reads the bridge settings from `ATVMATE_MQTT_*` variables. Returns `None`
+    /// when `ATVMATE_MQTT_HOST` is unset, which leaves the bridge disabled.
+    pub fn from_env() -> Result<Option<Self>, Box<dyn Error>> {
+        let Ok(host) = env::var("ATVMATE_MQTT_HOST") else {
+            return Ok(None);
+        };
+        let port = match env::var("ATVMATE_MQTT_PORT") {
+            Ok(port) => port
+                .parse()
+                .map_err(|e| format!("Invalid ATVMATE_MQTT_PORT '{}': {}", port, e))?,
+            Err(_) => DEFAULT_MQTT_PORT,
+        };
+        let state_interval = match env::var("ATVMATE_MQTT_STATE_INTERVAL_SECS") {
+            Ok(secs) => Duration::from_secs(secs.parse().map_err(|e| {
+                format!("Invalid ATVMATE_MQTT_STATE_INTERVAL_SECS '{}': {}", secs, e)
+            })?),
+            Err(_) => DEFAULT_STATE_INTERVAL,
+        };
+        // An empty discovery prefix turns Home Assistant discovery off.
+        let discovery_prefix = match env::var("ATVMATE_MQTT_DISCOVERY_PREFIX") {
+            Ok(prefix) if prefix.is_empty() => None,
+            Ok(prefix) => Some(prefix),
+            Err(_) => Some(DEFAULT_DISCOVERY_PREFIX.to_string()),
+        };
+
+        Ok(Some(Self {
+            host,
+            port,
+            username: env::var("ATVMATE_MQTT_USERNAME").ok(),
+            password: env::var("ATVMATE_MQTT_PASSWORD").ok(),
+            client_id: env::var("ATVMATE_MQTT_CLIENT_ID").unwrap_or_else(|_| "atvmate".to_string()),
+            topic_prefix: env::var("ATVMATE_MQTT_TOPIC_PREFIX")
+                .unwrap_or_else(|_| DEFAULT_TOPIC_PREFIX.to_string()),
+            discovery_prefix,
+            state_interval,
+        }))
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeviceState {
+    pub online: bool,
+    pub foreground_app: Option<String>,
+}
+
+pub struct MqttBridge {
+    config: MqttConfig,
+    client: AsyncClient,
+    device_manager: Arc<GlobalDeviceManager>,
+    macro_manager: Arc<MacroManager>,
+    published: Mutex<HashMap<String, String>>,
+}
+
+impl MqttBridge {
+    pub fn start(
+        config: MqttConfig,
+        device_manager: Arc<GlobalDeviceManager>,
+        macro_manager: Arc<MacroManager>,
+    ) -> tokio::task::JoinHandle<()> {
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            options.set_credentials(username, password);
+        }
+        options.set_last_will(LastWill::new(
+            bridge_status_topic(&config.topic_prefix),
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+        let (client, mut eventloop) = AsyncClient::new(options, 64);
+
+        let bridge = Arc::new(Self {
+            config,
+            client,
+            device_manager,
+            macro_manager,
+            published: Mutex::new(HashMap::new()),
+        });
+
+        tokio::spawn(async move {
+            tokio::spawn(bridge.clone().publish_state_periodically());
+            tokio::spawn(bridge.clone().forward_events());
+
+            loop {
+                match eventloop.poll().await {
+                    // Publishing from inside the poll loop can stall it, so
+                    // everything that sends messages runs on its own task.
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        tokio::spawn(bridge.clone().on_connected());
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        let payload = String::from_utf8_lossy(&publish.payload).into_owned();
+                        tokio::spawn(bridge.clone().handle_command(publish.topic, payload));
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        eprintln!("MQTT connection error: {}", e);
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        })
+    }
+
+    async fn on_connected(self: Arc<Self>) {
+        // Retained state may have been lost with the old session, so resend everything.
+        self.published
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+
+        let prefix = &self.config.topic_prefix;
+        self.publish(&bridge_status_topic(prefix), "online".to_string(), true)
+            .await;
+        let command_filter = format!("{}/+/command/+", prefix);
+        if let Err(e) = self
+            .client
+            .subscribe(&command_filter, QoS::AtLeastOnce)
+            .await
+        {
+            eprintln!("Failed to subscribe to {}: {}", command_filter, e);
+        }
+
+        for device_id in self.device_manager.list_devices() {
+            self.publish_discovery(&device_id).await;
+            self.publish_state(&device_id).await;
+        }
+    }
+
+    async fn publish_state_periodically(self: Arc<Self>) {
+        let mut ticker = tokio::time::interval(self.config.state_interval);
+        loop {
+            ticker.tick().await;
+            for device_id in self.device_manager.list_devices() {
+                self.publish_state(&device_id).await;
+            }
+        }
+    }
+
+    async fn forward_events(self: Arc<Self>) {
+        let mut events = self.device_manager.events().subscribe();
+        loop {
+            match events.recv().await {
+                Ok(DeviceEvent::DeviceAdded { device_id }) => {
+                    self.publish_discovery(&device_id).await;
+                    self.publish_state(&device_id).await;
+                }
+                Ok(DeviceEvent::DeviceRemoved { device_id }) => {
+                    self.remove_discovery(&device_id).await;
+                    self.publish_if_changed(
+                        &self.device_topic(&device_id, "online"),
+                        "offline".to_string(),
+                    )
+                    .await;
+                }
+                Ok(DeviceEvent::DeviceOnline { device_id }) => {
+                    self.publish_state(&device_id).await;
+                }
+                Ok(DeviceEvent::DeviceOffline { device_id, .. }) => {
+                    self.publish_if_changed(
+                        &self.device_topic(&device_id, "online"),
+                        "offline".to_string(),
+                    )
+                    .await;
+                }
+                Ok(DeviceEvent::ForegroundChanged {
+                    device_id, package, ..
+                }) => {
+                    self.publish_if_changed(
+                        &self.device_topic(&device_id, "foreground"),
+                        package.unwrap_or_default(),
+                    )
+                    .await;
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+
+    async fn publish_state(&self, device_id: &str) {
+        let Some(state) = collect_state(self.device_manager.clone(), device_id).await else {
+            return;
+        };
+        let online = if state.online { "online" } else { "offline" };
+        self.publish_if_changed(&self.device_topic(device_id, "online"), online.to_string())
+            .await;
+        if state.online {
+            self.publish_if_changed(
+                &self.device_topic(device_id, "foreground"),
+                state.foreground_app.unwrap_or_default(),
+            )
+            .await;
+        }
+    }
+
+    async fn handle_command(self: Arc<Self>, topic: String, payload: String) {
+        let Some((topic_device, kind)) = parse_command_topic(&self.config.topic_prefix, &topic)
+        else {
+            return;
+        };
+        let Some(device_id) = self
+            .device_manager
+            .list_devices()
+            .into_iter()
+            .find(|device_id| topic_id(device_id) == topic_device)
+        else {
+            eprintln!("MQTT command for unknown device {}", topic_device);
+            return;
+        };
+
+        let result = match parse_command(kind, payload.trim()) {
+            Ok(command) => {
+                execute_command(
+                    &self.device_manager,
+                    &self.macro_manager,
+                    &device_id,
+                    &command,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        let (success, message) = match result {
+            Ok(message) => (true, message),
+            Err(message) => (false, message),
+        };
+        let response = json!({ "command": kind, "success": success, "message": message });
+        self.publish(
+            &self.device_topic(&device_id, "result"),
+            response.to_string(),
+            false,
+        )
+        .await;
+    }
+
+    async fn publish_discovery(&self, device_id: &str) {
+        for (topic, payload) in self.discovery_messages(device_id) {
+            self.publish(&topic, payload.to_string(), true).await;
+        }
+    }
+
+    async fn remove_discovery(&self, device_id: &str) {
+        for (topic, _) in self.discovery_messages(device_id) {
+            self.publish(&topic, String::new(), true).await;
+        }
+    }
+
+    fn discovery_messages(&self, device_id: &str) -> Vec<(String, Value)> {
+        let Some(discovery_prefix) = &self.config.discovery_prefix else {
+            return Vec::new();
+        };
+        let object_id = format!("atvmate_{}", topic_id(device_id));
+        let device = json!({
+            "identifiers": [object_id],
+            "name": device_id,
+            "manufacturer": "atvmate",
+            "model": "Android TV",
+        });
+        let online_topic = self.device_topic(device_id, "online");
+        let bridge_availability =
+            json!([{ "topic": bridge_status_topic(&self.config.topic_prefix) }]);
+        let device_availability = json!([
+            { "topic": bridge_status_topic(&self.config.topic_prefix) },
+            { "topic": online_topic },
+        ]);
+
+        let mut messages = vec![
+            (
+                format!(
+                    "{}/binary_sensor/{}/online/config",
+                    discovery_prefix, object_id
+                ),
+                json!({
+                    "name": "Connection",
+                    "unique_id": format!("{}_online", object_id),
+                    "state_topic": online_topic,
+                    "payload_on": "online",
+                    "payload_off": "offline",
+                    "device_class": "connectivity",
+                    "availability": bridge_availability,
+                    "device": device,
+                }),
+            ),
+            (
+                format!(
+                    "{}/sensor/{}/foreground/config",
+                    discovery_prefix, object_id
+                ),
+                json!({
+                    "name": "Foreground app",
+                    "unique_id": format!("{}_foreground", object_id),
+                    "state_topic": self.device_topic(device_id, "foreground"),
+                    "icon": "mdi:application",
+                    "availability": device_availability,
+                    "availability_mode": "all",
+                    "device": device,
+                }),
+            ),
+        ];
+
+        for (key, name) in DISCOVERY_BUTTONS {
+            messages.push((
+                format!("{}/button/{}/{}/config", discovery_prefix, object_id, key),
+                json!({
+                    "name": name,
+                    "unique_id": format!("{}_{}", object_id, key),
+                    "command_topic": self.device_topic(device_id, "command/key"),
+                    "payload_press": key,
+                    "availability": device_availability,
+                    "availability_mode": "all",
+                    "device": device,
+                }),
+            ));
+        }
+
+        messages
+    }
+
+    async fn publish_if_changed(&self, topic: &str, payload: String) {
+        {
+            let mut published = self.published.lock().unwrap_or_else(|e| e.into_inner());
+            if published.get(topic) == Some(&payload) {
+                return;
+            }
+            published.insert(topic.to_string(), payload.clone());
+        }
+        self.publish(topic, payload, true).await;
+    }
+
+    async fn publish(&self, topic: &str, payload: String, retain: bool) {
+        if let Err(e) = self
+            .client
+            .publish(topic, QoS::AtLeastOnce, retain, payload)
+            .await
+        {
+            eprintln!("Failed to publish to {}: {}", topic, e);
+        }
+    }
+
+    fn device_topic(&self, device_id: &str, suffix: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.config.topic_prefix,
+            topic_id(device_id),
+            suffix
+        )
+    }
+}
+
+pub async fn collect_state(
+    device_manager: Arc<GlobalDeviceManager>,
+    device_id: &str,
+) -> Option<DeviceState> {
+    let health = device_manager.device_health(device_id).ok()?;
+    if health != DeviceHealth::Online {
+        return Some(DeviceState::default());
+    }
+
+    let device_id = device_id.to_string();
+    tokio::task::spawn_blocking(move || {
+        let controller = device_manager.get_controller(&device_id).ok()?;
+        // Skip this round rather than queue behind a running command.
+        let mut ctrl = controller.try_lock().ok()?;
+        Some(DeviceState {
+            online: true,
+            foreground_app: ctrl.foreground_package().ok().flatten(),
+        })
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+fn bridge_status_topic(prefix: &str) -> String {
+    format!("{}/bridge/status", prefix)
+}
+
+// Device ids contain characters such as ':' that Home Assistant rejects in
+// object ids, so topics use a sanitised form.
+fn topic_id(device_id: &str) -> String {
+    device_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn parse_command_topic<'a>(prefix: &str, topic: &'a str) -> Option<(&'a str, &'a str)> {
+    let rest = topic.strip_prefix(prefix)?.strip_prefix('/')?;
+    let mut parts = rest.split('/');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(device), Some("command"), Some(kind), None) => Some((device, kind)),
+        _ => None,
+    }
+}
+
+fn parse_command(kind: &str, payload: &str) -> Result<DeviceCommand, String> {
+    match kind {
+        "key" => Ok(DeviceCommand::Key(payload.to_string())),
+        "text" => Ok(DeviceCommand::Text(payload.to_string())),
+        "launch" => Ok(DeviceCommand::LaunchApp(payload.to_string())),
+        "macro" => Ok(DeviceCommand::Macro(payload.to_string())),
+        "reboot" => Ok(DeviceCommand::Reboot),
+        "power" => match payload {
+            "on" | "ON" => Ok(DeviceCommand::Power(PowerState::On)),
+            "off" | "OFF" => Ok(DeviceCommand::Power(PowerState::Off)),
+            "toggle" | "TOGGLE" | "" => Ok(DeviceCommand::Power(PowerState::Toggle)),
+            _ => Err(format!("Unknown power state: {}", payload)),
+        },
+        _ => Err(format!("Unknown command: {}", kind)),
+    }
+}
@@ -0,0 +1,305 @@
+use poem_openapi::Object;
+use serde::Serialize;
+use std::error::Error;
+
+pub const DEFAULT_PING_COUNT: u32 = 4;
+pub const MAX_PING_COUNT: u32 = 20;
+
+/// Sections are separated by marker lines so one shell round trip covers everything.
+pub const NETWORK_COMMAND: &str = "dumpsys wifi | grep -m 1 'mWifiInfo'; echo @@; \
+    ip -o link show; echo @@; ip -o addr show; echo @@; \
+    ip route show table all 2>/dev/null | grep '^default'; echo @@; \
+    getprop net.dns1; getprop net.dns2; \
+    dumpsys connectivity | grep -m 1 -o 'DnsAddresses: \\[[^]]*\\]'";
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct WifiInfo {
+    pub ssid: Option<String>,
+    pub bssid: Option<String>,
+    pub rssi: Option<i32>,
+    pub link_speed_mbps: Option<u32>,
+    pub frequency_mhz: Option<u32>,
+    pub supplicant_state: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub mac: Option<String>,
+    /// Operational state from `ip link`, e.g. "UP" or "DOWN".
+    pub state: String,
+    /// False when the cable is unplugged or the radio is not associated.
+    pub carrier: bool,
+    pub addresses: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct NetworkInfo {
+    pub wifi: Option<WifiInfo>,
+    pub ethernet: Option<NetworkInterface>,
+    pub interfaces: Vec<NetworkInterface>,
+    pub gateway: Option<String>,
+    pub gateway_interface: Option<String>,
+    pub dns_servers: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct PingResult {
+    pub transmitted: u32,
+    pub received: u32,
+    pub loss_percent: f64,
+    pub min_ms: Option<f64>,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct NetworkCheck {
+    pub target: String,
+    /// Address the device resolved the target to; None when resolution failed.
+    pub resolved_address: Option<String>,
+    pub dns_ok: bool,
+    pub ping: Option<PingResult>,
+    pub error: Option<String>,
+}
+
+pub fn validate_target(target: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !target.is_empty()
+        && !target.starts_with('-')
+        && target
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == ':');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid host name or address: {}", target).into())
+    }
+}
+
+pub fn ping_command(target: &str, count: u32) -> Result<String, Box<dyn Error>> {
+    validate_target(target)?;
+    let ping = if target.contains(':') {
+        "ping6"
+    } else {
+        "ping"
+    };
+    Ok(format!(
+        "{} -c {} -W 2 {} 2>&1",
+        ping,
+        count.clamp(1, MAX_PING_COUNT),
+        target
+    ))
+}
+
+pub fn parse_network_info(output: &str) -> NetworkInfo {
+    let mut sections = output.split("@@");
+    let mut next = || sections.next().unwrap_or("");
+    let wifi = parse_wifi_info(next());
+    let mut interfaces = parse_links(next());
+    for (name, address) in parse_addresses(next()) {
+        if let Some(interface) = interfaces.iter_mut().find(|i| i.name == name) {
+            interface.addresses.push(address);
+        }
+    }
+    let (gateway, gateway_interface) = parse_default_route(next());
+    let dns_servers = parse_dns(next());
+    interfaces.retain(|interface| interface.name != "lo");
+    let ethernet = interfaces
+        .iter()
+        .find(|interface| interface.name.starts_with("eth"))
+        .cloned();
+    NetworkInfo {
+        wifi,
+        ethernet,
+        interfaces,
+        gateway,
+        gateway_interface,
+        dns_servers,
+    }
+}
+
+// mWifiInfo SSID: "Home", BSSID: aa:bb:cc:dd:ee:ff, MAC: ..., Supplicant state: COMPLETED,
+//   RSSI: -58, Link speed: 433Mbps, Tx Link speed: 433Mbps, Frequency: 5180MHz, Net ID: 0, ...
+fn parse_wifi_info(section: &str) -> Option<WifiInfo> {
+    let line = section.lines().find(|line| line.contains("SSID:"))?;
+    let line = line.trim().trim_start_matches("mWifiInfo").trim();
+    let field = |key: &str| {
+        line.split(", ")
+            .find_map(|pair| pair.strip_prefix(key)?.strip_prefix(": "))
+            .map(str::trim)
+    };
+    let number = |value: Option<&str>, unit: &str| {
+        value
+            .map(|value| value.trim_end_matches(unit))
+            .and_then(|value| value.parse().ok())
+    };
+    let ssid = field("SSID")
+        .map(|ssid| ssid.trim_matches('"'))
+        .filter(|ssid| !ssid.is_empty() && *ssid != "<unknown ssid>")
+        .map(str::to_string);
+    let bssid = field("BSSID")
+        .filter(|bssid| *bssid != "<none>" && *bssid != "02:00:00:00:00:00")
+        .map(str::to_string);
+    Some(WifiInfo {
+        ssid,
+        bssid,
+        rssi: field("RSSI")
+            .and_then(|rssi| rssi.parse().ok())
+            .filter(|rssi| *rssi > -127),
+        link_speed_mbps: number(field("Link speed"), "Mbps"),
+        frequency_mhz: number(field("Frequency"), "MHz"),
+        supplicant_state: field("Supplicant state").map(str::to_string),
+    })
+}
+
+// 3: wlan0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 qdisc ... state UP mode DORMANT ...\    link/ether aa:bb:cc:dd:ee:ff brd ...
+pub(crate) fn parse_links(section: &str) -> Vec<NetworkInterface> {
+    section
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ": ");
+            parts.next()?.trim().parse::<u32>().ok()?;
+            // Virtual interfaces are shown as "name@parent".
+            let name = parts.next()?.split('@').next()?.to_string();
+            let rest = parts.next()?;
+            let flags = rest.strip_prefix('<')?.split('>').next()?;
+            let tokens: Vec<&str> = rest.split_whitespace().collect();
+            let after = |key: &str| {
+                tokens
+                    .iter()
+                    .position(|token| *token == key)
+                    .and_then(|index| tokens.get(index + 1))
+                    .map(|value| value.to_string())
+            };
+            Some(NetworkInterface {
+                name,
+                mac: after("link/ether"),
+                state: after("state").unwrap_or_else(|| "UNKNOWN".to_string()),
+                carrier: flags.split(',').any(|flag| flag == "LOWER_UP"),
+                addresses: Vec::new(),
+            })
+        })
+        .collect()
+}
+
+// 3: wlan0    inet 192.168.1.20/24 brd 192.168.1.255 scope global wlan0\       valid_lft forever ...
+fn parse_addresses(section: &str) -> Vec<(String, String)> {
+    section
+        .lines()
+        .filter_map(|line| {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let family = tokens.get(2)?;
+            if *family != "inet" && *family != "inet6" {
+                return None;
+            }
+            Some((tokens.get(1)?.to_string(), tokens.get(3)?.to_string()))
+        })
+        .collect()
+}
+
+// default via 192.168.1.1 dev wlan0 table wlan0 proto static
+fn parse_default_route(section: &str) -> (Option<String>, Option<String>) {
+    for line in section.lines() {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let after = |key: &str| {
+            tokens
+                .iter()
+                .position(|token| *token == key)
+                .and_then(|index| tokens.get(index + 1))
+                .map(|value| value.to_string())
+        };
+        if let Some(gateway) = after("via") {
+            return (Some(gateway), after("dev"));
+        }
+    }
+    (None, None)
+}
+
+// net.dns1/net.dns2 are empty since Android 8, so fall back to
+// "DnsAddresses: [ /192.168.1.1,/8.8.8.8 ]" from `dumpsys connectivity`.
+fn parse_dns(section: &str) -> Vec<String> {
+    let mut servers: Vec<String> = Vec::new();
+    for line in section.lines().map(str::trim) {
+        let candidates: Vec<&str> = match line.strip_prefix("DnsAddresses:") {
+            Some(list) => list
+                .trim()
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .split(',')
+                .map(|address| address.trim().trim_start_matches('/'))
+                .collect(),
+            None => vec![line],
+        };
+        for address in candidates {
+            if !address.is_empty() && !servers.iter().any(|server| server == address) {
+                servers.push(address.to_string());
+            }
+        }
+    }
+    servers
+}
+
+/// Parses `ping -c N` output, which also shows whether the target resolved.
+pub fn parse_ping(target: &str, output: &str) -> NetworkCheck {
+    // "PING example.com (93.184.216.34) 56(84) bytes of data."
+    let resolved_address = output
+        .lines()
+        .find(|line| line.starts_with("PING"))
+        .and_then(|line| line.split_once('(')?.1.split_once(')'))
+        .map(|(address, _)| address.to_string());
+
+    // "4 packets transmitted, 4 received, 0% packet loss, time 3004ms"
+    let summary = output
+        .lines()
+        .find(|line| line.contains("packets transmitted"));
+    let ping = summary.and_then(|line| {
+        let mut fields = line.split(", ");
+        let transmitted = fields.next()?.split_whitespace().next()?.parse().ok()?;
+        let received = fields.next()?.split_whitespace().next()?.parse().ok()?;
+        let loss_percent = line
+            .split(", ")
+            .find(|field| field.contains("packet loss"))
+            .and_then(|field| field.split('%').next()?.trim().parse().ok())
+            .unwrap_or(0.0);
+        // "rtt min/avg/max/mdev = 10.1/12.3/15.2/1.1 ms"
+        let rtt: Vec<f64> = output
+            .lines()
+            .find(|line| line.contains("min/avg/max"))
+            .and_then(|line| line.split_once(" = "))
+            .map(|(_, values)| {
+                values
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or("")
+                    .split('/')
+                    .filter_map(|value| value.parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Some(PingResult {
+            transmitted,
+            received,
+            loss_percent,
+            min_ms: rtt.first().copied(),
+            avg_ms: rtt.get(1).copied(),
+            max_ms: rtt.get(2).copied(),
+        })
+    });
+
+    let error = if ping.is_none() {
+        output
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(str::to_string)
+    } else {
+        None
+    };
+    NetworkCheck {
+        target: target.to_string(),
+        dns_ok: resolved_address.is_some(),
+        resolved_address,
+        ping,
+        error,
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+
+/// APKs are staged here before `pm` installs them; apps cannot read from
+/// most other places the shell user can write to.
+pub const APK_STAGING_DIR: &str = "/data/local/tmp";
+
+pub const HOME_ACTIVITY_COMMAND: &str = "cmd package resolve-activity --brief \
+    -a android.intent.action.MAIN -c android.intent.category.HOME";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackageFilter {
+    All,
+    ThirdParty,
+    Disabled,
+}
+
+impl PackageFilter {
+    pub fn list_command(&self) -> &'static str {
+        match self {
+            PackageFilter::All => "pm list packages",
+            PackageFilter::ThirdParty => "pm list packages -3",
+            PackageFilter::Disabled => "pm list packages -d",
+        }
+    }
+}
+
+/// Parses `pm list packages`, one "package:name" per line.
+pub fn parse_package_list(output: &str) -> BTreeSet<String> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("package:"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parses `pm path`, which lists the base APK first, then any splits.
+pub fn parse_package_paths(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("package:"))
+        .map(str::to_string)
+        .collect()
+}
+
+// Success: created install session [1234]
+pub fn parse_install_session(output: &str) -> Option<u32> {
+    let start = output.find('[')? + 1;
+    let end = start + output[start..].find(']')?;
+    output[start..end].parse().ok()
+}
+
+/// `pm` prints "Success" or "Failure [REASON]", and some versions write
+/// failures as exceptions instead.
+pub fn check_pm_output(action: &str, output: &str) -> Result<(), Box<dyn Error>> {
+    let output = output.trim();
+    if output.lines().any(|line| line.starts_with("Success")) {
+        Ok(())
+    } else {
+        Err(format!("Failed to {}: {}", action, output).into())
+    }
+}
+
+/// Parses `pm enable` and `pm disable-user`, which report the new state
+/// rather than success.
+pub fn check_enabled_state(package: &str, output: &str) -> Result<(), Box<dyn Error>> {
+    if output.contains("new state:") {
+        Ok(())
+    } else {
+        Err(format!("Failed to change state of {}: {}", package, output.trim()).into())
+    }
+}
+
+// priority=0 preferredOrder=0 match=0x108000 specificIndex=-1 isDefault=true
+// com.google.android.tvlauncher/.MainActivity
+pub fn parse_home_activity(output: &str) -> Option<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .rev()
+        .find(|line| line.contains('/') && !line.contains(' '))
+        .map(str::to_string)
+}
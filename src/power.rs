@@ -0,0 +1,82 @@
+use poem_openapi::{Enum, Object};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum Wakefulness {
+    Awake,
+    Asleep,
+    Dreaming,
+    Dozing,
+    Unknown,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum DisplayState {
+    On,
+    Off,
+    Doze,
+    DozeSuspend,
+    OnSuspend,
+    Vr,
+    Unknown,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct PowerStatus {
+    pub wakefulness: Wakefulness,
+    pub display: DisplayState,
+    /// True when the device is interactive, i.e. neither asleep nor showing a screensaver.
+    pub awake: bool,
+}
+
+/// Parses the output of
+/// `dumpsys power | grep -E 'mWakefulness=|Display Power'; dumpsys display | grep mScreenState=`.
+pub fn parse_power_status(output: &str) -> PowerStatus {
+    let mut wakefulness = Wakefulness::Unknown;
+    let mut display = DisplayState::Unknown;
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("mWakefulness=") {
+            wakefulness = match value.trim() {
+                "Awake" => Wakefulness::Awake,
+                "Asleep" => Wakefulness::Asleep,
+                "Dreaming" => Wakefulness::Dreaming,
+                "Dozing" => Wakefulness::Dozing,
+                _ => Wakefulness::Unknown,
+            };
+        } else if let Some(value) = line
+            .strip_prefix("Display Power: state=")
+            .or_else(|| line.strip_prefix("mScreenState="))
+        {
+            // Both lines describe the same display; the first one wins.
+            if display == DisplayState::Unknown {
+                display = parse_display_state(value.trim());
+            }
+        }
+    }
+    let awake = match wakefulness {
+        Wakefulness::Unknown => display == DisplayState::On,
+        wakefulness => wakefulness == Wakefulness::Awake,
+    };
+    PowerStatus {
+        wakefulness,
+        display,
+        awake,
+    }
+}
+
+fn parse_display_state(value: &str) -> DisplayState {
+    match value {
+        "ON" => DisplayState::On,
+        "OFF" => DisplayState::Off,
+        "DOZE" => DisplayState::Doze,
+        "DOZE_SUSPEND" => DisplayState::DozeSuspend,
+        "ON_SUSPEND" => DisplayState::OnSuspend,
+        "VR" => DisplayState::Vr,
+        _ => DisplayState::Unknown,
+    }
+}
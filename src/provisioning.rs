@@ -0,0 +1,677 @@
+use crate::atv_controller::{ATVController, validate_package_name};
+use crate::global_device_manager::GlobalDeviceManager;
+use crate::intent::validate_component;
+use crate::macro_manager::MacroManager;
+use crate::packages::PackageFilter;
+use crate::settings::{SettingsNamespace, validate_setting_key, validate_setting_value};
+use poem_openapi::{Enum, Object};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum ProfileFormat {
+    Yaml,
+    Toml,
+}
+
+impl ProfileFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ProfileFormat::Yaml => "yaml",
+            ProfileFormat::Toml => "toml",
+        }
+    }
+}
+
+/// The desired state of a device, e.g.
+///
+/// ```yaml
+/// settings:
+///   global:
+///     stay_on_while_plugged_in: 3
+/// install:
+///   - package: com.example.signage
+///     apk: signage.apk
+/// uninstall: [com.google.android.youtube.tv]
+/// disable: [com.google.android.tvrecommendations]
+/// launcher: com.example.signage/.HomeActivity
+/// screensaver: com.example.signage/.Dream
+/// macro: signage-setup
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub description: Option<String>,
+    pub settings: BTreeMap<SettingsNamespace, BTreeMap<String, SettingValue>>,
+    pub install: Vec<AppSource>,
+    pub uninstall: Vec<String>,
+    pub disable: Vec<String>,
+    /// Component of the home activity.
+    pub launcher: Option<String>,
+    /// Component of the screensaver (daydream).
+    pub screensaver: Option<String>,
+    /// Runs last on every apply, after all other steps succeeded.
+    #[serde(rename = "macro")]
+    pub macro_name: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AppSource {
+    pub package: String,
+    /// Path to the APK on this server, relative to the profiles directory.
+    pub apk: String,
+}
+
+/// Lets profiles write numbers and flags without quoting them. Booleans are
+/// written as 1 and 0, like Android's own flags.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum SettingValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl fmt::Display for SettingValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingValue::Bool(value) => write!(f, "{}", u8::from(*value)),
+            SettingValue::Integer(value) => write!(f, "{}", value),
+            SettingValue::Float(value) => write!(f, "{}", value),
+            SettingValue::Text(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+pub fn parse_profile(format: ProfileFormat, document: &str) -> Result<Profile, Box<dyn Error>> {
+    let profile: Profile = match format {
+        ProfileFormat::Yaml => serde_yaml::from_str(document)?,
+        ProfileFormat::Toml => {
+            let document = toml_edit::Document::parse(document)?;
+            serde_json::from_value(toml_item(document.as_item()))?
+        }
+    };
+    validate_profile(&profile)?;
+    Ok(profile)
+}
+
+fn validate_profile(profile: &Profile) -> Result<(), Box<dyn Error>> {
+    for (key, value) in profile.settings.values().flatten() {
+        validate_setting_key(key)?;
+        validate_setting_value(&value.to_string())?;
+    }
+    for app in &profile.install {
+        validate_package_name(&app.package)?;
+        if app.apk.trim().is_empty() {
+            return Err(format!("No APK given for {}", app.package).into());
+        }
+        if profile.uninstall.contains(&app.package) {
+            return Err(format!("{} is both installed and uninstalled", app.package).into());
+        }
+    }
+    for package in profile.uninstall.iter().chain(&profile.disable) {
+        validate_package_name(package)?;
+    }
+    for component in profile.launcher.iter().chain(&profile.screensaver) {
+        validate_component(component)?;
+    }
+    Ok(())
+}
+
+// Profiles are deserialized through JSON so both formats share one set of
+// serde definitions.
+fn toml_table<'a>(
+    entries: impl IntoIterator<Item = (&'a str, serde_json::Value)>,
+) -> serde_json::Value {
+    serde_json::Value::Object(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn toml_item(item: &toml_edit::Item) -> serde_json::Value {
+    match item {
+        toml_edit::Item::None => serde_json::Value::Null,
+        toml_edit::Item::Value(value) => toml_value(value),
+        toml_edit::Item::Table(table) => {
+            toml_table(table.iter().map(|(key, item)| (key, toml_item(item))))
+        }
+        toml_edit::Item::ArrayOfTables(tables) => tables
+            .iter()
+            .map(|table| toml_table(table.iter().map(|(key, item)| (key, toml_item(item)))))
+            .collect(),
+    }
+}
+
+fn toml_value(value: &toml_edit::Value) -> serde_json::Value {
+    match value {
+        toml_edit::Value::String(value) => value.value().clone().into(),
+        toml_edit::Value::Integer(value) => (*value.value()).into(),
+        toml_edit::Value::Float(value) => (*value.value()).into(),
+        toml_edit::Value::Boolean(value) => (*value.value()).into(),
+        toml_edit::Value::Datetime(value) => value.value().to_string().into(),
+        toml_edit::Value::Array(values) => values.iter().map(toml_value).collect(),
+        toml_edit::Value::InlineTable(table) => {
+            toml_table(table.iter().map(|(key, value)| (key, toml_value(value))))
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct ProfileSummary {
+    pub name: String,
+    pub format: ProfileFormat,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct ProfileDocument {
+    pub name: String,
+    pub format: ProfileFormat,
+    pub document: String,
+}
+
+/// Keeps each profile as a YAML or TOML file, so they can also be edited and
+/// versioned outside the service. APKs referenced by profiles live alongside.
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn list_profiles(&self) -> Result<Vec<ProfileSummary>, Box<dyn Error>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut profiles = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let format = match path.extension().and_then(|extension| extension.to_str()) {
+                Some("yaml" | "yml") => ProfileFormat::Yaml,
+                Some("toml") => ProfileFormat::Toml,
+                _ => continue,
+            };
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                profiles.push(ProfileSummary {
+                    name: name.to_string(),
+                    format,
+                });
+            }
+        }
+        profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(profiles)
+    }
+
+    pub fn get_document(&self, name: &str) -> Result<ProfileDocument, Box<dyn Error>> {
+        validate_profile_name(name)?;
+        let (path, format) = self
+            .find(name)
+            .ok_or_else(|| format!("Profile {} not found", name))?;
+        Ok(ProfileDocument {
+            name: name.to_string(),
+            format,
+            document: fs::read_to_string(path)?,
+        })
+    }
+
+    pub fn load_profile(&self, name: &str) -> Result<Profile, Box<dyn Error>> {
+        let document = self.get_document(name)?;
+        parse_profile(document.format, &document.document)
+            .map_err(|e| format!("Profile {} is invalid: {}", name, e).into())
+    }
+
+    /// Saves the document as written, after checking that it parses.
+    pub fn save_profile(
+        &self,
+        name: &str,
+        format: ProfileFormat,
+        document: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        validate_profile_name(name)?;
+        parse_profile(format, document)?;
+        fs::create_dir_all(&self.dir)?;
+        if let Some((path, _)) = self.find(name) {
+            fs::remove_file(path)?;
+        }
+        fs::write(
+            self.dir.join(format!("{}.{}", name, format.extension())),
+            document,
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_profile(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        validate_profile_name(name)?;
+        let (path, _) = self
+            .find(name)
+            .ok_or_else(|| format!("Profile {} not found", name))?;
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn find(&self, name: &str) -> Option<(PathBuf, ProfileFormat)> {
+        [
+            ("yaml", ProfileFormat::Yaml),
+            ("yml", ProfileFormat::Yaml),
+            ("toml", ProfileFormat::Toml),
+        ]
+        .into_iter()
+        .map(|(extension, format)| (self.dir.join(format!("{}.{}", name, extension)), format))
+        .find(|(path, _)| path.exists())
+    }
+}
+
+pub fn validate_profile_name(name: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid profile name: {}", name).into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum StepKind {
+    Install,
+    Uninstall,
+    Setting,
+    Launcher,
+    Screensaver,
+    Disable,
+    Enable,
+    Wallpaper,
+    Macro,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum StepStatus {
+    /// The device differs from the profile and the step would run.
+    Pending,
+    /// The device already matches the profile.
+    Unchanged,
+    Applied,
+    Failed,
+    /// Not run because an earlier step failed.
+    Skipped,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct ProvisionStep {
+    pub kind: StepKind,
+    /// Package, component, "namespace key" or macro name.
+    pub target: String,
+    pub current: Option<String>,
+    pub desired: Option<String>,
+    pub status: StepStatus,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct DeviceProvisionReport {
+    pub device_id: String,
+    pub success: bool,
+    pub message: String,
+    pub steps: Vec<ProvisionStep>,
+}
+
+enum Action<'a> {
+    Install(&'a AppSource),
+    Uninstall(&'a str),
+    Setting(SettingsNamespace, &'a str, String),
+    Launcher(&'a str),
+    Screensaver(&'a str),
+    Disable(&'a str),
+    Macro(&'a str),
+}
+
+impl Action<'_> {
+    fn kind(&self) -> StepKind {
+        match self {
+            Action::Install(_) => StepKind::Install,
+            Action::Uninstall(_) => StepKind::Uninstall,
+            Action::Setting(..) => StepKind::Setting,
+            Action::Launcher(_) => StepKind::Launcher,
+            Action::Screensaver(_) => StepKind::Screensaver,
+            Action::Disable(_) => StepKind::Disable,
+            Action::Macro(_) => StepKind::Macro,
+        }
+    }
+
+    fn target(&self) -> String {
+        match self {
+            Action::Install(app) => app.package.clone(),
+            Action::Setting(namespace, key, _) => format!("{} {}", namespace.as_arg(), key),
+            Action::Uninstall(target)
+            | Action::Launcher(target)
+            | Action::Screensaver(target)
+            | Action::Disable(target)
+            | Action::Macro(target) => target.to_string(),
+        }
+    }
+}
+
+// Packages come first so the launcher and screensaver can point at freshly
+// installed apps, and the old launcher is only disabled once it was replaced.
+fn actions(profile: &Profile) -> Vec<Action<'_>> {
+    let mut actions: Vec<Action> = profile.install.iter().map(Action::Install).collect();
+    actions.extend(
+        profile
+            .uninstall
+            .iter()
+            .map(|package| Action::Uninstall(package)),
+    );
+    for (namespace, settings) in &profile.settings {
+        for (key, value) in settings {
+            actions.push(Action::Setting(*namespace, key, value.to_string()));
+        }
+    }
+    actions.extend(profile.launcher.as_deref().map(Action::Launcher));
+    actions.extend(profile.screensaver.as_deref().map(Action::Screensaver));
+    actions.extend(
+        profile
+            .disable
+            .iter()
+            .map(|package| Action::Disable(package)),
+    );
+    actions.extend(profile.macro_name.as_deref().map(Action::Macro));
+    actions
+}
+
+struct PackageState {
+    installed: BTreeSet<String>,
+    disabled: BTreeSet<String>,
+}
+
+fn inspect(
+    ctrl: &mut ATVController,
+    action: &Action,
+    packages: &PackageState,
+) -> Result<(Option<String>, Option<String>), Box<dyn Error>> {
+    let state = |installed: bool| installed.then(|| "installed".to_string());
+    Ok(match action {
+        Action::Install(app) => (
+            state(packages.installed.contains(&app.package)),
+            state(true),
+        ),
+        Action::Uninstall(package) => (state(packages.installed.contains(*package)), None),
+        Action::Setting(namespace, key, value) => (
+            ctrl.get_setting(*namespace, key)?.value,
+            Some(value.clone()),
+        ),
+        Action::Launcher(component) => (ctrl.home_activity()?, Some(component.to_string())),
+        Action::Screensaver(component) => {
+            let enabled = ctrl
+                .get_setting(SettingsNamespace::Secure, "screensaver_enabled")?
+                .value;
+            let current = match enabled.as_deref() {
+                Some("1") => {
+                    ctrl.get_setting(SettingsNamespace::Secure, "screensaver_components")?
+                        .value
+                }
+                _ => None,
+            };
+            (current, Some(component.to_string()))
+        }
+        Action::Disable(package) => {
+            let current = if !packages.installed.contains(*package) {
+                None
+            } else if packages.disabled.contains(*package) {
+                Some("disabled".to_string())
+            } else {
+                Some("enabled".to_string())
+            };
+            // Nothing to disable when the package is not there.
+            let desired = current.as_ref().map(|_| "disabled".to_string());
+            (current, desired)
+        }
+        Action::Macro(name) => (None, Some(name.to_string())),
+    })
+}
+
+fn perform(
+    ctrl: &mut ATVController,
+    action: &Action,
+    apk_dir: &Path,
+) -> Result<(), Box<dyn Error>> {
+    match action {
+        Action::Install(app) => {
+            let path = apk_dir.join(&app.apk);
+            let mut apk = fs::File::open(&path)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+            ctrl.install_apk(&app.package, &mut apk)
+        }
+        Action::Uninstall(package) => ctrl.uninstall_package(package),
+        Action::Setting(namespace, key, value) => {
+            ctrl.put_setting(*namespace, key, value).map(|_| ())
+        }
+        Action::Launcher(component) => ctrl.set_home_activity(component),
+        Action::Screensaver(component) => {
+            ctrl.put_setting(
+                SettingsNamespace::Secure,
+                "screensaver_components",
+                component,
+            )?;
+            ctrl.put_setting(SettingsNamespace::Secure, "screensaver_enabled", "1")
+                .map(|_| ())
+        }
+        Action::Disable(package) => ctrl.set_package_enabled(package, false),
+        Action::Macro(_) => unreachable!("macros run on the async side"),
+    }
+}
+
+/// Compares the device with the profile and, when `apply` is set, changes
+/// whatever differs. Steps that already match are left alone, so applying a
+/// profile twice does nothing the second time apart from its macro.
+fn provision_blocking(
+    ctrl: &mut ATVController,
+    profile: &Profile,
+    apk_dir: &Path,
+    apply: bool,
+) -> Result<Vec<ProvisionStep>, Box<dyn Error>> {
+    let actions = actions(profile);
+    let packages = if actions.iter().any(|action| {
+        matches!(
+            action,
+            Action::Install(_) | Action::Uninstall(_) | Action::Disable(_)
+        )
+    }) {
+        PackageState {
+            installed: ctrl.packages(PackageFilter::All)?,
+            disabled: ctrl.packages(PackageFilter::Disabled)?,
+        }
+    } else {
+        PackageState {
+            installed: BTreeSet::new(),
+            disabled: BTreeSet::new(),
+        }
+    };
+
+    let mut steps = Vec::with_capacity(actions.len());
+    for action in &actions {
+        let (current, desired) = inspect(ctrl, action, &packages)?;
+        let pending = matches!(action, Action::Macro(_)) || current != desired;
+        let mut step = ProvisionStep {
+            kind: action.kind(),
+            target: action.target(),
+            current,
+            desired,
+            status: if pending {
+                StepStatus::Pending
+            } else {
+                StepStatus::Unchanged
+            },
+            error: None,
+        };
+        if apply && pending && step.kind != StepKind::Macro {
+            match perform(ctrl, action, apk_dir) {
+                Ok(_) => step.status = StepStatus::Applied,
+                Err(e) => {
+                    step.status = StepStatus::Failed;
+                    step.error = Some(e.to_string());
+                }
+            }
+        }
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+/// Plans or applies `profile` on one device and reports every step.
+pub async fn provision_device(
+    device_manager: &GlobalDeviceManager,
+    macro_manager: &MacroManager,
+    profile: Arc<Profile>,
+    apk_dir: PathBuf,
+    device_id: &str,
+    apply: bool,
+) -> DeviceProvisionReport {
+    let report =
+        |success: bool, message: String, steps: Vec<ProvisionStep>| DeviceProvisionReport {
+            device_id: device_id.to_string(),
+            success,
+            message,
+            steps,
+        };
+    let controller = match device_manager.get_controller(device_id) {
+        Ok(controller) => controller,
+        Err(e) => return report(false, e.to_string(), Vec::new()),
+    };
+
+    let blocking_controller = controller.clone();
+    let blocking_profile = profile.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let mut ctrl = blocking_controller
+            .lock()
+            .map_err(|e| format!("Failed to lock controller: {}", e))?;
+        provision_blocking(&mut ctrl, &blocking_profile, &apk_dir, apply).map_err(|e| e.to_string())
+    })
+    .await
+    .unwrap_or_else(|e| Err(format!("Provisioning task failed: {}", e)));
+    let mut steps = match result {
+        Ok(steps) => steps,
+        Err(message) => return report(false, message, Vec::new()),
+    };
+
+    if let Some(step) = steps.iter_mut().find(|step| step.kind == StepKind::Macro) {
+        let name = step.target.clone();
+        if let Err(e) = macro_manager.get_macro(&name) {
+            step.status = StepStatus::Failed;
+            step.error = Some(e.to_string());
+        }
+    }
+    let failed = steps.iter().any(|step| step.status == StepStatus::Failed);
+    if apply
+        && let Some(step) = steps
+            .iter_mut()
+            .find(|step| step.kind == StepKind::Macro && step.status == StepStatus::Pending)
+    {
+        if failed {
+            step.status = StepStatus::Skipped;
+        } else {
+            match macro_manager
+                .run_macro(controller, device_id, &step.target)
+                .await
+            {
+                Ok(run) if run.cancelled => {
+                    step.status = StepStatus::Failed;
+                    step.error = Some(format!(
+                        "Cancelled after {}/{} steps",
+                        run.steps_completed, run.steps_total
+                    ));
+                }
+                Ok(_) => step.status = StepStatus::Applied,
+                Err(e) => {
+                    step.status = StepStatus::Failed;
+                    step.error = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    let count = |status: StepStatus| steps.iter().filter(|step| step.status == status).count();
+    let failed = count(StepStatus::Failed);
+    let message = if apply {
+        format!(
+            "{} applied, {} unchanged, {} failed, {} skipped",
+            count(StepStatus::Applied),
+            count(StepStatus::Unchanged),
+            failed,
+            count(StepStatus::Skipped)
+        )
+    } else {
+        format!(
+            "{} of {} steps would change the device",
+            count(StepStatus::Pending),
+            steps.len()
+        )
+    };
+    report(failed == 0, message, steps)
+}
+
+/// Runs `provision_device` on every device at once.
+pub async fn provision_devices(
+    device_manager: Arc<GlobalDeviceManager>,
+    macro_manager: Arc<MacroManager>,
+    profile: Arc<Profile>,
+    apk_dir: PathBuf,
+    device_ids: Vec<String>,
+    apply: bool,
+) -> Vec<DeviceProvisionReport> {
+    let handles: Vec<_> = device_ids
+        .into_iter()
+        .map(|device_id| {
+            let device_manager = device_manager.clone();
+            let macro_manager = macro_manager.clone();
+            let profile = profile.clone();
+            let apk_dir = apk_dir.clone();
+            let target = device_id.clone();
+            let handle = tokio::spawn(async move {
+                provision_device(
+                    &device_manager,
+                    &macro_manager,
+                    profile,
+                    apk_dir,
+                    &target,
+                    apply,
+                )
+                .await
+            });
+            (device_id, handle)
+        })
+        .collect();
+
+    let mut reports = Vec::with_capacity(handles.len());
+    for (device_id, handle) in handles {
+        reports.push(handle.await.unwrap_or_else(|e| DeviceProvisionReport {
+            device_id,
+            success: false,
+            message: format!("Provisioning task failed: {}", e),
+            steps: Vec::new(),
+        }));
+    }
+    reports
+}
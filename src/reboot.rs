@@ -0,0 +1,56 @@
+use poem_openapi::Enum;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const DEFAULT_REBOOT_TIMEOUT: Duration = Duration::from_secs(180);
+pub const MAX_REBOOT_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum RebootMode {
+    #[default]
+    Normal,
+    Recovery,
+    Bootloader,
+    Shutdown,
+}
+
+impl RebootMode {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            RebootMode::Normal => "reboot",
+            RebootMode::Recovery => "reboot into recovery",
+            RebootMode::Bootloader => "reboot into the bootloader",
+            RebootMode::Shutdown => "shutdown",
+        }
+    }
+
+    /// Only a normal reboot brings adbd back up in Android.
+    pub fn returns_to_android(&self) -> bool {
+        *self == RebootMode::Normal
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BootState {
+    pub boot_completed: bool,
+    pub uptime: Duration,
+}
+
+/// Parses the output of `getprop sys.boot_completed; cat /proc/uptime`.
+pub fn parse_boot_state(output: &str) -> Option<BootState> {
+    // The property is empty rather than "0" while the device is still booting.
+    let mut lines = output.lines().map(str::trim);
+    let boot_completed = lines.next()? == "1";
+    let uptime = lines
+        .next()?
+        .split_whitespace()
+        .next()?
+        .parse::<f64>()
+        .ok()?;
+    Some(BootState {
+        boot_completed,
+        uptime: Duration::try_from_secs_f64(uptime).ok()?,
+    })
+}
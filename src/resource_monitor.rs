@@ -0,0 +1,332 @@
+use crate::global_device_manager::{DeviceHealth, GlobalDeviceManager};
+use chrono::Local;
+use poem_openapi::Object;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(30);
+const HISTORY_PER_DEVICE: usize = 120;
+const TOP_PROCESS_COUNT: usize = 5;
+
+/// Sections are separated by marker lines so one shell round trip covers everything.
+pub const STATS_COMMAND: &str = "head -n 1 /proc/stat; echo @@; cat /proc/meminfo; echo @@; \
+    for zone in /sys/class/thermal/thermal_zone*; do \
+    echo \"$(cat $zone/type 2>/dev/null) $(cat $zone/temp 2>/dev/null)\"; done; echo @@; \
+    df -k /data /system /cache 2>/dev/null; echo @@; \
+    top -b -n 1 -q -m 5 -o PID,%CPU,%MEM,NAME 2>/dev/null";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CpuTimes {
+    pub busy: u64,
+    pub total: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct MemoryStats {
+    pub total_kb: u64,
+    pub available_kb: u64,
+    pub used_percent: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct ThermalZone {
+    pub name: String,
+    pub celsius: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct StorageStats {
+    pub mount: String,
+    pub total_kb: u64,
+    pub used_kb: u64,
+    pub available_kb: u64,
+    pub used_percent: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct ProcessStats {
+    pub pid: u32,
+    pub name: String,
+    pub cpu_percent: f64,
+    pub memory_percent: f64,
+}
+
+/// One raw reading; CPU load needs two readings, see `ResourceMonitor`.
+#[derive(Clone, Debug)]
+pub struct SystemSnapshot {
+    pub cpu: Option<CpuTimes>,
+    pub memory: Option<MemoryStats>,
+    pub thermal: Vec<ThermalZone>,
+    pub storage: Vec<StorageStats>,
+    pub top_processes: Vec<ProcessStats>,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct StatsSample {
+    pub timestamp: String,
+    /// None for the first sample of a device, which has nothing to compare against.
+    pub cpu_percent: Option<f64>,
+    pub memory: Option<MemoryStats>,
+    pub thermal: Vec<ThermalZone>,
+    /// Hottest thermal zone, for a quick throttling check.
+    pub max_celsius: Option<f64>,
+    pub storage: Vec<StorageStats>,
+    pub top_processes: Vec<ProcessStats>,
+}
+
+pub fn parse_snapshot(output: &str) -> SystemSnapshot {
+    let mut sections = output.split("@@");
+    let mut next = || sections.next().unwrap_or("");
+    SystemSnapshot {
+        cpu: parse_cpu_times(next()),
+        memory: parse_meminfo(next()),
+        thermal: parse_thermal(next()),
+        storage: parse_df(next()),
+        top_processes: parse_top(next()),
+    }
+}
+
+// "cpu  4705 356 584 3699 23 23 0 0 0 0": user nice system idle iowait irq softirq steal ...
+fn parse_cpu_times(section: &str) -> Option<CpuTimes> {
+    let line = section.lines().find(|line| line.starts_with("cpu "))?;
+    let values: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .take(8)
+        .map(|value| value.parse().ok())
+        .collect::<Option<_>>()?;
+    if values.len() < 4 {
+        return None;
+    }
+    let total: u64 = values.iter().sum();
+    let idle = values[3] + values.get(4).copied().unwrap_or(0);
+    Some(CpuTimes {
+        busy: total - idle,
+        total,
+    })
+}
+
+fn parse_meminfo(section: &str) -> Option<MemoryStats> {
+    let fields: HashMap<&str, u64> = section
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some((key.trim(), value.split_whitespace().next()?.parse().ok()?))
+        })
+        .collect();
+    let total_kb = *fields.get("MemTotal")?;
+    // MemAvailable only exists on kernel 3.14 and newer.
+    let available_kb = fields.get("MemAvailable").copied().unwrap_or_else(|| {
+        ["MemFree", "Buffers", "Cached"]
+            .iter()
+            .filter_map(|key| fields.get(key))
+            .sum()
+    });
+    Some(MemoryStats {
+        total_kb,
+        available_kb,
+        used_percent: percent(total_kb.saturating_sub(available_kb), total_kb),
+    })
+}
+
+// "<type> <temp>" per zone; most kernels report millidegrees, a few report degrees.
+fn parse_thermal(section: &str) -> Vec<ThermalZone> {
+    section
+        .lines()
+        .filter_map(|line| {
+            let (name, temp) = line.trim().rsplit_once(' ')?;
+            let temp: f64 = temp.parse().ok()?;
+            let celsius = if temp.abs() >= 1000.0 {
+                temp / 1000.0
+            } else {
+                temp
+            };
+            Some(ThermalZone {
+                name: name.trim().to_string(),
+                celsius,
+            })
+        })
+        .collect()
+}
+
+// "Filesystem 1K-blocks Used Available Use% Mounted on"
+fn parse_df(section: &str) -> Vec<StorageStats> {
+    let mut storage: Vec<StorageStats> = section
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 6 {
+                return None;
+            }
+            let total_kb: u64 = fields[1].parse().ok()?;
+            let used_kb: u64 = fields[2].parse().ok()?;
+            let available_kb: u64 = fields[3].parse().ok()?;
+            Some(StorageStats {
+                mount: fields[5].to_string(),
+                total_kb,
+                used_kb,
+                available_kb,
+                used_percent: percent(used_kb, used_kb + available_kb),
+            })
+        })
+        .collect();
+    storage.dedup_by(|a, b| a.mount == b.mount);
+    storage
+}
+
+// "  1234  12.5  3.2 com.example.app" from toybox top with -q -o PID,%CPU,%MEM,NAME
+fn parse_top(section: &str) -> Vec<ProcessStats> {
+    section
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let pid = fields.next()?.parse().ok()?;
+            let cpu_percent = fields.next()?.parse().ok()?;
+            let memory_percent = fields.next()?.parse().ok()?;
+            let name = fields.collect::<Vec<_>>().join(" ");
+            Some(ProcessStats {
+                pid,
+                name,
+                cpu_percent,
+                memory_percent,
+            })
+        })
+        .take(TOP_PROCESS_COUNT)
+        .collect()
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (part as f64 / total as f64 * 1000.0).round() / 10.0
+    }
+}
+
+/// Reads `ATVMATE_STATS_POLL_SECS`; zero disables collection.
+pub fn stats_interval_from_env() -> Result<Option<Duration>, Box<dyn Error>> {
+    let Ok(value) = std::env::var("ATVMATE_STATS_POLL_SECS") else {
+        return Ok(Some(DEFAULT_STATS_INTERVAL));
+    };
+    let secs: u64 = value
+        .parse()
+        .map_err(|e| format!("Invalid ATVMATE_STATS_POLL_SECS '{}': {}", value, e))?;
+    Ok((secs > 0).then(|| Duration::from_secs(secs)))
+}
+
+#[derive(Default)]
+struct DeviceStats {
+    last_cpu: Option<CpuTimes>,
+    samples: VecDeque<StatsSample>,
+}
+
+pub struct ResourceMonitor {
+    device_manager: Arc<GlobalDeviceManager>,
+    stats: Mutex<HashMap<String, DeviceStats>>,
+}
+
+impl ResourceMonitor {
+    pub fn new(device_manager: Arc<GlobalDeviceManager>) -> Self {
+        Self {
+            device_manager,
+            stats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn start(self: Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let devices = self.device_manager.list_devices();
+                self.stats
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .retain(|device_id, _| devices.contains(device_id));
+
+                let polls: Vec<_> = devices
+                    .into_iter()
+                    .map(|device_id| {
+                        let monitor = self.clone();
+                        tokio::task::spawn_blocking(move || {
+                            if monitor.device_manager.device_health(&device_id).ok()
+                                == Some(DeviceHealth::Online)
+                            {
+                                // Skip busy controllers rather than queueing behind a macro.
+                                let _ = monitor.sample(&device_id, false);
+                            }
+                        })
+                    })
+                    .collect();
+                for poll in polls {
+                    let _ = poll.await;
+                }
+            }
+        })
+    }
+
+    /// Takes a sample now and adds it to the device's history.
+    pub fn sample(&self, device_id: &str, wait: bool) -> Result<StatsSample, Box<dyn Error>> {
+        let controller = self.device_manager.get_controller(device_id)?;
+        let snapshot = if wait {
+            controller
+                .lock()
+                .map_err(|e| format!("Failed to lock controller: {}", e))?
+                .system_snapshot()?
+        } else {
+            controller
+                .try_lock()
+                .map_err(|_| "Controller is busy")?
+                .system_snapshot()?
+        };
+
+        let mut stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        let device = stats.entry(device_id.to_string()).or_default();
+        let cpu_percent = match (device.last_cpu, snapshot.cpu) {
+            (Some(previous), Some(current)) if current.total > previous.total => Some(percent(
+                current.busy.saturating_sub(previous.busy),
+                current.total - previous.total,
+            )),
+            _ => None,
+        };
+        if snapshot.cpu.is_some() {
+            device.last_cpu = snapshot.cpu;
+        }
+        let max_celsius = snapshot
+            .thermal
+            .iter()
+            .map(|zone| zone.celsius)
+            .fold(None, |max: Option<f64>, celsius| {
+                Some(max.map_or(celsius, |max| max.max(celsius)))
+            });
+        let sample = StatsSample {
+            timestamp: Local::now().to_rfc3339(),
+            cpu_percent,
+            memory: snapshot.memory,
+            thermal: snapshot.thermal,
+            max_celsius,
+            storage: snapshot.storage,
+            top_processes: snapshot.top_processes,
+        };
+        if device.samples.len() == HISTORY_PER_DEVICE {
+            device.samples.pop_front();
+        }
+        device.samples.push_back(sample.clone());
+        Ok(sample)
+    }
+
+    /// Most recent samples for a device, oldest first.
+    pub fn history(&self, device_id: &str, limit: usize) -> Vec<StatsSample> {
+        let stats = self.stats.lock().unwrap_or_else(|e| e.into_inner());
+        stats
+            .get(device_id)
+            .map(|device| {
+                let skip = device.samples.len().saturating_sub(limit);
+                device.samples.iter().skip(skip).cloned().collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,387 @@
+use crate::atv_controller::{ATVController, validate_package_name};
+use crate::command::{DeviceCommand, DeviceCommandResult, PowerState, broadcast_command};
+use crate::events::DeviceEvent;
+use crate::global_device_manager::GlobalDeviceManager;
+use crate::group_manager::GroupManager;
+use crate::macro_manager::MacroManager;
+use crate::storage::{load_json, save_json};
+use chrono::{DateTime, Local, Timelike};
+use croner::Cron;
+use poem_openapi::{Object, Union};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_HISTORY_PER_JOB: usize = 50;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct KeyAction {
+    pub key: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct MacroAction {
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct LaunchAppAction {
+    pub package: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct RebootAction {}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct PowerAction {
+    pub state: PowerState,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Union)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[oai(discriminator_name = "type", rename_all = "snake_case")]
+pub enum ScheduledAction {
+    Key(KeyAction),
+    Macro(MacroAction),
+    LaunchApp(LaunchAppAction),
+    Reboot(RebootAction),
+    Power(PowerAction),
+}
+
+impl From<&ScheduledAction> for DeviceCommand {
+    fn from(action: &ScheduledAction) -> Self {
+        match action {
+            ScheduledAction::Key(action) => DeviceCommand::Key(action.key.clone()),
+            ScheduledAction::Macro(action) => DeviceCommand::Macro(action.name.clone()),
+            ScheduledAction::LaunchApp(action) => DeviceCommand::LaunchApp(action.package.clone()),
+            ScheduledAction::Reboot(_) => DeviceCommand::Reboot,
+            ScheduledAction::Power(action) => DeviceCommand::Power(action.state),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct ScheduledJob {
+    pub name: String,
+    /// Five-field cron expression evaluated in the server's local time zone.
+    pub cron: Option<String>,
+    /// RFC 3339 timestamp for a one-shot job.
+    pub run_at: Option<String>,
+    pub device_id: Option<String>,
+    pub group: Option<String>,
+    pub action: ScheduledAction,
+    #[serde(default = "default_enabled")]
+    #[oai(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct JobRun {
+    pub started_at: String,
+    pub finished_at: String,
+    pub success: bool,
+    pub message: String,
+    pub results: Vec<DeviceCommandResult>,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct ScheduleInfo {
+    pub job: ScheduledJob,
+    pub next_run: Option<String>,
+    pub last_run: Option<JobRun>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ScheduleState {
+    jobs: BTreeMap<String, ScheduledJob>,
+    history: BTreeMap<String, Vec<JobRun>>,
+}
+
+pub struct Scheduler {
+    path: PathBuf,
+    state: Mutex<ScheduleState>,
+    next_runs: Mutex<HashMap<String, DateTime<Local>>>,
+    device_manager: Arc<GlobalDeviceManager>,
+    macro_manager: Arc<MacroManager>,
+    group_manager: Arc<GroupManager>,
+}
+
+impl Scheduler {
+    pub fn load(
+        path: impl Into<PathBuf>,
+        device_manager: Arc<GlobalDeviceManager>,
+        macro_manager: Arc<MacroManager>,
+        group_manager: Arc<GroupManager>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let path = path.into();
+        let state: ScheduleState = load_json(&path)?;
+        let now = Local::now();
+        let next_runs = state
+            .jobs
+            .values()
+            .filter_map(|job| next_run_after(job, now).map(|next| (job.name.clone(), next)))
+            .collect();
+        Ok(Self {
+            path,
+            state: Mutex::new(state),
+            next_runs: Mutex::new(next_runs),
+            device_manager,
+            macro_manager,
+            group_manager,
+        })
+    }
+
+    pub fn start(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK_INTERVAL);
+            loop {
+                interval.tick().await;
+                for job in self.take_due_jobs(Local::now()) {
+                    let scheduler = self.clone();
+                    tokio::spawn(async move {
+                        scheduler.execute_job(job, true).await;
+                    });
+                }
+            }
+        })
+    }
+
+    pub fn list_jobs(&self) -> Vec<ScheduleInfo> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let next_runs = self.next_runs.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .jobs
+            .values()
+            .map(|job| ScheduleInfo {
+                job: job.clone(),
+                next_run: next_runs.get(&job.name).map(|next| next.to_rfc3339()),
+                last_run: state
+                    .history
+                    .get(&job.name)
+                    .and_then(|runs| runs.last())
+                    .cloned(),
+            })
+            .collect()
+    }
+
+    pub fn job_history(&self, name: &str) -> Result<Vec<JobRun>, Box<dyn Error>> {
+        let state = self
+            .state
+            .lock()
+            .map_err(|e| format!("Failed to lock schedules: {}", e))?;
+        if !state.jobs.contains_key(name) {
+            return Err(format!("Schedule {} not found", name).into());
+        }
+        Ok(state.history.get(name).cloned().unwrap_or_default())
+    }
+
+    pub fn save_job(&self, job: ScheduledJob) -> Result<(), Box<dyn Error>> {
+        validate_job(&job)?;
+        let next = next_run_after(&job, Local::now());
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|e| format!("Failed to lock schedules: {}", e))?;
+        let name = job.name.clone();
+        state.jobs.insert(name.clone(), job);
+        save_json(&self.path, &*state)?;
+        drop(state);
+        self.set_next_run(&name, next);
+        Ok(())
+    }
+
+    pub fn remove_job(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|e| format!("Failed to lock schedules: {}", e))?;
+        if state.jobs.remove(name).is_none() {
+            return Err(format!("Schedule {} not found", name).into());
+        }
+        state.history.remove(name);
+        save_json(&self.path, &*state)?;
+        drop(state);
+        self.set_next_run(name, None);
+        Ok(())
+    }
+
+    pub async fn run_job_now(&self, name: &str) -> Result<JobRun, Box<dyn Error + Send + Sync>> {
+        let job = {
+            let state = self
+                .state
+                .lock()
+                .map_err(|e| format!("Failed to lock schedules: {}", e))?;
+            state
+                .jobs
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("Schedule {} not found", name))?
+        };
+        Ok(self.execute_job(job, false).await)
+    }
+
+    fn take_due_jobs(&self, now: DateTime<Local>) -> Vec<ScheduledJob> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut next_runs = self.next_runs.lock().unwrap_or_else(|e| e.into_inner());
+        let mut due = Vec::new();
+        for job in state.jobs.values() {
+            if !matches!(next_runs.get(&job.name), Some(next) if *next <= now) {
+                continue;
+            }
+            match job.cron.as_ref().and_then(|_| next_run_after(job, now)) {
+                Some(next) => next_runs.insert(job.name.clone(), next),
+                None => next_runs.remove(&job.name),
+            };
+            due.push(job.clone());
+        }
+        due
+    }
+
+    async fn execute_job(&self, job: ScheduledJob, scheduled: bool) -> JobRun {
+        let started_at = Local::now().to_rfc3339();
+        let command = DeviceCommand::from(&job.action);
+
+        let target = match (&job.device_id, &job.group) {
+            (Some(device_id), _) => Ok(vec![device_id.clone()]),
+            (None, Some(group)) => self
+                .group_manager
+                .resolve_devices(group, &self.device_manager)
+                .map_err(|e| e.to_string()),
+            (None, None) => Err("Schedule has no target".to_string()),
+        };
+
+        let run = match target {
+            Ok(device_ids) => {
+                let results = broadcast_command(
+                    self.device_manager.clone(),
+                    self.macro_manager.clone(),
+                    device_ids,
+                    command.clone(),
+                )
+                .await;
+                let succeeded = results.iter().filter(|result| result.success).count();
+                JobRun {
+                    started_at,
+                    finished_at: Local::now().to_rfc3339(),
+                    success: succeeded == results.len(),
+                    message: format!(
+                        "Sent {}: {}/{} devices succeeded",
+                        command.describe(),
+                        succeeded,
+                        results.len()
+                    ),
+                    results,
+                }
+            }
+            Err(e) => JobRun {
+                started_at,
+                finished_at: Local::now().to_rfc3339(),
+                success: false,
+                message: e,
+                results: Vec::new(),
+            },
+        };
+
+        if !run.success {
+            self.device_manager
+                .events()
+                .publish(DeviceEvent::ScheduledJobFailed {
+                    job: job.name.clone(),
+                    message: run.message.clone(),
+                });
+        }
+        self.record_run(&job, run.clone(), scheduled);
+        run
+    }
+
+    fn record_run(&self, job: &ScheduledJob, run: JobRun, scheduled: bool) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        // The job may have been removed while it was running.
+        if !state.jobs.contains_key(&job.name) {
+            return;
+        }
+        if scheduled
+            && job.cron.is_none()
+            && let Some(stored) = state.jobs.get_mut(&job.name)
+        {
+            stored.enabled = false;
+        }
+        let history = state.history.entry(job.name.clone()).or_default();
+        history.push(run);
+        if history.len() > MAX_HISTORY_PER_JOB {
+            let excess = history.len() - MAX_HISTORY_PER_JOB;
+            history.drain(..excess);
+        }
+        if let Err(e) = save_json(&self.path, &*state) {
+            eprintln!("Failed to save schedules: {}", e);
+        }
+    }
+
+    fn set_next_run(&self, name: &str, next: Option<DateTime<Local>>) {
+        let mut next_runs = self.next_runs.lock().unwrap_or_else(|e| e.into_inner());
+        match next {
+            Some(next) => next_runs.insert(name.to_string(), next),
+            None => next_runs.remove(name),
+        };
+    }
+}
+
+fn next_run_after(job: &ScheduledJob, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    if !job.enabled {
+        return None;
+    }
+    if let Some(expression) = &job.cron {
+        let cron = Cron::from_str(expression).ok()?;
+        let now = now.with_nanosecond(0).unwrap_or(now);
+        return cron.find_next_occurrence(&now, false).ok();
+    }
+    // One-shot jobs that were missed while the server was down still run once.
+    job.run_at
+        .as_deref()
+        .and_then(|run_at| DateTime::parse_from_rfc3339(run_at).ok())
+        .map(|run_at| run_at.with_timezone(&Local))
+}
+
+fn validate_job(job: &ScheduledJob) -> Result<(), Box<dyn Error>> {
+    let valid_name = !job.name.is_empty()
+        && job
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        return Err(format!("Invalid schedule name: {}", job.name).into());
+    }
+
+    match (&job.cron, &job.run_at) {
+        (Some(expression), None) => {
+            Cron::from_str(expression)
+                .map_err(|e| format!("Invalid cron expression '{}': {}", expression, e))?;
+        }
+        (None, Some(run_at)) => {
+            DateTime::parse_from_rfc3339(run_at)
+                .map_err(|e| format!("Invalid run_at '{}': {}", run_at, e))?;
+        }
+        _ => return Err("Exactly one of cron or run_at must be set".into()),
+    }
+
+    if job.device_id.is_some() == job.group.is_some() {
+        return Err("Exactly one of device_id or group must be set".into());
+    }
+
+    match &job.action {
+        ScheduledAction::Key(action) if ATVController::keycode_for(&action.key).is_none() => {
+            Err(format!("Unknown key: {}", action.key).into())
+        }
+        ScheduledAction::LaunchApp(action) => validate_package_name(&action.package),
+        _ => Ok(()),
+    }
+}
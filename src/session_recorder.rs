@@ -0,0 +1,213 @@
+use crate::macro_manager::{MacroDefinition, MacroStep, WaitStep};
+use crate::storage::{load_json, save_json};
+use chrono::Local;
+use poem_openapi::Object;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+pub const MAX_REPLAY_SPEED: f64 = 100.0;
+/// Gaps shorter than this are left out when converting to a macro.
+const MIN_WAIT_MS: u64 = 100;
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct RecordedStep {
+    /// Milliseconds since the recording started.
+    pub offset_ms: u64,
+    pub timestamp: String,
+    pub step: MacroStep,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct Session {
+    pub name: String,
+    pub device_id: String,
+    pub started_at: String,
+    pub duration_ms: u64,
+    pub steps: Vec<RecordedStep>,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct SessionSummary {
+    pub name: String,
+    pub device_id: String,
+    pub started_at: String,
+    pub duration_ms: u64,
+    pub step_count: u32,
+}
+
+impl Session {
+    pub fn summary(&self) -> SessionSummary {
+        SessionSummary {
+            name: self.name.clone(),
+            device_id: self.device_id.clone(),
+            started_at: self.started_at.clone(),
+            duration_ms: self.duration_ms,
+            step_count: self.steps.len() as u32,
+        }
+    }
+
+    /// Turns the session into macro steps, with the gaps between commands
+    /// as waits divided by `speed`.
+    pub fn to_macro(
+        &self,
+        name: &str,
+        description: Option<String>,
+        speed: f64,
+    ) -> Result<MacroDefinition, Box<dyn Error>> {
+        validate_speed(speed)?;
+        let mut steps = Vec::with_capacity(self.steps.len() * 2);
+        let mut previous_offset = 0;
+        for recorded in &self.steps {
+            let gap = recorded.offset_ms.saturating_sub(previous_offset);
+            let wait = (gap as f64 / speed).round() as u64;
+            if wait >= MIN_WAIT_MS {
+                steps.push(MacroStep::Wait(WaitStep { ms: wait }));
+            }
+            steps.push(recorded.step.clone());
+            previous_offset = recorded.offset_ms;
+        }
+        Ok(MacroDefinition {
+            name: name.to_string(),
+            description,
+            steps,
+        })
+    }
+}
+
+/// An in-progress recording, owned by the device's controller.
+pub struct Recording {
+    name: String,
+    started: Instant,
+    started_at: String,
+    steps: Vec<RecordedStep>,
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct RecordingStatus {
+    pub name: String,
+    pub started_at: String,
+    pub step_count: u32,
+}
+
+impl Recording {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            started: Instant::now(),
+            started_at: Local::now().to_rfc3339(),
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, step: MacroStep) {
+        self.steps.push(RecordedStep {
+            offset_ms: self.started.elapsed().as_millis() as u64,
+            timestamp: Local::now().to_rfc3339(),
+            step,
+        });
+    }
+
+    pub fn status(&self) -> RecordingStatus {
+        RecordingStatus {
+            name: self.name.clone(),
+            started_at: self.started_at.clone(),
+            step_count: self.steps.len() as u32,
+        }
+    }
+
+    pub fn finish(self, device_id: &str) -> Session {
+        Session {
+            name: self.name,
+            device_id: device_id.to_string(),
+            started_at: self.started_at,
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            steps: self.steps,
+        }
+    }
+}
+
+/// Stores each session as its own JSON file.
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn list_sessions(&self) -> Result<Vec<SessionSummary>, Box<dyn Error>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut sessions = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                let session: Option<Session> = load_json(&path)?;
+                sessions.extend(session.map(|session| session.summary()));
+            }
+        }
+        sessions.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        Ok(sessions)
+    }
+
+    pub fn get_session(&self, name: &str) -> Result<Session, Box<dyn Error>> {
+        validate_session_name(name)?;
+        let session: Option<Session> = load_json(&self.path(name))?;
+        session.ok_or_else(|| format!("Session {} not found", name).into())
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.path(name).exists()
+    }
+
+    pub fn save_session(&self, session: &Session) -> Result<(), Box<dyn Error>> {
+        validate_session_name(&session.name)?;
+        save_json(&self.path(&session.name), session)
+    }
+
+    pub fn remove_session(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        validate_session_name(name)?;
+        let path = self.path(name);
+        if !path.exists() {
+            return Err(format!("Session {} not found", name).into());
+        }
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+}
+
+pub fn validate_session_name(name: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid session name: {}", name).into())
+    }
+}
+
+pub fn validate_speed(speed: f64) -> Result<(), Box<dyn Error>> {
+    if speed.is_finite() && speed > 0.0 && speed <= MAX_REPLAY_SPEED {
+        Ok(())
+    } else {
+        Err(format!(
+            "Speed must be greater than 0 and at most {}, got {}",
+            MAX_REPLAY_SPEED, speed
+        )
+        .into())
+    }
+}
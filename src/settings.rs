@@ -0,0 +1,206 @@
+use poem_openapi::{Enum, Object};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// Undo history kept per device, oldest entries are dropped first.
+pub const SETTINGS_HISTORY_LIMIT: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum SettingsNamespace {
+    System,
+    Secure,
+    Global,
+}
+
+impl SettingsNamespace {
+    pub fn as_arg(&self) -> &'static str {
+        match self {
+            SettingsNamespace::System => "system",
+            SettingsNamespace::Secure => "secure",
+            SettingsNamespace::Global => "global",
+        }
+    }
+}
+
+pub struct KnownSetting {
+    pub namespace: SettingsNamespace,
+    pub key: &'static str,
+    pub description: &'static str,
+}
+
+const fn known(
+    namespace: SettingsNamespace,
+    key: &'static str,
+    description: &'static str,
+) -> KnownSetting {
+    KnownSetting {
+        namespace,
+        key,
+        description,
+    }
+}
+
+pub const KNOWN_SETTINGS: &[KnownSetting] = &[
+    known(
+        SettingsNamespace::System,
+        "screen_off_timeout",
+        "Milliseconds of inactivity before the screen turns off",
+    ),
+    known(
+        SettingsNamespace::Secure,
+        "sleep_timeout",
+        "Milliseconds of inactivity before the device goes to sleep (Android TV), -1 for never",
+    ),
+    known(
+        SettingsNamespace::Secure,
+        "screensaver_enabled",
+        "1 to start the screensaver (daydream) when idle, 0 to disable it",
+    ),
+    known(
+        SettingsNamespace::Secure,
+        "screensaver_components",
+        "Screensaver component, e.g. com.google.android.backdrop/.Backdrop",
+    ),
+    known(
+        SettingsNamespace::Secure,
+        "screensaver_activate_on_sleep",
+        "1 to show the screensaver instead of sleeping",
+    ),
+    known(
+        SettingsNamespace::Global,
+        "stay_on_while_plugged_in",
+        "Bitmask of power sources that keep the screen on: 1 AC, 2 USB, 4 wireless, 0 for none",
+    ),
+    known(
+        SettingsNamespace::Global,
+        "window_animation_scale",
+        "Window animation speed, 0 disables animations, 1 is normal",
+    ),
+    known(
+        SettingsNamespace::Global,
+        "transition_animation_scale",
+        "Transition animation speed, 0 disables animations, 1 is normal",
+    ),
+    known(
+        SettingsNamespace::Global,
+        "animator_duration_scale",
+        "Animator duration scale, 0 disables animations, 1 is normal",
+    ),
+    known(
+        SettingsNamespace::Global,
+        "adb_enabled",
+        "1 when USB debugging is enabled",
+    ),
+    known(
+        SettingsNamespace::Global,
+        "adb_wifi_enabled",
+        "1 when wireless debugging is enabled (Android 11+)",
+    ),
+    known(
+        SettingsNamespace::Global,
+        "install_non_market_apps",
+        "1 to allow installing apps from unknown sources (before Android 8)",
+    ),
+    known(
+        SettingsNamespace::Secure,
+        "install_non_market_apps",
+        "1 to allow installing apps from unknown sources (before Android 8)",
+    ),
+];
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct Setting {
+    pub namespace: SettingsNamespace,
+    pub key: String,
+    /// None when the key is not set.
+    pub value: Option<String>,
+    /// Present for allow-listed keys.
+    pub description: Option<String>,
+}
+
+impl Setting {
+    pub fn new(namespace: SettingsNamespace, key: &str, value: Option<String>) -> Self {
+        Self {
+            namespace,
+            key: key.to_string(),
+            value,
+            description: known_setting(namespace, key).map(|known| known.description.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct SettingChange {
+    pub namespace: SettingsNamespace,
+    pub key: String,
+    pub previous: Option<String>,
+    /// None when the key was deleted.
+    pub value: Option<String>,
+    pub changed_at: String,
+}
+
+pub fn known_setting(namespace: SettingsNamespace, key: &str) -> Option<&'static KnownSetting> {
+    KNOWN_SETTINGS
+        .iter()
+        .find(|known| known.namespace == namespace && known.key == key)
+}
+
+/// Writes are limited to allow-listed keys unless the caller forces them.
+pub fn check_writable(
+    namespace: SettingsNamespace,
+    key: &str,
+    force: bool,
+) -> Result<(), Box<dyn Error>> {
+    validate_setting_key(key)?;
+    if force || known_setting(namespace, key).is_some() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} {} is not a known setting, pass force to change it anyway",
+            namespace.as_arg(),
+            key
+        )
+        .into())
+    }
+}
+
+pub fn validate_setting_key(key: &str) -> Result<(), Box<dyn Error>> {
+    let valid = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid setting key: {}", key).into())
+    }
+}
+
+pub fn validate_setting_value(value: &str) -> Result<(), Box<dyn Error>> {
+    if value.is_empty() || value.chars().any(char::is_control) {
+        return Err("Setting values must be non-empty and free of control characters".into());
+    }
+    Ok(())
+}
+
+/// `settings get` prints "null" for keys that are not set.
+pub fn parse_setting_value(output: &str) -> Option<String> {
+    match output.trim() {
+        "null" => None,
+        value => Some(value.to_string()),
+    }
+}
+
+/// Parses `settings list <namespace>`, one "key=value" per line.
+pub fn parse_settings_list(namespace: SettingsNamespace, output: &str) -> Vec<Setting> {
+    let mut settings: Vec<Setting> = output
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(key, _)| validate_setting_key(key).is_ok())
+        .map(|(key, value)| Setting::new(namespace, key, parse_setting_value(value)))
+        .collect();
+    settings.sort_by(|a, b| a.key.cmp(&b.key));
+    settings
+}
@@ -0,0 +1,24 @@
+use serde::{Serialize, de::DeserializeOwned};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+pub fn load_json<T: DeserializeOwned + Default>(path: &Path) -> Result<T, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(T::default());
+    }
+    let content = fs::read_to_string(path)?;
+    serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse {}: {}", path.display(), e).into())
+}
+
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Write to a sibling file first so a crash never leaves a truncated store behind.
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_string_pretty(value)?)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use crate::device::{ADBDevice, DeviceConnection};
+use adb_client::{ADBListItemType, AdbStatResponse, RebootType, Result, RustADBError};
+use std::io::{Read, Write};
+
+/// Everything the controller needs from a connection to a device.
+pub trait DeviceTransport: Send {
+    fn shell_command(&mut self, command: &str, output: &mut dyn Write) -> Result<()>;
+
+    /// Runs a command without a shell in between, feeding it `input`.
+    fn exec(
+        &mut self,
+        command: &str,
+        input: &mut dyn Read,
+        output: Box<dyn Write + Send>,
+    ) -> Result<()>;
+
+    fn stat(&mut self, path: &str) -> Result<AdbStatResponse>;
+
+    fn list(&mut self, path: &str) -> Result<Vec<ADBListItemType>>;
+
+    fn pull(&mut self, source: &str, output: &mut dyn Write) -> Result<()>;
+
+    fn push(&mut self, input: &mut dyn Read, path: &str) -> Result<()>;
+
+    fn reboot(&mut self, reboot_type: RebootType) -> Result<()>;
+
+    /// Drops the current connection and opens a new one to the same device.
+    fn reconnect(&mut self) -> Result<()>;
+
+    /// Opens an independent connection, used for long-running commands.
+    fn open_connection(&self) -> Result<Box<dyn DeviceTransport>>;
+}
+
+/// An adb_client device together with how it was reached, so it can reconnect.
+pub struct AdbTransport {
+    connection: DeviceConnection,
+    device: ADBDevice,
+}
+
+impl AdbTransport {
+    pub fn connect(connection: DeviceConnection) -> Result<Self> {
+        let device = connection.connect()?;
+        Ok(Self::new(connection, device))
+    }
+
+    pub fn new(connection: DeviceConnection, device: ADBDevice) -> Self {
+        Self { connection, device }
+    }
+}
+
+impl DeviceTransport for AdbTransport {
+    fn shell_command(&mut self, command: &str, output: &mut dyn Write) -> Result<()> {
+        self.device.shell_command(&command, output)
+    }
+
+    fn exec(
+        &mut self,
+        command: &str,
+        input: &mut dyn Read,
+        output: Box<dyn Write + Send>,
+    ) -> Result<()> {
+        self.device.exec(command, input, output)
+    }
+
+    fn stat(&mut self, path: &str) -> Result<AdbStatResponse> {
+        self.device.stat(&path)
+    }
+
+    fn list(&mut self, path: &str) -> Result<Vec<ADBListItemType>> {
+        self.device.list(&path)
+    }
+
+    fn pull(&mut self, source: &str, output: &mut dyn Write) -> Result<()> {
+        self.device.pull(&source, output)
+    }
+
+    fn push(&mut self, input: &mut dyn Read, path: &str) -> Result<()> {
+        self.device.push(input, &path)
+    }
+
+    fn reboot(&mut self, reboot_type: RebootType) -> Result<()> {
+        self.device.reboot(reboot_type)
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        self.device = self.connection.connect()?;
+        Ok(())
+    }
+
+    fn open_connection(&self) -> Result<Box<dyn DeviceTransport>> {
+        // USB interfaces can only be claimed once.
+        if matches!(self.connection, DeviceConnection::Usb { .. }) {
+            return Err(RustADBError::ADBRequestFailed(
+                "USB connections do not support a second connection".to_string(),
+            ));
+        }
+        Ok(Box::new(Self::connect(self.connection.clone())?))
+    }
+}
@@ -0,0 +1,666 @@
+use crate::storage::{load_json, save_json};
+use crate::transport::DeviceTransport;
+use crate::tv_remote_proto::{
+    CODE_LENGTH, ENCODING_HEXADECIMAL, FrameReader, KeyDirection, PairingMessage, ROLE_INPUT,
+    RemoteDeviceInfo, RemoteMessage, write_frame,
+};
+use adb_client::{ADBListItemType, AdbStatResponse, RebootType, RustADBError};
+use rcgen::{CertificateParams, DnType, KeyPair, PKCS_RSA_SHA256};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, aws_lc_rs, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, SignatureScheme, StreamOwned};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use x509_parser::prelude::{FromDer, X509Certificate};
+use x509_parser::public_key::PublicKey;
+
+pub const PAIRING_PORT: u16 = 6467;
+pub const REMOTE_PORT: u16 = 6466;
+
+const CLIENT_NAME: &str = "atvmate";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const VOLUME_SETTLE_TIMEOUT: Duration = Duration::from_secs(2);
+// Feature bits announced in RemoteConfigure: ping, key, power, volume and app links.
+const REMOTE_FEATURES: u64 = 622;
+const APP_LINK_PREFIX: &str = "market://launch?id=";
+const MUSIC_STREAM: &str = "3";
+
+type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+#[derive(Serialize, Deserialize)]
+struct StoredIdentity {
+    certificate: String,
+    private_key: String,
+}
+
+/// The certificate atvmate presents to TVs. A TV remembers paired clients by
+/// this certificate, so it has to survive restarts.
+#[derive(Clone)]
+pub struct RemoteIdentity {
+    certificate: Vec<u8>,
+    private_key: Vec<u8>,
+}
+
+impl RemoteIdentity {
+    /// Creates a self-signed RSA certificate; pairing hashes the RSA key, so
+    /// other key types are not accepted by TVs.
+    pub fn generate(common_name: &str) -> Result<Self, Box<dyn Error>> {
+        let key_pair = KeyPair::generate_for(&PKCS_RSA_SHA256)?;
+        let mut params = CertificateParams::default();
+        params
+            .distinguished_name
+            .push(DnType::CommonName, common_name);
+        let certificate = params.self_signed(&key_pair)?;
+        Ok(Self {
+            certificate: certificate.der().to_vec(),
+            private_key: key_pair.serialize_der(),
+        })
+    }
+
+    pub fn load_or_create(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if let Some(stored) = load_json::<Option<StoredIdentity>>(path)? {
+            let invalid =
+                |e: pem::PemError| format!("Invalid identity in {}: {}", path.display(), e);
+            return Ok(Self {
+                certificate: pem::parse(&stored.certificate)
+                    .map_err(invalid)?
+                    .into_contents(),
+                private_key: pem::parse(&stored.private_key)
+                    .map_err(invalid)?
+                    .into_contents(),
+            });
+        }
+        let identity = Self::generate(CLIENT_NAME)?;
+        save_json(
+            path,
+            &StoredIdentity {
+                certificate: pem::encode(&pem::Pem::new(
+                    "CERTIFICATE",
+                    identity.certificate.clone(),
+                )),
+                private_key: pem::encode(&pem::Pem::new(
+                    "PRIVATE KEY",
+                    identity.private_key.clone(),
+                )),
+            },
+        )?;
+        Ok(identity)
+    }
+
+    pub fn certificate(&self) -> CertificateDer<'static> {
+        CertificateDer::from(self.certificate.clone())
+    }
+
+    pub fn private_key(&self) -> PrivateKeyDer<'static> {
+        PrivatePkcs8KeyDer::from(self.private_key.clone()).into()
+    }
+}
+
+pub(crate) fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(aws_lc_rs::default_provider())
+}
+
+/// TVs present self-signed certificates; trust comes from pairing, so any
+/// certificate is accepted as long as the handshake signatures check out.
+#[derive(Debug)]
+struct AnyServerCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyServerCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn connect_tls(addr: SocketAddr, identity: &RemoteIdentity) -> Result<TlsStream, Box<dyn Error>> {
+    let provider = crypto_provider();
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AnyServerCertificate(provider)))
+        .with_client_auth_cert(vec![identity.certificate()], identity.private_key())?;
+    let socket = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?;
+    socket.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    socket.set_nodelay(true)?;
+    let connection = ClientConnection::new(Arc::new(config), ServerName::from(addr.ip()))?;
+    let mut stream = StreamOwned::new(connection, socket);
+    // Finish the handshake up front so certificate problems surface here.
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+    Ok(stream)
+}
+
+fn rsa_public_key(certificate: &[u8]) -> Result<(Vec<u8>, Vec<u8>), Box<dyn Error>> {
+    let (_, certificate) = X509Certificate::from_der(certificate)
+        .map_err(|e| format!("Invalid certificate: {}", e))?;
+    match certificate.public_key().parsed() {
+        Ok(PublicKey::RSA(key)) => Ok((
+            trim_leading_zeros(key.modulus),
+            trim_leading_zeros(key.exponent),
+        )),
+        _ => Err("Certificate does not hold an RSA key".into()),
+    }
+}
+
+fn trim_leading_zeros(value: &[u8]) -> Vec<u8> {
+    let start = value
+        .iter()
+        .position(|&byte| byte != 0)
+        .unwrap_or(value.len());
+    value[start..].to_vec()
+}
+
+/// The secret both sides derive from their RSA keys and the last two bytes
+/// of the code on screen. The TV shows the secret's first byte as the first
+/// two digits of the code, which lets the client check what was typed.
+pub(crate) fn pairing_secret(
+    client_certificate: &[u8],
+    server_certificate: &[u8],
+    nonce: &[u8],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    for certificate in [client_certificate, server_certificate] {
+        let (modulus, exponent) = rsa_public_key(certificate)?;
+        hasher.update(modulus);
+        hasher.update(exponent);
+    }
+    hasher.update(nonce);
+    Ok(hasher.finalize().to_vec())
+}
+
+/// A pairing handshake that is waiting for the code shown on the TV.
+pub struct PairingSession {
+    stream: TlsStream,
+    reader: FrameReader,
+    client_certificate: Vec<u8>,
+    server_certificate: Vec<u8>,
+}
+
+impl PairingSession {
+    /// Connects to the pairing service and asks the TV to show a code.
+    pub fn start(addr: SocketAddr, identity: &RemoteIdentity) -> Result<Self, Box<dyn Error>> {
+        let stream = connect_tls(addr, identity)?;
+        let server_certificate = stream
+            .conn
+            .peer_certificates()
+            .and_then(|certificates| certificates.first())
+            .ok_or("TV did not present a certificate")?
+            .to_vec();
+        let mut session = Self {
+            stream,
+            reader: FrameReader::new(),
+            client_certificate: identity.certificate.clone(),
+            server_certificate,
+        };
+        session.exchange(PairingMessage::Request {
+            service_name: CLIENT_NAME.to_string(),
+            client_name: CLIENT_NAME.to_string(),
+        })?;
+        session.exchange(PairingMessage::Options {
+            encoding: ENCODING_HEXADECIMAL,
+            symbol_length: CODE_LENGTH,
+            preferred_role: ROLE_INPUT,
+        })?;
+        match session.exchange(PairingMessage::Configuration {
+            encoding: ENCODING_HEXADECIMAL,
+            symbol_length: CODE_LENGTH,
+            client_role: ROLE_INPUT,
+        })? {
+            PairingMessage::ConfigurationAck => Ok(session),
+            reply => Err(format!("Unexpected pairing reply: {:?}", reply).into()),
+        }
+    }
+
+    /// Checks `code` and sends the derived secret. A mistyped code is caught
+    /// locally, so the session stays usable for another attempt.
+    pub fn finish(&mut self, code: &str) -> Result<(), Box<dyn Error>> {
+        let code = hex::decode(code.trim())
+            .ok()
+            .filter(|code| code.len() == 3)
+            .ok_or("Pairing code must be 6 hexadecimal characters")?;
+        let secret = pairing_secret(
+            &self.client_certificate,
+            &self.server_certificate,
+            &code[1..],
+        )?;
+        if secret[0] != code[0] {
+            return Err("Pairing code does not match the one shown on the TV".into());
+        }
+        match self.exchange(PairingMessage::Secret(secret))? {
+            PairingMessage::SecretAck(_) => Ok(()),
+            reply => Err(format!("Unexpected pairing reply: {:?}", reply).into()),
+        }
+    }
+
+    fn exchange(&mut self, message: PairingMessage) -> Result<PairingMessage, Box<dyn Error>> {
+        write_frame(&mut self.stream, &message.encode())?;
+        let reply = PairingMessage::decode(&self.reader.read_frame(&mut self.stream)?)?;
+        match reply {
+            PairingMessage::Error(status) => {
+                Err(format!("TV rejected pairing with status {}", status).into())
+            }
+            reply => Ok(reply),
+        }
+    }
+}
+
+/// The client identity plus pairing handshakes that are waiting for a code,
+/// keyed by the TV's pairing address.
+pub struct RemotePairing {
+    path: PathBuf,
+    identity: Mutex<Option<RemoteIdentity>>,
+    sessions: Mutex<HashMap<SocketAddr, PairingSession>>,
+}
+
+impl RemotePairing {
+    /// The identity stored at `path` is created on first use, since
+    /// generating an RSA key takes a moment.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            identity: Mutex::new(None),
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn identity(&self) -> Result<RemoteIdentity, Box<dyn Error>> {
+        let mut identity = self.identity.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(identity) = identity.as_ref() {
+            return Ok(identity.clone());
+        }
+        let loaded = RemoteIdentity::load_or_create(&self.path)?;
+        *identity = Some(loaded.clone());
+        Ok(loaded)
+    }
+
+    /// Starts pairing with the TV at `addr`, replacing any earlier attempt.
+    pub fn start(&self, addr: SocketAddr) -> Result<(), Box<dyn Error>> {
+        let session = PairingSession::start(addr, &self.identity()?)?;
+        self.sessions().insert(addr, session);
+        Ok(())
+    }
+
+    pub fn finish(&self, addr: SocketAddr, code: &str) -> Result<(), Box<dyn Error>> {
+        let mut sessions = self.sessions();
+        let session = sessions
+            .get_mut(&addr)
+            .ok_or_else(|| format!("No pairing in progress with {}", addr))?;
+        session.finish(code)?;
+        sessions.remove(&addr);
+        Ok(())
+    }
+
+    fn sessions(&self) -> MutexGuard<'_, HashMap<SocketAddr, PairingSession>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct VolumeLevel {
+    level: u32,
+    max: u32,
+    muted: bool,
+}
+
+/// What the TV has reported about itself since the connection opened.
+#[derive(Default)]
+struct RemoteState {
+    powered: Option<bool>,
+    volume: Option<VolumeLevel>,
+    foreground: Option<String>,
+    closed: Option<String>,
+}
+
+struct RemoteSession {
+    outgoing: Sender<RemoteMessage>,
+    state: Arc<Mutex<RemoteState>>,
+}
+
+impl RemoteSession {
+    fn open(addr: SocketAddr, identity: &RemoteIdentity) -> Result<Self, Box<dyn Error>> {
+        let mut stream = connect_tls(addr, identity)?;
+        let mut reader = FrameReader::new();
+        let state = Arc::new(Mutex::new(RemoteState::default()));
+        // The TV opens with its configuration and expects ours, then asks
+        // the client to become active. Unpaired clients are disconnected.
+        loop {
+            let frame = reader
+                .read_frame(&mut stream)
+                .map_err(|e| format!("Remote handshake with {} failed: {}", addr, e))?;
+            match RemoteMessage::decode(&frame)? {
+                RemoteMessage::Configure { .. } => send(
+                    &mut stream,
+                    RemoteMessage::Configure {
+                        code: REMOTE_FEATURES,
+                        device_info: Some(RemoteDeviceInfo {
+                            model: CLIENT_NAME.to_string(),
+                            vendor: CLIENT_NAME.to_string(),
+                            package_name: CLIENT_NAME.to_string(),
+                            app_version: env!("CARGO_PKG_VERSION").to_string(),
+                        }),
+                    },
+                )?,
+                RemoteMessage::SetActive(_) => {
+                    send(&mut stream, RemoteMessage::SetActive(REMOTE_FEATURES))?;
+                    break;
+                }
+                message => handle_message(&mut stream, &state, message)?,
+            }
+        }
+        stream.sock.set_read_timeout(Some(POLL_INTERVAL))?;
+        let (outgoing, receiver) = mpsc::channel();
+        let session_state = state.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = pump(&mut stream, &mut reader, &receiver, &session_state) {
+                session_state
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .closed = Some(e.to_string());
+            }
+        });
+        Ok(Self { outgoing, state })
+    }
+}
+
+fn send(stream: &mut TlsStream, message: RemoteMessage) -> io::Result<()> {
+    write_frame(stream, &message.encode())
+}
+
+// Forwards queued messages and applies the TV's updates until the transport
+// is dropped or the connection fails.
+fn pump(
+    stream: &mut TlsStream,
+    reader: &mut FrameReader,
+    outgoing: &Receiver<RemoteMessage>,
+    state: &Mutex<RemoteState>,
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        loop {
+            match outgoing.try_recv() {
+                Ok(message) => send(stream, message)?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+        match reader.read_frame(stream) {
+            Ok(frame) => handle_message(stream, state, RemoteMessage::decode(&frame)?)?,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn handle_message(
+    stream: &mut TlsStream,
+    state: &Mutex<RemoteState>,
+    message: RemoteMessage,
+) -> Result<(), Box<dyn Error>> {
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+    match message {
+        RemoteMessage::PingRequest(value) => send(stream, RemoteMessage::PingResponse(value))?,
+        RemoteMessage::Start(powered) => state.powered = Some(powered),
+        RemoteMessage::SetVolumeLevel { max, level, muted } => {
+            state.volume = Some(VolumeLevel { level, max, muted })
+        }
+        RemoteMessage::ImeKeyInject { app_package } if !app_package.is_empty() => {
+            state.foreground = Some(app_package)
+        }
+        RemoteMessage::Error => return Err("TV reported a remote protocol error".into()),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// A device controlled through the Android TV Remote service instead of ADB.
+/// The service has no shell, so the commands `ATVController` uses for keys,
+/// app launches, power and volume are translated into remote messages and
+/// answered from the state the TV reports. Anything else is rejected.
+pub struct RemoteTransport {
+    addr: SocketAddr,
+    identity: RemoteIdentity,
+    session: RemoteSession,
+}
+
+impl RemoteTransport {
+    pub fn connect(addr: SocketAddr, identity: &RemoteIdentity) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            addr,
+            identity: identity.clone(),
+            session: RemoteSession::open(addr, identity)?,
+        })
+    }
+
+    fn state(&self) -> adb_client::Result<MutexGuard<'_, RemoteState>> {
+        let state = self.session.state.lock().unwrap_or_else(|e| e.into_inner());
+        match &state.closed {
+            Some(reason) => Err(RustADBError::IOError(io::Error::new(
+                io::ErrorKind::NotConnected,
+                format!("Android TV Remote connection closed: {}", reason),
+            ))),
+            None => Ok(state),
+        }
+    }
+
+    fn send(&self, message: RemoteMessage) -> adb_client::Result<()> {
+        drop(self.state()?);
+        self.session.outgoing.send(message).map_err(|_| {
+            RustADBError::IOError(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "Android TV Remote connection closed",
+            ))
+        })
+    }
+
+    fn press(&self, key_code: u32, long_press: bool) -> adb_client::Result<()> {
+        let directions = if long_press {
+            vec![KeyDirection::StartLong, KeyDirection::EndLong]
+        } else {
+            vec![KeyDirection::Short]
+        };
+        for direction in directions {
+            self.send(RemoteMessage::KeyInject {
+                key_code,
+                direction,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn volume(&self) -> adb_client::Result<VolumeLevel> {
+        self.state()?.volume.ok_or_else(|| not_reported("volume"))
+    }
+
+    fn set_volume(&self, target: u32) -> adb_client::Result<()> {
+        // There is no absolute volume message, so step towards the target
+        // like a physical remote would.
+        let current = self.volume()?;
+        let key_code = if target > current.level { 24 } else { 25 };
+        for _ in 0..current.level.abs_diff(target) {
+            self.press(key_code, false)?;
+        }
+        let deadline = Instant::now() + VOLUME_SETTLE_TIMEOUT;
+        while self.volume()?.level != target && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    fn run(&mut self, command: &str) -> adb_client::Result<String> {
+        let command = command.trim();
+        if command == "echo ok" {
+            drop(self.state()?);
+            return Ok("ok\n".to_string());
+        }
+        if let Some(args) = command.strip_prefix("input keyevent ") {
+            let (long_press, key_code) = match args.strip_prefix("--longpress ") {
+                Some(key_code) => (true, key_code),
+                None => (false, args),
+            };
+            let key_code = key_code.trim().parse().map_err(|_| unsupported(command))?;
+            self.press(key_code, long_press)?;
+            return Ok(String::new());
+        }
+        if let Some(args) = command.strip_prefix("monkey -p ") {
+            let package = args.split_whitespace().next().unwrap_or_default();
+            self.send(RemoteMessage::AppLinkLaunch(format!(
+                "{}{}",
+                APP_LINK_PREFIX, package
+            )))?;
+            return Ok("Events injected: 1\n".to_string());
+        }
+        if command.starts_with("dumpsys power") {
+            let powered = self.state()?.powered.ok_or_else(|| not_reported("power"))?;
+            return Ok(if powered {
+                "mWakefulness=Awake\nDisplay Power: state=ON\n".to_string()
+            } else {
+                "mWakefulness=Asleep\nDisplay Power: state=OFF\n".to_string()
+            });
+        }
+        if let Some(args) = command.strip_prefix("cmd media_session volume --stream ") {
+            let args: Vec<_> = args.split_whitespace().collect();
+            match args.as_slice() {
+                [MUSIC_STREAM, "--get"] => {
+                    let volume = self.volume()?;
+                    return Ok(format!(
+                        "volume is {} in range [0..{}]\n",
+                        volume.level, volume.max
+                    ));
+                }
+                [MUSIC_STREAM, "--set", level] => {
+                    let level = level.parse().map_err(|_| unsupported(command))?;
+                    self.set_volume(level)?;
+                    return Ok(String::new());
+                }
+                _ => return Err(unsupported(command)),
+            }
+        }
+        if command.starts_with("dumpsys audio") {
+            return Ok(format!(
+                "- STREAM_MUSIC:\n   Muted: {}\n",
+                self.volume()?.muted
+            ));
+        }
+        if command.starts_with("dumpsys window") || command.starts_with("dumpsys activity") {
+            // Only the package is known, which leaves the activity empty.
+            return Ok(match &self.state()?.foreground {
+                Some(package) => format!("  mCurrentFocus=Window{{0 u0 {}/}}\n", package),
+                None => String::new(),
+            });
+        }
+        Err(unsupported(command))
+    }
+}
+
+fn unsupported(command: &str) -> RustADBError {
+    RustADBError::ADBRequestFailed(format!(
+        "'{}' is not supported over the Android TV Remote protocol",
+        command
+    ))
+}
+
+fn not_reported(what: &str) -> RustADBError {
+    RustADBError::ADBRequestFailed(format!("TV has not reported its {} state yet", what))
+}
+
+impl DeviceTransport for RemoteTransport {
+    fn shell_command(&mut self, command: &str, output: &mut dyn Write) -> adb_client::Result<()> {
+        let reply = self.run(command)?;
+        output.write_all(reply.as_bytes())?;
+        Ok(())
+    }
+
+    fn exec(
+        &mut self,
+        command: &str,
+        _input: &mut dyn Read,
+        _output: Box<dyn Write + Send>,
+    ) -> adb_client::Result<()> {
+        Err(unsupported(command))
+    }
+
+    fn stat(&mut self, _path: &str) -> adb_client::Result<AdbStatResponse> {
+        Err(unsupported("stat"))
+    }
+
+    fn list(&mut self, _path: &str) -> adb_client::Result<Vec<ADBListItemType>> {
+        Err(unsupported("list"))
+    }
+
+    fn pull(&mut self, _source: &str, _output: &mut dyn Write) -> adb_client::Result<()> {
+        Err(unsupported("pull"))
+    }
+
+    fn push(&mut self, _input: &mut dyn Read, _path: &str) -> adb_client::Result<()> {
+        Err(unsupported("push"))
+    }
+
+    fn reboot(&mut self, _reboot_type: RebootType) -> adb_client::Result<()> {
+        Err(unsupported("reboot"))
+    }
+
+    fn reconnect(&mut self) -> adb_client::Result<()> {
+        self.session = RemoteSession::open(self.addr, &self.identity)
+            .map_err(|e| RustADBError::ADBRequestFailed(e.to_string()))?;
+        Ok(())
+    }
+
+    fn open_connection(&self) -> adb_client::Result<Box<dyn DeviceTransport>> {
+        let transport = Self::connect(self.addr, &self.identity)
+            .map_err(|e| RustADBError::ADBRequestFailed(e.to_string()))?;
+        Ok(Box::new(transport))
+    }
+}
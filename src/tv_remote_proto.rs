@@ -0,0 +1,506 @@
+use std::error::Error;
+use std::io::{self, Read, Write};
+
+// Both the pairing service (port 6467) and the remote service (port 6466) of
+// the Android TV Remote protocol v2 exchange protobuf messages, each prefixed
+// with its varint-encoded length. Only the messages atvmate uses are
+// modelled; unknown fields are skipped.
+
+const PROTOCOL_VERSION: u64 = 2;
+const STATUS_OK: u64 = 200;
+const MAX_FRAME_LEN: usize = 64 * 1024;
+
+pub const ENCODING_HEXADECIMAL: u64 = 3;
+pub const ROLE_INPUT: u64 = 1;
+pub const CODE_LENGTH: u64 = 6;
+
+/// A pairing message together with its envelope (`OuterMessage`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PairingMessage {
+    Request {
+        service_name: String,
+        client_name: String,
+    },
+    RequestAck {
+        server_name: String,
+    },
+    Options {
+        encoding: u64,
+        symbol_length: u64,
+        preferred_role: u64,
+    },
+    Configuration {
+        encoding: u64,
+        symbol_length: u64,
+        client_role: u64,
+    },
+    ConfigurationAck,
+    Secret(Vec<u8>),
+    SecretAck(Vec<u8>),
+    /// Any reply whose status is not OK, e.g. 402 for a wrong secret.
+    Error(u64),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteDeviceInfo {
+    pub model: String,
+    pub vendor: String,
+    pub package_name: String,
+    pub app_version: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyDirection {
+    StartLong,
+    EndLong,
+    Short,
+}
+
+impl KeyDirection {
+    fn value(&self) -> u64 {
+        match self {
+            KeyDirection::StartLong => 1,
+            KeyDirection::EndLong => 2,
+            KeyDirection::Short => 3,
+        }
+    }
+
+    fn from_value(value: u64) -> Option<Self> {
+        match value {
+            1 => Some(KeyDirection::StartLong),
+            2 => Some(KeyDirection::EndLong),
+            3 => Some(KeyDirection::Short),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RemoteMessage {
+    Configure {
+        code: u64,
+        device_info: Option<RemoteDeviceInfo>,
+    },
+    SetActive(u64),
+    Error,
+    PingRequest(u64),
+    PingResponse(u64),
+    /// Key codes are Android `KeyEvent` codes.
+    KeyInject {
+        key_code: u32,
+        direction: KeyDirection,
+    },
+    /// Reports the app that owns the focused text field, i.e. the foreground app.
+    ImeKeyInject {
+        app_package: String,
+    },
+    /// Reports whether the screen is on.
+    Start(bool),
+    SetVolumeLevel {
+        max: u32,
+        level: u32,
+        muted: bool,
+    },
+    AppLinkLaunch(String),
+    /// A message this implementation has no use for.
+    Unknown(u32),
+}
+
+#[derive(Default)]
+struct Encoder {
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    fn varint(mut self, field: u32, value: u64) -> Self {
+        put_varint(&mut self.buffer, u64::from(field) << 3);
+        put_varint(&mut self.buffer, value);
+        self
+    }
+
+    fn bytes(mut self, field: u32, value: &[u8]) -> Self {
+        put_varint(&mut self.buffer, (u64::from(field) << 3) | 2);
+        put_varint(&mut self.buffer, value.len() as u64);
+        self.buffer.extend_from_slice(value);
+        self
+    }
+
+    fn string(self, field: u32, value: &str) -> Self {
+        self.bytes(field, value.as_bytes())
+    }
+
+    fn message(self, field: u32, value: Encoder) -> Self {
+        self.bytes(field, &value.buffer)
+    }
+}
+
+fn put_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn take_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = data.split_first()?;
+        *data = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+impl Value<'_> {
+    fn varint(&self) -> u64 {
+        match self {
+            Value::Varint(value) => *value,
+            Value::Bytes(_) => 0,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Value::Bytes(value) => value,
+            Value::Varint(_) => &[],
+        }
+    }
+
+    fn string(&self) -> String {
+        String::from_utf8_lossy(self.bytes()).into_owned()
+    }
+}
+
+fn decode_fields(mut data: &[u8]) -> Result<Vec<(u32, Value<'_>)>, Box<dyn Error>> {
+    let truncated = || "Truncated protobuf message".to_string();
+    let mut fields = Vec::new();
+    while !data.is_empty() {
+        let key = take_varint(&mut data).ok_or_else(truncated)?;
+        let field = (key >> 3) as u32;
+        let value = match key & 7 {
+            0 => Value::Varint(take_varint(&mut data).ok_or_else(truncated)?),
+            1 | 5 => {
+                let len = if key & 7 == 1 { 8 } else { 4 };
+                data = data.get(len..).ok_or_else(truncated)?;
+                continue;
+            }
+            2 => {
+                let len = take_varint(&mut data).ok_or_else(truncated)? as usize;
+                let value = data.get(..len).ok_or_else(truncated)?;
+                data = &data[len..];
+                Value::Bytes(value)
+            }
+            wire_type => return Err(format!("Unsupported protobuf wire type {}", wire_type).into()),
+        };
+        fields.push((field, value));
+    }
+    Ok(fields)
+}
+
+fn field<'a>(fields: &'a [(u32, Value<'a>)], number: u32) -> Option<&'a Value<'a>> {
+    fields
+        .iter()
+        .rev()
+        .find(|(field, _)| *field == number)
+        .map(|(_, value)| value)
+}
+
+fn varint_field(fields: &[(u32, Value<'_>)], number: u32) -> u64 {
+    field(fields, number).map_or(0, Value::varint)
+}
+
+fn string_field(fields: &[(u32, Value<'_>)], number: u32) -> String {
+    field(fields, number).map_or_else(String::new, Value::string)
+}
+
+fn encoding(encoding: u64, symbol_length: u64) -> Encoder {
+    Encoder::default()
+        .varint(1, encoding)
+        .varint(2, symbol_length)
+}
+
+fn decode_encoding(data: &[u8]) -> Result<(u64, u64), Box<dyn Error>> {
+    let fields = decode_fields(data)?;
+    Ok((varint_field(&fields, 1), varint_field(&fields, 2)))
+}
+
+impl PairingMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let status = match self {
+            PairingMessage::Error(status) => *status,
+            _ => STATUS_OK,
+        };
+        let outer = Encoder::default()
+            .varint(1, PROTOCOL_VERSION)
+            .varint(2, status);
+        let outer = match self {
+            PairingMessage::Request {
+                service_name,
+                client_name,
+            } => outer.message(
+                10,
+                Encoder::default()
+                    .string(1, service_name)
+                    .string(2, client_name),
+            ),
+            PairingMessage::RequestAck { server_name } => {
+                outer.message(11, Encoder::default().string(1, server_name))
+            }
+            PairingMessage::Options {
+                encoding: kind,
+                symbol_length,
+                preferred_role,
+            } => outer.message(
+                20,
+                Encoder::default()
+                    .message(1, encoding(*kind, *symbol_length))
+                    .varint(3, *preferred_role),
+            ),
+            PairingMessage::Configuration {
+                encoding: kind,
+                symbol_length,
+                client_role,
+            } => outer.message(
+                30,
+                Encoder::default()
+                    .message(1, encoding(*kind, *symbol_length))
+                    .varint(2, *client_role),
+            ),
+            PairingMessage::ConfigurationAck => outer.message(31, Encoder::default()),
+            PairingMessage::Secret(secret) => {
+                outer.message(40, Encoder::default().bytes(1, secret))
+            }
+            PairingMessage::SecretAck(secret) => {
+                outer.message(41, Encoder::default().bytes(1, secret))
+            }
+            PairingMessage::Error(_) => outer,
+        };
+        outer.buffer
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let fields = decode_fields(data)?;
+        let status = varint_field(&fields, 2);
+        if status != STATUS_OK {
+            return Ok(PairingMessage::Error(status));
+        }
+        let Some((number, value)) = fields.iter().find(|(number, _)| *number >= 10) else {
+            return Err("Pairing message has no payload".into());
+        };
+        let inner = decode_fields(value.bytes())?;
+        let message = match number {
+            10 => PairingMessage::Request {
+                service_name: string_field(&inner, 1),
+                client_name: string_field(&inner, 2),
+            },
+            11 => PairingMessage::RequestAck {
+                server_name: string_field(&inner, 1),
+            },
+            20 => {
+                // Both sides list a single encoding in practice.
+                let (encoding, symbol_length) = match field(&inner, 1).or(field(&inner, 2)) {
+                    Some(value) => decode_encoding(value.bytes())?,
+                    None => (0, 0),
+                };
+                PairingMessage::Options {
+                    encoding,
+                    symbol_length,
+                    preferred_role: varint_field(&inner, 3),
+                }
+            }
+            30 => {
+                let (encoding, symbol_length) =
+                    decode_encoding(field(&inner, 1).map_or(&[][..], Value::bytes))?;
+                PairingMessage::Configuration {
+                    encoding,
+                    symbol_length,
+                    client_role: varint_field(&inner, 2),
+                }
+            }
+            31 => PairingMessage::ConfigurationAck,
+            40 => PairingMessage::Secret(field(&inner, 1).map_or(&[][..], Value::bytes).to_vec()),
+            41 => {
+                PairingMessage::SecretAck(field(&inner, 1).map_or(&[][..], Value::bytes).to_vec())
+            }
+            number => return Err(format!("Unexpected pairing message {}", number).into()),
+        };
+        Ok(message)
+    }
+}
+
+impl RemoteMessage {
+    pub fn encode(&self) -> Vec<u8> {
+        let outer = Encoder::default();
+        let outer = match self {
+            RemoteMessage::Configure { code, device_info } => {
+                let mut inner = Encoder::default().varint(1, *code);
+                if let Some(info) = device_info {
+                    inner = inner.message(
+                        2,
+                        Encoder::default()
+                            .string(1, &info.model)
+                            .string(2, &info.vendor)
+                            .varint(3, 1)
+                            .string(4, "1")
+                            .string(5, &info.package_name)
+                            .string(6, &info.app_version),
+                    );
+                }
+                outer.message(1, inner)
+            }
+            RemoteMessage::SetActive(active) => {
+                outer.message(2, Encoder::default().varint(1, *active))
+            }
+            RemoteMessage::Error => outer.message(3, Encoder::default()),
+            RemoteMessage::PingRequest(value) => {
+                outer.message(8, Encoder::default().varint(1, *value))
+            }
+            RemoteMessage::PingResponse(value) => {
+                outer.message(9, Encoder::default().varint(1, *value))
+            }
+            RemoteMessage::KeyInject {
+                key_code,
+                direction,
+            } => outer.message(
+                10,
+                Encoder::default()
+                    .varint(1, u64::from(*key_code))
+                    .varint(2, direction.value()),
+            ),
+            RemoteMessage::ImeKeyInject { app_package } => outer.message(
+                20,
+                Encoder::default().message(1, Encoder::default().string(12, app_package)),
+            ),
+            RemoteMessage::Start(started) => {
+                outer.message(40, Encoder::default().varint(1, u64::from(*started)))
+            }
+            RemoteMessage::SetVolumeLevel { max, level, muted } => outer.message(
+                50,
+                Encoder::default()
+                    .varint(6, u64::from(*max))
+                    .varint(7, u64::from(*level))
+                    .varint(8, u64::from(*muted)),
+            ),
+            RemoteMessage::AppLinkLaunch(app_link) => {
+                outer.message(90, Encoder::default().string(1, app_link))
+            }
+            RemoteMessage::Unknown(number) => outer.message(*number, Encoder::default()),
+        };
+        outer.buffer
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let fields = decode_fields(data)?;
+        let Some((number, value)) = fields.first() else {
+            return Err("Remote message has no payload".into());
+        };
+        let inner = decode_fields(value.bytes())?;
+        let message = match number {
+            1 => RemoteMessage::Configure {
+                code: varint_field(&inner, 1),
+                device_info: match field(&inner, 2) {
+                    Some(info) => {
+                        let info = decode_fields(info.bytes())?;
+                        Some(RemoteDeviceInfo {
+                            model: string_field(&info, 1),
+                            vendor: string_field(&info, 2),
+                            package_name: string_field(&info, 5),
+                            app_version: string_field(&info, 6),
+                        })
+                    }
+                    None => None,
+                },
+            },
+            2 => RemoteMessage::SetActive(varint_field(&inner, 1)),
+            3 => RemoteMessage::Error,
+            8 => RemoteMessage::PingRequest(varint_field(&inner, 1)),
+            9 => RemoteMessage::PingResponse(varint_field(&inner, 1)),
+            10 => RemoteMessage::KeyInject {
+                key_code: varint_field(&inner, 1) as u32,
+                direction: KeyDirection::from_value(varint_field(&inner, 2))
+                    .ok_or("Unknown key direction")?,
+            },
+            20 => {
+                let app_info = field(&inner, 1).map_or(&[][..], Value::bytes);
+                RemoteMessage::ImeKeyInject {
+                    app_package: string_field(&decode_fields(app_info)?, 12),
+                }
+            }
+            40 => RemoteMessage::Start(varint_field(&inner, 1) != 0),
+            50 => RemoteMessage::SetVolumeLevel {
+                max: varint_field(&inner, 6) as u32,
+                level: varint_field(&inner, 7) as u32,
+                muted: varint_field(&inner, 8) != 0,
+            },
+            90 => RemoteMessage::AppLinkLaunch(string_field(&inner, 1)),
+            number => RemoteMessage::Unknown(*number),
+        };
+        Ok(message)
+    }
+}
+
+/// Splits a byte stream into length-prefixed frames. Bytes that arrive
+/// before a read times out are kept, so a caller polling with a short read
+/// timeout never loses half a message.
+#[derive(Default)]
+pub struct FrameReader {
+    buffer: Vec<u8>,
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read_frame(&mut self, stream: &mut impl Read) -> io::Result<Vec<u8>> {
+        loop {
+            if let Some(frame) = self.take_frame()? {
+                return Ok(frame);
+            }
+            let mut chunk = [0u8; 4096];
+            let read = stream.read(&mut chunk)?;
+            if read == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+
+    fn take_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut data = &self.buffer[..];
+        let Some(len) = take_varint(&mut data) else {
+            return Ok(None);
+        };
+        let len = len as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Frame of {} bytes is too large", len),
+            ));
+        }
+        if data.len() < len {
+            return Ok(None);
+        }
+        let frame = data[..len].to_vec();
+        let consumed = self.buffer.len() - data.len() + len;
+        self.buffer.drain(..consumed);
+        Ok(Some(frame))
+    }
+}
+
+pub fn write_frame(stream: &mut impl Write, message: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(message.len() + 2);
+    put_varint(&mut frame, message.len() as u64);
+    frame.extend_from_slice(message);
+    stream.write_all(&frame)?;
+    stream.flush()
+}
@@ -0,0 +1,75 @@
+use poem_openapi::{Enum, Object};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Enum)]
+#[serde(rename_all = "snake_case")]
+#[oai(rename_all = "snake_case")]
+pub enum VolumeStream {
+    #[default]
+    Music,
+    System,
+    Notification,
+}
+
+impl VolumeStream {
+    pub fn stream_id(&self) -> u32 {
+        match self {
+            VolumeStream::System => 1,
+            VolumeStream::Music => 3,
+            VolumeStream::Notification => 5,
+        }
+    }
+
+    fn dumpsys_name(&self) -> &'static str {
+        match self {
+            VolumeStream::Music => "STREAM_MUSIC",
+            VolumeStream::System => "STREAM_SYSTEM",
+            VolumeStream::Notification => "STREAM_NOTIFICATION",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct VolumeState {
+    pub stream: VolumeStream,
+    pub level: u32,
+    pub min: u32,
+    pub max: u32,
+    pub muted: bool,
+}
+
+// e.g. "volume is 7 in range [0..15]" from `cmd media_session volume --get`.
+pub fn parse_volume_range(output: &str) -> Option<(u32, u32, u32)> {
+    let rest = output.split("volume is ").nth(1)?;
+    let (level, rest) = rest.split_once(" in range [")?;
+    let (min, rest) = rest.split_once("..")?;
+    let max = rest.split(']').next()?;
+    Some((
+        level.trim().parse().ok()?,
+        min.trim().parse().ok()?,
+        max.trim().parse().ok()?,
+    ))
+}
+
+// Reads the stream's block in `dumpsys audio`:
+//   - STREAM_MUSIC:
+//      Muted: false          (Android 10+)
+//      Mute count: 0         (Android 9)
+pub fn parse_muted(output: &str, stream: VolumeStream) -> Option<bool> {
+    let header = format!("- {}:", stream.dumpsys_name());
+    let mut lines = output.lines().skip_while(|line| line.trim() != header);
+    lines.next()?;
+    for line in lines {
+        let line = line.trim();
+        if line.starts_with("- ") {
+            break;
+        }
+        if let Some(muted) = line.strip_prefix("Muted:") {
+            return Some(muted.trim() == "true");
+        }
+        if let Some(count) = line.strip_prefix("Mute count:") {
+            return count.trim().parse::<u32>().ok().map(|count| count > 0);
+        }
+    }
+    None
+}
@@ -0,0 +1,176 @@
+use crate::network_diagnostics::parse_links;
+use crate::storage::{load_json, save_json};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+pub const DEFAULT_WAKE_TIMEOUT: Duration = Duration::from_secs(60);
+pub const MAX_WAKE_TIMEOUT: Duration = Duration::from_secs(300);
+pub const WOL_PORT: u16 = 9;
+
+/// Interfaces first, then the MACs some vendors only expose as properties.
+pub const MAC_COMMAND: &str = "ip -o link show; echo @@; \
+    getprop ro.boot.ethernet_mac; getprop ro.boot.mac; getprop ro.boot.wifimacaddr";
+
+pub fn parse_mac(mac: &str) -> Result<[u8; 6], Box<dyn Error>> {
+    let bytes: Vec<u8> = mac
+        .trim()
+        .split([':', '-'])
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Invalid MAC address: {}", mac))?;
+    bytes
+        .try_into()
+        .map_err(|_| format!("Invalid MAC address: {}", mac).into())
+}
+
+// Android reports 02:00:00:00:00:00 when apps may not see the real address.
+fn is_usable(mac: &str) -> bool {
+    match parse_mac(mac) {
+        Ok(bytes) => bytes != [0; 6] && bytes != [2, 0, 0, 0, 0, 0] && bytes != [0xff; 6],
+        Err(_) => false,
+    }
+}
+
+/// Picks the address to wake the device by from the output of `MAC_COMMAND`.
+/// Wired interfaces win, since few Wi-Fi chips keep listening in deep standby.
+pub fn parse_mac_address(output: &str) -> Option<String> {
+    let mut sections = output.split("@@");
+    let links = parse_links(sections.next().unwrap_or(""));
+    let link_mac = |prefix: &str| {
+        links
+            .iter()
+            .filter(|link| link.name.starts_with(prefix))
+            .find_map(|link| link.mac.clone().filter(|mac| is_usable(mac)))
+    };
+    link_mac("eth")
+        .or_else(|| link_mac("wlan"))
+        .or_else(|| {
+            sections
+                .next()
+                .unwrap_or("")
+                .lines()
+                .map(str::trim)
+                .find(|mac| is_usable(mac))
+                .map(str::to_string)
+        })
+        .map(|mac| mac.to_lowercase())
+}
+
+/// Six 0xff bytes followed by the MAC repeated sixteen times.
+pub fn magic_packet(mac: [u8; 6]) -> Vec<u8> {
+    let mut packet = vec![0xff; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac);
+    }
+    packet
+}
+
+/// Reads `ATVMATE_WOL_BROADCAST`, either an address or an address and port.
+pub fn broadcast_from_env() -> Result<Option<SocketAddrV4>, Box<dyn Error>> {
+    match std::env::var("ATVMATE_WOL_BROADCAST") {
+        Ok(value) => parse_broadcast(&value).map(Some),
+        Err(_) => Ok(None),
+    }
+}
+
+pub fn parse_broadcast(value: &str) -> Result<SocketAddrV4, Box<dyn Error>> {
+    let value = value.trim();
+    if let Ok(addr) = value.parse::<SocketAddrV4>() {
+        return Ok(addr);
+    }
+    value
+        .parse::<Ipv4Addr>()
+        .map(|ip| SocketAddrV4::new(ip, WOL_PORT))
+        .map_err(|_| format!("Invalid broadcast address: {}", value).into())
+}
+
+/// MAC addresses learned from connected devices, kept across restarts
+/// because a device in deep standby can no longer be asked for it.
+pub struct WakeOnLan {
+    path: Option<PathBuf>,
+    macs: Mutex<BTreeMap<String, String>>,
+    broadcast: SocketAddrV4,
+}
+
+impl Default for WakeOnLan {
+    fn default() -> Self {
+        Self {
+            path: None,
+            macs: Mutex::new(BTreeMap::new()),
+            broadcast: SocketAddrV4::new(Ipv4Addr::BROADCAST, WOL_PORT),
+        }
+    }
+}
+
+impl WakeOnLan {
+    /// Keeps addresses in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let path = path.into();
+        let macs = load_json(&path)?;
+        Ok(Self {
+            path: Some(path),
+            macs: Mutex::new(macs),
+            ..Self::default()
+        })
+    }
+
+    pub fn with_broadcast(mut self, broadcast: SocketAddrV4) -> Self {
+        self.broadcast = broadcast;
+        self
+    }
+
+    pub fn mac_address(&self, device_id: &str) -> Option<String> {
+        self.macs().get(device_id).cloned()
+    }
+
+    pub fn record(&self, device_id: &str, mac: &str) -> Result<(), Box<dyn Error>> {
+        parse_mac(mac)?;
+        let mut macs = self.macs();
+        if macs.get(device_id).map(String::as_str) == Some(mac) {
+            return Ok(());
+        }
+        macs.insert(device_id.to_string(), mac.to_string());
+        match &self.path {
+            Some(path) => save_json(path, &*macs),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends a magic packet for the device to `broadcast`, or the configured
+    /// broadcast address. Devices added by IP also get a unicast copy, which
+    /// reaches them across subnets while the switch still knows their port.
+    /// Returns the MAC address the packet was for.
+    pub fn send(
+        &self,
+        device_id: &str,
+        broadcast: Option<SocketAddrV4>,
+    ) -> Result<String, Box<dyn Error>> {
+        let mac = self.mac_address(device_id).ok_or_else(|| {
+            format!(
+                "MAC address of {} is unknown; it is learned while the device is connected",
+                device_id
+            )
+        })?;
+        let packet = magic_packet(parse_mac(&mac)?);
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.send_to(&packet, broadcast.unwrap_or(self.broadcast))?;
+        if let Ok(ip) = device_id.parse::<Ipv4Addr>() {
+            // Best effort: without an ARP entry the kernel cannot deliver it.
+            let _ = socket.send_to(&packet, SocketAddrV4::new(ip, WOL_PORT));
+        }
+        Ok(mac)
+    }
+
+    fn macs(&self) -> MutexGuard<'_, BTreeMap<String, String>> {
+        self.macs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
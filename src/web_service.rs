@@ -1,8 +1,47 @@
+use crate::backup::{DeviceBackup, capture_backup, restore_backup};
+use crate::command::{
+    DeviceCommand, DeviceCommandResult, PowerState, broadcast_command, execute_command,
+};
+use crate::file_browser::{FileEntry, FileKind};
 use crate::global_device_manager::GlobalDeviceManager;
-use poem_openapi::{Object, OpenApi, param::Path as ApiPath, payload::Json};
+use crate::group_manager::{DeviceGroup, GroupManager};
+use crate::hdmi_cec::{CecAction, CecStatus};
+use crate::intent::IntentTarget;
+use crate::logcat::{
+    DEFAULT_DUMP_LINES, LogBuffer, LogRecord, LogcatFilter, compile_regex, follow_logcat,
+};
+use crate::macro_manager::{MacroDefinition, MacroManager, MacroStep};
+use crate::media_session::{MediaAction, MediaSession};
+use crate::network_diagnostics::{DEFAULT_PING_COUNT, NetworkCheck, NetworkInfo};
+use crate::power::PowerStatus;
+use crate::provisioning::{
+    DeviceProvisionReport, ProfileDocument, ProfileFormat, ProfileStore, ProfileSummary,
+    ProvisionStep, StepStatus, provision_devices,
+};
+use crate::reboot::{DEFAULT_REBOOT_TIMEOUT, MAX_REBOOT_TIMEOUT, RebootMode};
+use crate::resource_monitor::{ResourceMonitor, StatsSample};
+use crate::scheduler::{JobRun, ScheduleInfo, ScheduledAction, ScheduledJob, Scheduler};
+use crate::session_recorder::{RecordingStatus, Session, SessionStore, SessionSummary};
+use crate::settings::{Setting, SettingChange, SettingsNamespace, check_writable};
+use crate::tv_remote::{PAIRING_PORT, REMOTE_PORT, RemotePairing};
+use crate::volume::{VolumeState, VolumeStream};
+use crate::wake_on_lan::{DEFAULT_WAKE_TIMEOUT, MAX_WAKE_TIMEOUT, parse_broadcast};
+use crate::webhook_manager::{Webhook, WebhookDelivery, WebhookManager};
+use chrono::Local;
+use poem::Body;
+use poem_openapi::{
+    Object, OpenApi,
+    param::{Path, Query},
+    payload::{Binary, EventStream, Json},
+};
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddrV4;
+use std::net::{IpAddr, SocketAddr, SocketAddrV4};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::SyncIoBridge;
+
+const DEFAULT_FILES_PATH: &str = "/sdcard";
 
 #[derive(Serialize, Object)]
 struct DeviceList {
@@ -40,11 +79,395 @@ struct AddServerDeviceRequest {
     server_addr: Option<String>,
 }
 
+#[derive(Deserialize, Object)]
+struct RemotePairingRequest {
+    host: String,
+    /// Defaults to 6467.
+    port: Option<u16>,
+}
+
+#[derive(Deserialize, Object)]
+struct FinishRemotePairingRequest {
+    host: String,
+    port: Option<u16>,
+    /// The six characters shown on the TV.
+    code: String,
+}
+
+#[derive(Deserialize, Object)]
+struct AddRemoteDeviceRequest {
+    host: String,
+    /// Defaults to 6466.
+    port: Option<u16>,
+}
+
 #[derive(Deserialize, Object)]
 struct TextInputRequest {
     text: String,
 }
 
+#[derive(Serialize, Object)]
+struct MacroList {
+    macros: Vec<MacroDefinition>,
+}
+
+#[derive(Deserialize, Object)]
+struct SaveMacroRequest {
+    description: Option<String>,
+    steps: Vec<MacroStep>,
+}
+
+#[derive(Deserialize, Object)]
+struct StartRecordingRequest {
+    name: String,
+}
+
+#[derive(Serialize, Object)]
+struct RecordingResponse {
+    success: bool,
+    message: String,
+    recording: Option<RecordingStatus>,
+}
+
+#[derive(Serialize, Object)]
+struct SessionList {
+    sessions: Vec<SessionSummary>,
+}
+
+#[derive(Serialize, Object)]
+struct ProfileList {
+    profiles: Vec<ProfileSummary>,
+}
+
+#[derive(Serialize, Object)]
+struct ProfileResponse {
+    success: bool,
+    message: String,
+    profile: Option<ProfileDocument>,
+}
+
+#[derive(Deserialize, Object)]
+struct SaveProfileRequest {
+    format: ProfileFormat,
+    document: String,
+}
+
+#[derive(Serialize, Object)]
+struct ProvisionResponse {
+    success: bool,
+    message: String,
+    devices: Vec<DeviceProvisionReport>,
+}
+
+#[derive(Serialize, Object)]
+struct SessionResponse {
+    success: bool,
+    message: String,
+    session: Option<Session>,
+}
+
+#[derive(Deserialize, Object)]
+struct ReplaySessionRequest {
+    device_id: String,
+    /// 1 replays with the original timing, 2 twice as fast, and so on.
+    speed: Option<f64>,
+}
+
+#[derive(Deserialize, Object)]
+struct SessionToMacroRequest {
+    /// Defaults to the session name.
+    macro_name: Option<String>,
+    description: Option<String>,
+    speed: Option<f64>,
+}
+
+#[derive(Serialize, Object)]
+struct GroupList {
+    groups: Vec<DeviceGroup>,
+}
+
+#[derive(Deserialize, Object)]
+struct SaveGroupRequest {
+    devices: Vec<String>,
+}
+
+#[derive(Serialize, Object)]
+struct BroadcastResponse {
+    success: bool,
+    message: String,
+    results: Vec<DeviceCommandResult>,
+}
+
+#[derive(Serialize, Object)]
+struct ScheduleList {
+    schedules: Vec<ScheduleInfo>,
+}
+
+#[derive(Deserialize, Object)]
+struct SaveScheduleRequest {
+    cron: Option<String>,
+    run_at: Option<String>,
+    device_id: Option<String>,
+    group: Option<String>,
+    action: ScheduledAction,
+    enabled: Option<bool>,
+}
+
+#[derive(Serialize, Object)]
+struct ScheduleHistory {
+    success: bool,
+    message: String,
+    runs: Vec<JobRun>,
+}
+
+#[derive(Serialize, Object)]
+struct WebhookList {
+    webhooks: Vec<Webhook>,
+}
+
+#[derive(Deserialize, Object)]
+struct SaveWebhookRequest {
+    url: String,
+    events: Option<Vec<String>>,
+    secret: Option<String>,
+    enabled: Option<bool>,
+}
+
+#[derive(Serialize, Object)]
+struct WebhookDeliveryLog {
+    success: bool,
+    message: String,
+    deliveries: Vec<WebhookDelivery>,
+}
+
+#[derive(Serialize, Object)]
+struct ForegroundResponse {
+    success: bool,
+    message: String,
+    package: Option<String>,
+    activity: Option<String>,
+}
+
+#[derive(Serialize, Object)]
+struct MediaResponse {
+    success: bool,
+    message: String,
+    media_button_package: Option<String>,
+    sessions: Vec<MediaSession>,
+}
+
+#[derive(Deserialize, Object)]
+struct SetVolumeRequest {
+    stream: Option<VolumeStream>,
+    level: Option<u32>,
+    muted: Option<bool>,
+}
+
+#[derive(Serialize, Object)]
+struct VolumeResponse {
+    success: bool,
+    message: String,
+    volume: Option<VolumeState>,
+}
+
+#[derive(Serialize, Object)]
+struct PowerResponse {
+    success: bool,
+    message: String,
+    power: Option<PowerStatus>,
+}
+
+#[derive(Deserialize, Object)]
+struct CecSettingsRequest {
+    enabled: Option<bool>,
+    auto_wakeup: Option<bool>,
+    auto_device_off: Option<bool>,
+}
+
+#[derive(Serialize, Object)]
+struct CecResponse {
+    success: bool,
+    message: String,
+    cec: Option<CecStatus>,
+}
+
+#[derive(Serialize, Object)]
+struct FileList {
+    success: bool,
+    message: String,
+    entries: Vec<FileEntry>,
+}
+
+#[derive(Deserialize, Object)]
+struct MakeDirectoryRequest {
+    path: String,
+}
+
+#[derive(Deserialize, Object)]
+struct RenameRequest {
+    from: String,
+    to: String,
+}
+
+#[derive(poem_openapi::ApiResponse)]
+enum FileDownload {
+    #[oai(status = 200)]
+    Ok(Binary<Body>, #[oai(header = "Content-Disposition")] String),
+    #[oai(status = 404)]
+    NotFound(Json<ApiResponse>),
+    #[oai(status = 400)]
+    Failed(Json<ApiResponse>),
+}
+
+#[derive(poem_openapi::ApiResponse)]
+enum BackupDownload {
+    #[oai(status = 200)]
+    Ok(
+        Binary<Vec<u8>>,
+        #[oai(header = "Content-Disposition")] String,
+    ),
+    #[oai(status = 400)]
+    Failed(Json<ApiResponse>),
+}
+
+#[derive(Deserialize, Object)]
+struct BackupRequest {
+    /// Also pull the APKs of third-party apps so they can be reinstalled.
+    #[serde(default)]
+    #[oai(default)]
+    include_apks: bool,
+}
+
+#[derive(Serialize, Object)]
+struct RestoreResponse {
+    success: bool,
+    message: String,
+    steps: Vec<ProvisionStep>,
+}
+
+#[derive(Serialize, Object)]
+struct NetworkResponse {
+    success: bool,
+    message: String,
+    network: Option<NetworkInfo>,
+}
+
+#[derive(Deserialize, Object)]
+struct NetworkCheckRequest {
+    /// Host name or IP address to resolve and ping from the device.
+    target: String,
+    count: Option<u32>,
+}
+
+#[derive(Serialize, Object)]
+struct NetworkCheckResponse {
+    success: bool,
+    message: String,
+    check: Option<NetworkCheck>,
+}
+
+#[derive(Serialize, Object)]
+struct StatsResponse {
+    success: bool,
+    message: String,
+    current: Option<StatsSample>,
+    /// Recent samples, oldest first.
+    history: Vec<StatsSample>,
+}
+
+#[derive(Deserialize, Object)]
+struct RebootRequest {
+    #[serde(default)]
+    #[oai(default)]
+    mode: RebootMode,
+    /// Wait for a normal reboot to finish and the device to reconnect.
+    #[serde(default)]
+    #[oai(default)]
+    wait: bool,
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Serialize, Object)]
+struct RebootResponse {
+    success: bool,
+    message: String,
+    /// Seconds until the device was back online, when waiting.
+    elapsed_secs: Option<f64>,
+}
+
+#[derive(Deserialize, Object)]
+struct WakeOnLanRequest {
+    /// Where to send the packet, "address" or "address:port"; defaults to
+    /// the limited broadcast address on port 9.
+    broadcast: Option<String>,
+    /// Wait for the device to answer over ADB again.
+    #[serde(default)]
+    #[oai(default)]
+    wait: bool,
+    timeout_secs: Option<u64>,
+}
+
+#[derive(Serialize, Object)]
+struct WakeOnLanResponse {
+    success: bool,
+    message: String,
+    mac: Option<String>,
+    /// Seconds until the device was back online, when waiting.
+    elapsed_secs: Option<f64>,
+}
+
+#[derive(Serialize, Object)]
+struct SettingList {
+    success: bool,
+    message: String,
+    settings: Vec<Setting>,
+}
+
+#[derive(Serialize, Object)]
+struct SettingResponse {
+    success: bool,
+    message: String,
+    setting: Option<Setting>,
+}
+
+#[derive(Deserialize, Object)]
+struct PutSettingRequest {
+    value: String,
+    /// Allows writing keys that are not in the allow-list.
+    #[serde(default)]
+    #[oai(default)]
+    force: bool,
+}
+
+#[derive(Serialize, Object)]
+struct SettingChangeResponse {
+    success: bool,
+    message: String,
+    change: Option<SettingChange>,
+}
+
+#[derive(Serialize, Object)]
+struct SettingHistory {
+    success: bool,
+    message: String,
+    changes: Vec<SettingChange>,
+}
+
+#[derive(poem_openapi::ApiResponse)]
+enum LogcatStream {
+    #[oai(status = 200)]
+    Ok(EventStream<ReceiverStream<LogRecord>>),
+    #[oai(status = 400)]
+    Failed(Json<ApiResponse>),
+}
+
+#[derive(Serialize, Object)]
+struct LogcatDump {
+    success: bool,
+    message: String,
+    records: Vec<LogRecord>,
+}
+
 #[derive(Serialize, Object)]
 struct ApiResponse {
     success: bool,
@@ -53,11 +476,178 @@ struct ApiResponse {
 
 pub struct ApiService {
     device_manager: Arc<GlobalDeviceManager>,
+    macro_manager: Arc<MacroManager>,
+    group_manager: Arc<GroupManager>,
+    scheduler: Arc<Scheduler>,
+    webhook_manager: Arc<WebhookManager>,
+    resource_monitor: Arc<ResourceMonitor>,
+    session_store: Arc<SessionStore>,
+    remote_pairing: Arc<RemotePairing>,
+    profile_store: Arc<ProfileStore>,
 }
 
 impl ApiService {
-    pub fn new(device_manager: Arc<GlobalDeviceManager>) -> Self {
-        Self { device_manager }
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device_manager: Arc<GlobalDeviceManager>,
+        macro_manager: Arc<MacroManager>,
+        group_manager: Arc<GroupManager>,
+        scheduler: Arc<Scheduler>,
+        webhook_manager: Arc<WebhookManager>,
+        resource_monitor: Arc<ResourceMonitor>,
+        session_store: Arc<SessionStore>,
+        remote_pairing: Arc<RemotePairing>,
+        profile_store: Arc<ProfileStore>,
+    ) -> Self {
+        Self {
+            device_manager,
+            macro_manager,
+            group_manager,
+            scheduler,
+            webhook_manager,
+            resource_monitor,
+            session_store,
+            remote_pairing,
+            profile_store,
+        }
+    }
+
+    /// Plans or applies a stored profile on each device.
+    async fn provision(
+        &self,
+        name: &str,
+        device_ids: Vec<String>,
+        apply: bool,
+    ) -> Json<ProvisionResponse> {
+        let profile = match self.profile_store.load_profile(name) {
+            Ok(profile) => Arc::new(profile),
+            Err(e) => {
+                return Json(ProvisionResponse {
+                    success: false,
+                    message: e.to_string(),
+                    devices: Vec::new(),
+                });
+            }
+        };
+        let devices = provision_devices(
+            self.device_manager.clone(),
+            self.macro_manager.clone(),
+            profile,
+            self.profile_store.dir().to_path_buf(),
+            device_ids,
+            apply,
+        )
+        .await;
+        let succeeded = devices.iter().filter(|device| device.success).count();
+        let verb = if apply { "Applied" } else { "Planned" };
+        Json(ProvisionResponse {
+            success: succeeded == devices.len(),
+            message: format!(
+                "{} profile {}: {}/{} devices succeeded",
+                verb,
+                name,
+                succeeded,
+                devices.len()
+            ),
+            devices,
+        })
+    }
+
+    async fn provision_group(
+        &self,
+        group: &str,
+        name: &str,
+        apply: bool,
+    ) -> Json<ProvisionResponse> {
+        let device_ids = match self
+            .group_manager
+            .resolve_devices(group, &self.device_manager)
+        {
+            Ok(device_ids) => device_ids,
+            Err(e) => {
+                return Json(ProvisionResponse {
+                    success: false,
+                    message: format!("Failed to resolve group: {}", e),
+                    devices: Vec::new(),
+                });
+            }
+        };
+        self.provision(name, device_ids, apply).await
+    }
+
+    async fn broadcast(&self, group: &str, command: DeviceCommand) -> Json<BroadcastResponse> {
+        let device_ids = match self
+            .group_manager
+            .resolve_devices(group, &self.device_manager)
+        {
+            Ok(device_ids) => device_ids,
+            Err(e) => {
+                return Json(BroadcastResponse {
+                    success: false,
+                    message: format!("Failed to resolve group: {}", e),
+                    results: Vec::new(),
+                });
+            }
+        };
+
+        let description = command.describe();
+        let results = broadcast_command(
+            self.device_manager.clone(),
+            self.macro_manager.clone(),
+            device_ids,
+            command,
+        )
+        .await;
+        let succeeded = results.iter().filter(|result| result.success).count();
+        Json(BroadcastResponse {
+            success: succeeded == results.len(),
+            message: format!(
+                "Sent {} to group {}: {}/{} devices succeeded",
+                description,
+                group,
+                succeeded,
+                results.len()
+            ),
+            results,
+        })
+    }
+
+    fn logcat_filter(
+        &self,
+        device_id: &str,
+        buffers: Vec<LogBuffer>,
+        specs: Vec<String>,
+        pid: Option<u32>,
+        package: Option<String>,
+        regex: Option<String>,
+    ) -> Result<LogcatFilter, String> {
+        let regex = regex
+            .as_deref()
+            .map(compile_regex)
+            .transpose()
+            .map_err(|e| e.to_string())?;
+        let pid = match (pid, package) {
+            (Some(_), Some(_)) => return Err("Use either pid or package, not both".to_string()),
+            (Some(pid), None) => Some(pid),
+            (None, Some(package)) => {
+                let controller = self
+                    .device_manager
+                    .get_controller(device_id)
+                    .map_err(|e| format!("Failed to get controller: {}", e))?;
+                let mut ctrl = controller.lock().unwrap();
+                let pid = ctrl
+                    .pidof(&package)
+                    .map_err(|e| format!("Failed to look up {}: {}", package, e))?;
+                Some(pid.ok_or_else(|| format!("Package {} is not running", package))?)
+            }
+            (None, None) => None,
+        };
+        Ok(LogcatFilter {
+            buffers,
+            specs,
+            pid,
+            regex,
+        })
     }
 }
 
@@ -71,6 +661,16 @@ fn parse_server_addr(server_addr: &Option<String>) -> Result<Option<SocketAddrV4
         .transpose()
 }
 
+fn parse_remote_addr(
+    host: &str,
+    port: Option<u16>,
+    default_port: u16,
+) -> Result<SocketAddr, String> {
+    host.parse::<IpAddr>()
+        .map(|ip| SocketAddr::new(ip, port.unwrap_or(default_port)))
+        .map_err(|e| format!("Invalid host '{}': {}", host, e))
+}
+
 #[OpenApi]
 impl ApiService {
     #[oai(path = "/devices", method = "get")]
@@ -151,59 +751,132 @@ impl ApiService {
         }
     }
 
-    #[oai(path = "/devices/:device_id", method = "delete")]
-    async fn remove_device(&self, device_id: ApiPath<String>) -> Json<ApiResponse> {
-        let device_id = device_id.0;
-        match self.device_manager.remove_device(&device_id) {
-            Ok(_) => Json(ApiResponse {
-                success: true,
-                message: format!("Device {} removed successfully", device_id),
-            }),
-            Err(e) => Json(ApiResponse {
-                success: false,
-                message: format!("Failed to remove device: {}", e),
-            }),
-        }
+    /// Connects to a TV's Android TV Remote service so it shows a pairing code.
+    #[oai(path = "/remote/pairing", method = "post")]
+    async fn start_remote_pairing(&self, body: Json<RemotePairingRequest>) -> Json<ApiResponse> {
+        let addr = match parse_remote_addr(&body.host, body.port, PAIRING_PORT) {
+            Ok(addr) => addr,
+            Err(e) => {
+                return Json(ApiResponse {
+                    success: false,
+                    message: e,
+                });
+            }
+        };
+        let remote_pairing = self.remote_pairing.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            remote_pairing.start(addr).map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Pairing task failed: {}", e)));
+
+        match result {
+            Ok(()) => Json(ApiResponse {
+                success: true,
+                message: format!("Enter the code shown on {}", body.host),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to start pairing: {}", e),
+            }),
+        }
     }
 
-    #[oai(path = "/devices/:device_id/key/:key_name", method = "post")]
-    async fn send_key(
+    #[oai(path = "/remote/pairing/finish", method = "post")]
+    async fn finish_remote_pairing(
         &self,
-        device_id: ApiPath<String>,
-        key_name: ApiPath<String>,
+        body: Json<FinishRemotePairingRequest>,
     ) -> Json<ApiResponse> {
+        let addr = match parse_remote_addr(&body.host, body.port, PAIRING_PORT) {
+            Ok(addr) => addr,
+            Err(e) => {
+                return Json(ApiResponse {
+                    success: false,
+                    message: e,
+                });
+            }
+        };
+        let remote_pairing = self.remote_pairing.clone();
+        let code = body.code.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            remote_pairing
+                .finish(addr, &code)
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Pairing task failed: {}", e)));
+
+        match result {
+            Ok(()) => Json(ApiResponse {
+                success: true,
+                message: format!("Paired with {}", body.host),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to pair: {}", e),
+            }),
+        }
+    }
+
+    /// Adds a paired TV that is controlled without ADB.
+    #[oai(path = "/devices/remote", method = "post")]
+    async fn add_remote_device(&self, body: Json<AddRemoteDeviceRequest>) -> Json<ApiResponse> {
+        let addr = match parse_remote_addr(&body.host, body.port, REMOTE_PORT) {
+            Ok(addr) => addr,
+            Err(e) => {
+                return Json(ApiResponse {
+                    success: false,
+                    message: e,
+                });
+            }
+        };
+        let remote_pairing = self.remote_pairing.clone();
+        let device_manager = self.device_manager.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let identity = remote_pairing.identity().map_err(|e| e.to_string())?;
+            device_manager
+                .add_remote_device(addr, &identity)
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Connect task failed: {}", e)));
+
+        match result {
+            Ok(device_id) => Json(ApiResponse {
+                success: true,
+                message: format!("Android TV Remote device {} added successfully", device_id),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to add Android TV Remote device: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id", method = "delete")]
+    async fn remove_device(&self, device_id: Path<String>) -> Json<ApiResponse> {
+        let device_id = device_id.0;
+        match self.device_manager.remove_device(&device_id) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Device {} removed successfully", device_id),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to remove device: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/key/:key_name", method = "post")]
+    async fn send_key(&self, device_id: Path<String>, key_name: Path<String>) -> Json<ApiResponse> {
         let device_id = device_id.0;
         let key_name = key_name.0;
 
         match self.device_manager.get_controller(&device_id) {
             Ok(controller) => {
                 let mut ctrl = controller.lock().unwrap();
-                let result = match key_name.as_str() {
-                    "home" => ctrl.home(),
-                    "back" => ctrl.back(),
-                    "volume_up" => ctrl.volume_up(),
-                    "volume_down" => ctrl.volume_down(),
-                    "volume_mute" => ctrl.volume_mute(),
-                    "menu" => ctrl.menu(),
-                    "recent_apps" => ctrl.recent_apps(),
-                    "dpad_up" => ctrl.dpad_up(),
-                    "dpad_down" => ctrl.dpad_down(),
-                    "dpad_left" => ctrl.dpad_left(),
-                    "dpad_right" => ctrl.dpad_right(),
-                    "dpad_center" => ctrl.dpad_center(),
-                    "play_pause" => ctrl.play_pause(),
-                    "stop" => ctrl.stop(),
-                    "next" => ctrl.next(),
-                    "previous" => ctrl.previous(),
-                    "enter" => ctrl.enter(),
-                    "space" => ctrl.space(),
-                    "backspace" => ctrl.backspace(),
-                    "tab" => ctrl.tab(),
-                    "power" => ctrl.power(),
-                    "sleep" => ctrl.sleep(),
-                    "wake_up" => ctrl.wake_up(),
-                    _ => Err(format!("Unknown key: {}", key_name).into()),
-                };
+                let result = ctrl.press_key(&key_name);
 
                 match result {
                     Ok(_) => Json(ApiResponse {
@@ -226,7 +899,7 @@ impl ApiService {
     #[oai(path = "/devices/:device_id/input/text", method = "post")]
     async fn send_text(
         &self,
-        device_id: ApiPath<String>,
+        device_id: Path<String>,
         body: Json<TextInputRequest>,
     ) -> Json<ApiResponse> {
         let device_id = device_id.0;
@@ -250,4 +923,1934 @@ impl ApiService {
             }),
         }
     }
+
+    #[oai(path = "/macros", method = "get")]
+    async fn list_macros(&self) -> Json<MacroList> {
+        let macros = self.macro_manager.list_macros();
+        Json(MacroList { macros })
+    }
+
+    #[oai(path = "/macros/:name", method = "put")]
+    async fn save_macro(
+        &self,
+        name: Path<String>,
+        body: Json<SaveMacroRequest>,
+    ) -> Json<ApiResponse> {
+        let name = name.0;
+        let body = body.0;
+        let definition = MacroDefinition {
+            name: name.clone(),
+            description: body.description,
+            steps: body.steps,
+        };
+        match self.macro_manager.save_macro(definition) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Macro {} saved successfully", name),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to save macro: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/macros/:name", method = "delete")]
+    async fn remove_macro(&self, name: Path<String>) -> Json<ApiResponse> {
+        let name = name.0;
+        match self.macro_manager.remove_macro(&name) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Macro {} removed successfully", name),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to remove macro: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/macros/:name/run", method = "post")]
+    async fn run_macro(&self, device_id: Path<String>, name: Path<String>) -> Json<ApiResponse> {
+        let device_id = device_id.0;
+        let name = name.0;
+        let controller = match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => controller,
+            Err(e) => {
+                return Json(ApiResponse {
+                    success: false,
+                    message: format!("Failed to get controller: {}", e),
+                });
+            }
+        };
+
+        match self
+            .macro_manager
+            .run_macro(controller, &device_id, &name)
+            .await
+        {
+            Ok(report) if report.cancelled => Json(ApiResponse {
+                success: false,
+                message: format!(
+                    "Macro {} cancelled on device {} after {}/{} steps",
+                    name, device_id, report.steps_completed, report.steps_total
+                ),
+            }),
+            Ok(report) => Json(ApiResponse {
+                success: true,
+                message: format!(
+                    "Macro {} completed on device {} ({} steps)",
+                    name, device_id, report.steps_completed
+                ),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to run macro: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/macros/:name/cancel", method = "post")]
+    async fn cancel_macro(&self, device_id: Path<String>, name: Path<String>) -> Json<ApiResponse> {
+        let device_id = device_id.0;
+        let name = name.0;
+        match self.macro_manager.cancel_macro(&device_id, &name) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!(
+                    "Macro {} cancellation requested on device {}",
+                    name, device_id
+                ),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to cancel macro: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/recording", method = "get")]
+    async fn recording_status(&self, device_id: Path<String>) -> Json<RecordingResponse> {
+        let device_id = device_id.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let ctrl = controller.lock().unwrap();
+                let recording = ctrl.recording_status();
+                Json(RecordingResponse {
+                    success: true,
+                    message: match &recording {
+                        Some(recording) => format!("Recording session {}", recording.name),
+                        None => "Not recording".to_string(),
+                    },
+                    recording,
+                })
+            }
+            Err(e) => Json(RecordingResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                recording: None,
+            }),
+        }
+    }
+
+    /// Records every key, text, tap, swipe and app launch sent to the device until stopped.
+    #[oai(path = "/devices/:device_id/recording/start", method = "post")]
+    async fn start_recording(
+        &self,
+        device_id: Path<String>,
+        body: Json<StartRecordingRequest>,
+    ) -> Json<RecordingResponse> {
+        let device_id = device_id.0;
+        let name = body.0.name;
+        if self.session_store.exists(&name) {
+            return Json(RecordingResponse {
+                success: false,
+                message: format!("Session {} already exists", name),
+                recording: None,
+            });
+        }
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.start_recording(&name) {
+                    Ok(_) => Json(RecordingResponse {
+                        success: true,
+                        message: format!("Recording session {} on {}", name, device_id),
+                        recording: ctrl.recording_status(),
+                    }),
+                    Err(e) => Json(RecordingResponse {
+                        success: false,
+                        message: format!("Failed to start recording: {}", e),
+                        recording: None,
+                    }),
+                }
+            }
+            Err(e) => Json(RecordingResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                recording: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/recording/stop", method = "post")]
+    async fn stop_recording(&self, device_id: Path<String>) -> Json<SessionResponse> {
+        let device_id = device_id.0;
+        let session = match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => controller.lock().unwrap().stop_recording(),
+            Err(e) => {
+                return Json(SessionResponse {
+                    success: false,
+                    message: format!("Failed to get controller: {}", e),
+                    session: None,
+                });
+            }
+        };
+        let Some(session) = session else {
+            return Json(SessionResponse {
+                success: false,
+                message: format!("Device {} is not recording", device_id),
+                session: None,
+            });
+        };
+        match self.session_store.save_session(&session) {
+            Ok(_) => Json(SessionResponse {
+                success: true,
+                message: format!(
+                    "Session {} saved with {} steps",
+                    session.name,
+                    session.steps.len()
+                ),
+                session: Some(session),
+            }),
+            Err(e) => Json(SessionResponse {
+                success: false,
+                message: format!("Failed to save session: {}", e),
+                session: Some(session),
+            }),
+        }
+    }
+
+    #[oai(path = "/sessions", method = "get")]
+    async fn list_sessions(&self) -> Json<SessionList> {
+        let sessions = self.session_store.list_sessions().unwrap_or_else(|e| {
+            eprintln!("Failed to list sessions: {}", e);
+            Vec::new()
+        });
+        Json(SessionList { sessions })
+    }
+
+    #[oai(path = "/sessions/:name", method = "get")]
+    async fn get_session(&self, name: Path<String>) -> Json<SessionResponse> {
+        match self.session_store.get_session(&name.0) {
+            Ok(session) => Json(SessionResponse {
+                success: true,
+                message: format!("Session {} has {} steps", session.name, session.steps.len()),
+                session: Some(session),
+            }),
+            Err(e) => Json(SessionResponse {
+                success: false,
+                message: format!("Failed to load session: {}", e),
+                session: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/sessions/:name", method = "delete")]
+    async fn remove_session(&self, name: Path<String>) -> Json<ApiResponse> {
+        let name = name.0;
+        match self.session_store.remove_session(&name) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Session {} removed successfully", name),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to remove session: {}", e),
+            }),
+        }
+    }
+
+    /// Replays a session as a macro run, so it can be cancelled like one.
+    #[oai(path = "/sessions/:name/replay", method = "post")]
+    async fn replay_session(
+        &self,
+        name: Path<String>,
+        body: Json<ReplaySessionRequest>,
+    ) -> Json<ApiResponse> {
+        let name = name.0;
+        let body = body.0;
+        let definition = match self
+            .session_store
+            .get_session(&name)
+            .and_then(|session| session.to_macro(&name, None, body.speed.unwrap_or(1.0)))
+        {
+            Ok(definition) => definition,
+            Err(e) => {
+                return Json(ApiResponse {
+                    success: false,
+                    message: format!("Failed to load session: {}", e),
+                });
+            }
+        };
+        let controller = match self.device_manager.get_controller(&body.device_id) {
+            Ok(controller) => controller,
+            Err(e) => {
+                return Json(ApiResponse {
+                    success: false,
+                    message: format!("Failed to get controller: {}", e),
+                });
+            }
+        };
+
+        match self
+            .macro_manager
+            .run_definition(controller, &body.device_id, &definition)
+            .await
+        {
+            Ok(report) if report.cancelled => Json(ApiResponse {
+                success: false,
+                message: format!(
+                    "Replay of {} cancelled on device {} after {}/{} steps",
+                    name, body.device_id, report.steps_completed, report.steps_total
+                ),
+            }),
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Replayed session {} on device {}", name, body.device_id),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to replay session: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/sessions/:name/macro", method = "post")]
+    async fn session_to_macro(
+        &self,
+        name: Path<String>,
+        body: Json<SessionToMacroRequest>,
+    ) -> Json<ApiResponse> {
+        let name = name.0;
+        let body = body.0;
+        let macro_name = body.macro_name.unwrap_or_else(|| name.clone());
+        let result = self
+            .session_store
+            .get_session(&name)
+            .and_then(|session| {
+                session.to_macro(&macro_name, body.description, body.speed.unwrap_or(1.0))
+            })
+            .and_then(|definition| self.macro_manager.save_macro(definition));
+        match result {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Session {} saved as macro {}", name, macro_name),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to convert session: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/apps/:package/launch", method = "post")]
+    async fn launch_app(
+        &self,
+        device_id: Path<String>,
+        package: Path<String>,
+    ) -> Json<ApiResponse> {
+        let device_id = device_id.0;
+        let package = package.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.launch_app(&package) {
+                    Ok(_) => Json(ApiResponse {
+                        success: true,
+                        message: format!("App {} launched on device {}", package, device_id),
+                    }),
+                    Err(e) => Json(ApiResponse {
+                        success: false,
+                        message: format!("Failed to launch app: {}", e),
+                    }),
+                }
+            }
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/foreground", method = "get")]
+    async fn foreground(&self, device_id: Path<String>) -> Json<ForegroundResponse> {
+        let device_id = device_id.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.foreground_app() {
+                    Ok(Some(app)) => Json(ForegroundResponse {
+                        success: true,
+                        message: format!("{} is in the foreground", app.package),
+                        package: Some(app.package),
+                        activity: app.activity,
+                    }),
+                    Ok(None) => Json(ForegroundResponse {
+                        success: true,
+                        message: "No app is in the foreground".to_string(),
+                        package: None,
+                        activity: None,
+                    }),
+                    Err(e) => Json(ForegroundResponse {
+                        success: false,
+                        message: format!("Failed to get foreground app: {}", e),
+                        package: None,
+                        activity: None,
+                    }),
+                }
+            }
+            Err(e) => Json(ForegroundResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                package: None,
+                activity: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/media", method = "get")]
+    async fn media(&self, device_id: Path<String>) -> Json<MediaResponse> {
+        let device_id = device_id.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.media_status() {
+                    Ok(status) => Json(MediaResponse {
+                        success: true,
+                        message: format!("{} media sessions found", status.sessions.len()),
+                        media_button_package: status.media_button_package,
+                        sessions: status.sessions,
+                    }),
+                    Err(e) => Json(MediaResponse {
+                        success: false,
+                        message: format!("Failed to get media sessions: {}", e),
+                        media_button_package: None,
+                        sessions: Vec::new(),
+                    }),
+                }
+            }
+            Err(e) => Json(MediaResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                media_button_package: None,
+                sessions: Vec::new(),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/media/:package/:action", method = "post")]
+    async fn media_control(
+        &self,
+        device_id: Path<String>,
+        package: Path<String>,
+        action: Path<MediaAction>,
+    ) -> Json<ApiResponse> {
+        let device_id = device_id.0;
+        let package = package.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.media_control(&package, action.0) {
+                    Ok(_) => Json(ApiResponse {
+                        success: true,
+                        message: format!(
+                            "Sent {:?} to {} on device {}",
+                            action.0, package, device_id
+                        ),
+                    }),
+                    Err(e) => Json(ApiResponse {
+                        success: false,
+                        message: format!("Failed to control media session: {}", e),
+                    }),
+                }
+            }
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/volume", method = "get")]
+    async fn get_volume(
+        &self,
+        device_id: Path<String>,
+        stream: Query<Option<VolumeStream>>,
+    ) -> Json<VolumeResponse> {
+        let device_id = device_id.0;
+        let stream = stream.0.unwrap_or_default();
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.volume(stream) {
+                    Ok(volume) => Json(VolumeResponse {
+                        success: true,
+                        message: format!("Volume is {} of {}", volume.level, volume.max),
+                        volume: Some(volume),
+                    }),
+                    Err(e) => Json(VolumeResponse {
+                        success: false,
+                        message: format!("Failed to get volume: {}", e),
+                        volume: None,
+                    }),
+                }
+            }
+            Err(e) => Json(VolumeResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                volume: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/volume", method = "put")]
+    async fn set_volume(
+        &self,
+        device_id: Path<String>,
+        body: Json<SetVolumeRequest>,
+    ) -> Json<VolumeResponse> {
+        let device_id = device_id.0;
+        let body = body.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.set_volume(body.stream.unwrap_or_default(), body.level, body.muted) {
+                    Ok(volume) => Json(VolumeResponse {
+                        success: true,
+                        message: format!("Volume set to {} of {}", volume.level, volume.max),
+                        volume: Some(volume),
+                    }),
+                    Err(e) => Json(VolumeResponse {
+                        success: false,
+                        message: format!("Failed to set volume: {}", e),
+                        volume: None,
+                    }),
+                }
+            }
+            Err(e) => Json(VolumeResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                volume: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/power", method = "get")]
+    async fn power_status(&self, device_id: Path<String>) -> Json<PowerResponse> {
+        let device_id = device_id.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.power_status() {
+                    Ok(power) => Json(PowerResponse {
+                        success: true,
+                        message: format!(
+                            "Device {} is {}",
+                            device_id,
+                            if power.awake { "on" } else { "off" }
+                        ),
+                        power: Some(power),
+                    }),
+                    Err(e) => Json(PowerResponse {
+                        success: false,
+                        message: format!("Failed to get power state: {}", e),
+                        power: None,
+                    }),
+                }
+            }
+            Err(e) => Json(PowerResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                power: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/power/:state", method = "post")]
+    async fn set_power(
+        &self,
+        device_id: Path<String>,
+        state: Path<PowerState>,
+    ) -> Json<ApiResponse> {
+        let command = DeviceCommand::Power(state.0);
+        match execute_command(
+            &self.device_manager,
+            &self.macro_manager,
+            &device_id.0,
+            &command,
+        )
+        .await
+        {
+            Ok(message) => Json(ApiResponse {
+                success: true,
+                message,
+            }),
+            Err(message) => Json(ApiResponse {
+                success: false,
+                message,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/reboot", method = "post")]
+    async fn reboot(
+        &self,
+        device_id: Path<String>,
+        body: Json<RebootRequest>,
+    ) -> Json<RebootResponse> {
+        let device_id = device_id.0;
+        let body = body.0;
+        if body.wait && !body.mode.returns_to_android() {
+            return Json(RebootResponse {
+                success: false,
+                message: format!("Cannot wait for a {} to finish", body.mode.describe()),
+                elapsed_secs: None,
+            });
+        }
+        let timeout = body
+            .timeout_secs
+            .map_or(DEFAULT_REBOOT_TIMEOUT, Duration::from_secs)
+            .min(MAX_REBOOT_TIMEOUT);
+
+        let device_manager = self.device_manager.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let rebooted_at = Instant::now();
+            device_manager
+                .reboot_device(&device_id, body.mode)
+                .map_err(|e| format!("Failed to {}: {}", body.mode.describe(), e))?;
+            if !body.wait {
+                return Ok(None);
+            }
+            device_manager
+                .wait_for_reboot(&device_id, rebooted_at, timeout)
+                .map(Some)
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Reboot task failed: {}", e)));
+
+        match result {
+            Ok(Some(elapsed)) => Json(RebootResponse {
+                success: true,
+                message: format!("Device is back online after {}s", elapsed.as_secs()),
+                elapsed_secs: Some(elapsed.as_secs_f64()),
+            }),
+            Ok(None) => Json(RebootResponse {
+                success: true,
+                message: format!("Sent {}", body.mode.describe()),
+                elapsed_secs: None,
+            }),
+            Err(message) => Json(RebootResponse {
+                success: false,
+                message,
+                elapsed_secs: None,
+            }),
+        }
+    }
+
+    /// Sends a Wake-on-LAN magic packet to a device whose MAC address was
+    /// learned while it was connected.
+    #[oai(path = "/devices/:device_id/wol", method = "post")]
+    async fn wake_on_lan(
+        &self,
+        device_id: Path<String>,
+        body: Json<WakeOnLanRequest>,
+    ) -> Json<WakeOnLanResponse> {
+        let device_id = device_id.0;
+        let body = body.0;
+        let broadcast = match body.broadcast.as_deref().map(parse_broadcast).transpose() {
+            Ok(broadcast) => broadcast,
+            Err(e) => {
+                return Json(WakeOnLanResponse {
+                    success: false,
+                    message: e.to_string(),
+                    mac: None,
+                    elapsed_secs: None,
+                });
+            }
+        };
+        let timeout = body
+            .timeout_secs
+            .map_or(DEFAULT_WAKE_TIMEOUT, Duration::from_secs)
+            .min(MAX_WAKE_TIMEOUT);
+
+        let device_manager = self.device_manager.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mac = device_manager
+                .wake_on_lan()
+                .send(&device_id, broadcast)
+                .map_err(|e| e.to_string())?;
+            if !body.wait {
+                return Ok((mac, None));
+            }
+            device_manager
+                .wait_for_adb(&device_id, timeout)
+                .map(|elapsed| (mac, Some(elapsed)))
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Wake-on-LAN task failed: {}", e)));
+
+        match result {
+            Ok((mac, Some(elapsed))) => Json(WakeOnLanResponse {
+                success: true,
+                message: format!("Device is back online after {}s", elapsed.as_secs()),
+                mac: Some(mac),
+                elapsed_secs: Some(elapsed.as_secs_f64()),
+            }),
+            Ok((mac, None)) => Json(WakeOnLanResponse {
+                success: true,
+                message: format!("Sent magic packet to {}", mac),
+                mac: Some(mac),
+                elapsed_secs: None,
+            }),
+            Err(message) => Json(WakeOnLanResponse {
+                success: false,
+                message,
+                mac: None,
+                elapsed_secs: None,
+            }),
+        }
+    }
+
+    /// Resource usage history; a sample is taken now when there is none yet or `refresh` is set.
+    #[oai(path = "/devices/:device_id/stats", method = "get")]
+    async fn device_stats(
+        &self,
+        device_id: Path<String>,
+        limit: Query<Option<u32>>,
+        refresh: Query<Option<bool>>,
+    ) -> Json<StatsResponse> {
+        let device_id = device_id.0;
+        let limit = limit.0.unwrap_or(60) as usize;
+        let mut history = self.resource_monitor.history(&device_id, limit);
+        if history.is_empty() || refresh.0.unwrap_or(false) {
+            let resource_monitor = self.resource_monitor.clone();
+            let target = device_id.clone();
+            let sampled = tokio::task::spawn_blocking(move || {
+                resource_monitor
+                    .sample(&target, true)
+                    .map_err(|e| e.to_string())
+            })
+            .await
+            .unwrap_or_else(|e| Err(format!("Stats task failed: {}", e)));
+            if let Err(e) = sampled {
+                return Json(StatsResponse {
+                    success: false,
+                    message: format!("Failed to collect stats: {}", e),
+                    current: None,
+                    history,
+                });
+            }
+            history = self.resource_monitor.history(&device_id, limit);
+        }
+        Json(StatsResponse {
+            success: true,
+            message: format!("{} samples", history.len()),
+            current: history.last().cloned(),
+            history,
+        })
+    }
+
+    #[oai(path = "/devices/:device_id/network", method = "get")]
+    async fn network_info(&self, device_id: Path<String>) -> Json<NetworkResponse> {
+        let device_id = device_id.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.network_info() {
+                    Ok(network) => {
+                        let message = match &network.wifi {
+                            Some(wifi) if wifi.ssid.is_some() => format!(
+                                "Connected to {} at {} dBm",
+                                wifi.ssid.as_deref().unwrap_or_default(),
+                                wifi.rssi.map_or("?".to_string(), |rssi| rssi.to_string())
+                            ),
+                            _ if network.ethernet.as_ref().is_some_and(|eth| eth.carrier) => {
+                                "Connected over Ethernet".to_string()
+                            }
+                            _ => "No active Wi-Fi or Ethernet link".to_string(),
+                        };
+                        Json(NetworkResponse {
+                            success: true,
+                            message,
+                            network: Some(network),
+                        })
+                    }
+                    Err(e) => Json(NetworkResponse {
+                        success: false,
+                        message: format!("Failed to read network state: {}", e),
+                        network: None,
+                    }),
+                }
+            }
+            Err(e) => Json(NetworkResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                network: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/network/check", method = "post")]
+    async fn network_check(
+        &self,
+        device_id: Path<String>,
+        body: Json<NetworkCheckRequest>,
+    ) -> Json<NetworkCheckResponse> {
+        let device_id = device_id.0;
+        let body = body.0;
+        let controller = match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => controller,
+            Err(e) => {
+                return Json(NetworkCheckResponse {
+                    success: false,
+                    message: format!("Failed to get controller: {}", e),
+                    check: None,
+                });
+            }
+        };
+        // Pinging takes about a second per packet, so keep it off the async runtime.
+        let result = tokio::task::spawn_blocking(move || {
+            let mut ctrl = controller
+                .lock()
+                .map_err(|e| format!("Failed to lock controller: {}", e))?;
+            ctrl.network_check(&body.target, body.count.unwrap_or(DEFAULT_PING_COUNT))
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Network check task failed: {}", e)));
+
+        match result {
+            Ok(check) => {
+                let (success, message) = match (&check.ping, &check.resolved_address) {
+                    (_, None) => (false, format!("Could not resolve {}", check.target)),
+                    (Some(ping), Some(address)) if ping.received > 0 => (
+                        true,
+                        format!(
+                            "{} ({}) replied to {}/{} pings",
+                            check.target, address, ping.received, ping.transmitted
+                        ),
+                    ),
+                    (_, Some(address)) => (
+                        false,
+                        format!("{} resolved to {} but did not reply", check.target, address),
+                    ),
+                };
+                Json(NetworkCheckResponse {
+                    success,
+                    message,
+                    check: Some(check),
+                })
+            }
+            Err(e) => Json(NetworkCheckResponse {
+                success: false,
+                message: format!("Failed to run network check: {}", e),
+                check: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/cec", method = "get")]
+    async fn cec_status(&self, device_id: Path<String>) -> Json<CecResponse> {
+        let device_id = device_id.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.cec_status() {
+                    Ok(cec) => Json(CecResponse {
+                        success: true,
+                        message: format!("TV power status is {:?}", cec.tv_power),
+                        cec: Some(cec),
+                    }),
+                    Err(e) => Json(CecResponse {
+                        success: false,
+                        message: format!("Failed to get CEC status: {}", e),
+                        cec: None,
+                    }),
+                }
+            }
+            Err(e) => Json(CecResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                cec: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/cec", method = "put")]
+    async fn set_cec_settings(
+        &self,
+        device_id: Path<String>,
+        body: Json<CecSettingsRequest>,
+    ) -> Json<CecResponse> {
+        let device_id = device_id.0;
+        let body = body.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.set_cec_settings(body.enabled, body.auto_wakeup, body.auto_device_off) {
+                    Ok(cec) => Json(CecResponse {
+                        success: true,
+                        message: "CEC settings updated".to_string(),
+                        cec: Some(cec),
+                    }),
+                    Err(e) => Json(CecResponse {
+                        success: false,
+                        message: format!("Failed to update CEC settings: {}", e),
+                        cec: None,
+                    }),
+                }
+            }
+            Err(e) => Json(CecResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                cec: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/cec/:action", method = "post")]
+    async fn cec_action(
+        &self,
+        device_id: Path<String>,
+        action: Path<CecAction>,
+    ) -> Json<ApiResponse> {
+        let device_id = device_id.0;
+        let controller = match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => controller,
+            Err(e) => {
+                return Json(ApiResponse {
+                    success: false,
+                    message: format!("Failed to get controller: {}", e),
+                });
+            }
+        };
+        // Turning the TV on waits for the box to wake up, so keep it off the async runtime.
+        let result = tokio::task::spawn_blocking(move || {
+            let mut ctrl = controller
+                .lock()
+                .map_err(|e| format!("Failed to lock controller: {}", e))?;
+            ctrl.cec_action(action.0).map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("CEC task failed: {}", e)));
+        match result {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Sent CEC {:?} from device {}", action.0, device_id),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to send CEC command: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/intents", method = "post")]
+    async fn send_intent(
+        &self,
+        device_id: Path<String>,
+        body: Json<IntentTarget>,
+    ) -> Json<ApiResponse> {
+        let device_id = device_id.0;
+        let intent = match body.0.into_request() {
+            Ok(intent) => intent,
+            Err(e) => {
+                return Json(ApiResponse {
+                    success: false,
+                    message: format!("Invalid intent: {}", e),
+                });
+            }
+        };
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.send_intent(&intent) {
+                    Ok(output) => Json(ApiResponse {
+                        success: true,
+                        message: output,
+                    }),
+                    Err(e) => Json(ApiResponse {
+                        success: false,
+                        message: format!("Failed to send intent: {}", e),
+                    }),
+                }
+            }
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/files", method = "get")]
+    async fn list_files(
+        &self,
+        device_id: Path<String>,
+        path: Query<Option<String>>,
+    ) -> Json<FileList> {
+        let device_id = device_id.0;
+        let path = path.0.unwrap_or_else(|| DEFAULT_FILES_PATH.to_string());
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.list_files(&path) {
+                    Ok(entries) => Json(FileList {
+                        success: true,
+                        message: format!("{} entries in {}", entries.len(), path),
+                        entries,
+                    }),
+                    Err(e) => Json(FileList {
+                        success: false,
+                        message: format!("Failed to list files: {}", e),
+                        entries: Vec::new(),
+                    }),
+                }
+            }
+            Err(e) => Json(FileList {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                entries: Vec::new(),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/files", method = "delete")]
+    async fn delete_file(
+        &self,
+        device_id: Path<String>,
+        path: Query<String>,
+        recursive: Query<Option<bool>>,
+    ) -> Json<ApiResponse> {
+        let device_id = device_id.0;
+        let path = path.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.delete_path(&path, recursive.0.unwrap_or(false)) {
+                    Ok(_) => Json(ApiResponse {
+                        success: true,
+                        message: format!("Deleted {}", path),
+                    }),
+                    Err(e) => Json(ApiResponse {
+                        success: false,
+                        message: format!("Failed to delete {}: {}", path, e),
+                    }),
+                }
+            }
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/files/content", method = "get")]
+    async fn download_file(&self, device_id: Path<String>, path: Query<String>) -> FileDownload {
+        let path = path.0;
+        let failed = |message: String| {
+            FileDownload::Failed(Json(ApiResponse {
+                success: false,
+                message,
+            }))
+        };
+        let controller = match self.device_manager.get_controller(&device_id.0) {
+            Ok(controller) => controller,
+            Err(e) => return failed(format!("Failed to get controller: {}", e)),
+        };
+        let entry = controller.lock().unwrap().stat_file(&path);
+        let entry = match entry {
+            Ok(Some(entry)) if entry.kind == FileKind::Directory => {
+                return failed(format!("{} is a directory", path));
+            }
+            Ok(Some(entry)) => entry,
+            Ok(None) => {
+                return FileDownload::NotFound(Json(ApiResponse {
+                    success: false,
+                    message: format!("Path {} not found", path),
+                }));
+            }
+            Err(e) => return failed(format!("Failed to read {}: {}", path, e)),
+        };
+
+        // The sync protocol is blocking, so pull on a worker thread and pipe
+        // the bytes into the response as they arrive.
+        let (reader, writer) = tokio::io::duplex(64 * 1024);
+        let mut writer = SyncIoBridge::new(writer);
+        tokio::task::spawn_blocking(move || {
+            let result = match controller.lock() {
+                Ok(mut ctrl) => ctrl.pull_file(&path, &mut writer),
+                Err(e) => Err(format!("Failed to lock controller: {}", e).into()),
+            };
+            if let Err(e) = result {
+                eprintln!("Failed to pull {}: {}", path, e);
+            }
+        });
+        let disposition = format!(
+            "attachment; filename=\"{}\"",
+            entry.name.replace(['"', '\\'], "_")
+        );
+        FileDownload::Ok(Binary(Body::from_async_read(reader)), disposition)
+    }
+
+    #[oai(path = "/devices/:device_id/files/content", method = "put")]
+    async fn upload_file(
+        &self,
+        device_id: Path<String>,
+        path: Query<String>,
+        body: Binary<Body>,
+    ) -> Json<ApiResponse> {
+        let path = path.0;
+        let controller = match self.device_manager.get_controller(&device_id.0) {
+            Ok(controller) => controller,
+            Err(e) => {
+                return Json(ApiResponse {
+                    success: false,
+                    message: format!("Failed to get controller: {}", e),
+                });
+            }
+        };
+        let mut reader = SyncIoBridge::new(body.0.into_async_read());
+        let target = path.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut ctrl = controller
+                .lock()
+                .map_err(|e| format!("Failed to lock controller: {}", e))?;
+            ctrl.push_file(&mut reader, &target)
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Upload task failed: {}", e)));
+        match result {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Uploaded {}", path),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to upload {}: {}", path, e),
+            }),
+        }
+    }
+
+    /// Captures apps, their enabled state, key settings, the launcher and the
+    /// wallpaper into an archive that `restore` can apply to any device.
+    #[oai(path = "/devices/:device_id/backup", method = "post")]
+    async fn backup(&self, device_id: Path<String>, body: Json<BackupRequest>) -> BackupDownload {
+        let device_id = device_id.0;
+        let controller = match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => controller,
+            Err(e) => {
+                return BackupDownload::Failed(Json(ApiResponse {
+                    success: false,
+                    message: format!("Failed to get controller: {}", e),
+                }));
+            }
+        };
+        let include_apks = body.0.include_apks;
+        let result = tokio::task::spawn_blocking(move || {
+            let mut ctrl = controller
+                .lock()
+                .map_err(|e| format!("Failed to lock controller: {}", e))?;
+            capture_backup(&mut ctrl, include_apks)
+                .and_then(|backup| backup.to_archive())
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Backup task failed: {}", e)));
+        match result {
+            Ok(archive) => {
+                let disposition = format!(
+                    "attachment; filename=\"{}-{}.atvbackup.json.gz\"",
+                    device_id.replace(|c: char| !c.is_ascii_alphanumeric() && c != '.', "_"),
+                    Local::now().format("%Y%m%d-%H%M%S")
+                );
+                BackupDownload::Ok(Binary(archive), disposition)
+            }
+            Err(message) => BackupDownload::Failed(Json(ApiResponse {
+                success: false,
+                message: format!("Failed to back up {}: {}", device_id, message),
+            })),
+        }
+    }
+
+    /// Reapplies an archive from `backup`, reinstalling apps when it includes
+    /// their APKs.
+    #[oai(path = "/devices/:device_id/restore", method = "post")]
+    async fn restore(
+        &self,
+        device_id: Path<String>,
+        body: Binary<Vec<u8>>,
+    ) -> Json<RestoreResponse> {
+        let failed = |message: String| {
+            Json(RestoreResponse {
+                success: false,
+                message,
+                steps: Vec::new(),
+            })
+        };
+        let backup = match DeviceBackup::from_archive(&body.0) {
+            Ok(backup) => backup,
+            Err(e) => return failed(e.to_string()),
+        };
+        let controller = match self.device_manager.get_controller(&device_id.0) {
+            Ok(controller) => controller,
+            Err(e) => return failed(format!("Failed to get controller: {}", e)),
+        };
+        let source = backup.device_id.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut ctrl = controller
+                .lock()
+                .map_err(|e| format!("Failed to lock controller: {}", e))?;
+            restore_backup(&mut ctrl, &backup).map_err(|e| e.to_string())
+        })
+        .await
+        .unwrap_or_else(|e| Err(format!("Restore task failed: {}", e)));
+        let steps = match result {
+            Ok(steps) => steps,
+            Err(message) => return failed(format!("Failed to restore: {}", message)),
+        };
+        let count = |status: StepStatus| steps.iter().filter(|step| step.status == status).count();
+        let failures = count(StepStatus::Failed);
+        Json(RestoreResponse {
+            success: failures == 0,
+            message: format!(
+                "Restored backup of {}: {} applied, {} unchanged, {} failed",
+                source,
+                count(StepStatus::Applied),
+                count(StepStatus::Unchanged),
+                failures
+            ),
+            steps,
+        })
+    }
+
+    #[oai(path = "/devices/:device_id/files/mkdir", method = "post")]
+    async fn make_directory(
+        &self,
+        device_id: Path<String>,
+        body: Json<MakeDirectoryRequest>,
+    ) -> Json<ApiResponse> {
+        let device_id = device_id.0;
+        let path = body.0.path;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.make_directory(&path) {
+                    Ok(_) => Json(ApiResponse {
+                        success: true,
+                        message: format!("Created {}", path),
+                    }),
+                    Err(e) => Json(ApiResponse {
+                        success: false,
+                        message: format!("Failed to create {}: {}", path, e),
+                    }),
+                }
+            }
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/files/rename", method = "post")]
+    async fn rename_file(
+        &self,
+        device_id: Path<String>,
+        body: Json<RenameRequest>,
+    ) -> Json<ApiResponse> {
+        let device_id = device_id.0;
+        let body = body.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.rename_path(&body.from, &body.to) {
+                    Ok(_) => Json(ApiResponse {
+                        success: true,
+                        message: format!("Renamed {} to {}", body.from, body.to),
+                    }),
+                    Err(e) => Json(ApiResponse {
+                        success: false,
+                        message: format!("Failed to rename {}: {}", body.from, e),
+                    }),
+                }
+            }
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+            }),
+        }
+    }
+
+    /// Streams `logcat -v threadtime` as server-sent events, one record per event.
+    #[oai(path = "/devices/:device_id/logcat", method = "get")]
+    async fn stream_logcat(
+        &self,
+        device_id: Path<String>,
+        buffer: Query<Vec<LogBuffer>>,
+        filter: Query<Vec<String>>,
+        pid: Query<Option<u32>>,
+        package: Query<Option<String>>,
+        regex: Query<Option<String>>,
+    ) -> LogcatStream {
+        let device_id = device_id.0;
+        let failed = |message: String| {
+            LogcatStream::Failed(Json(ApiResponse {
+                success: false,
+                message,
+            }))
+        };
+        let filter =
+            match self.logcat_filter(&device_id, buffer.0, filter.0, pid.0, package.0, regex.0) {
+                Ok(filter) => filter,
+                Err(e) => return failed(e),
+            };
+        let device = match self.device_manager.open_connection(&device_id) {
+            Ok(device) => device,
+            Err(e) => return failed(format!("Failed to open connection: {}", e)),
+        };
+        match follow_logcat(device, filter) {
+            Ok(receiver) => LogcatStream::Ok(
+                EventStream::new(ReceiverStream::new(receiver)).keep_alive(Duration::from_secs(15)),
+            ),
+            Err(e) => failed(format!("Failed to start logcat: {}", e)),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/logcat/dump", method = "get")]
+    #[allow(clippy::too_many_arguments)]
+    async fn dump_logcat(
+        &self,
+        device_id: Path<String>,
+        lines: Query<Option<u32>>,
+        buffer: Query<Vec<LogBuffer>>,
+        filter: Query<Vec<String>>,
+        pid: Query<Option<u32>>,
+        package: Query<Option<String>>,
+        regex: Query<Option<String>>,
+    ) -> Json<LogcatDump> {
+        let device_id = device_id.0;
+        let lines = lines.0.unwrap_or(DEFAULT_DUMP_LINES);
+        let filter =
+            match self.logcat_filter(&device_id, buffer.0, filter.0, pid.0, package.0, regex.0) {
+                Ok(filter) => filter,
+                Err(e) => {
+                    return Json(LogcatDump {
+                        success: false,
+                        message: e,
+                        records: Vec::new(),
+                    });
+                }
+            };
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.logcat_dump(&filter, lines) {
+                    Ok(records) => Json(LogcatDump {
+                        success: true,
+                        message: format!("{} log records", records.len()),
+                        records,
+                    }),
+                    Err(e) => Json(LogcatDump {
+                        success: false,
+                        message: format!("Failed to read logcat: {}", e),
+                        records: Vec::new(),
+                    }),
+                }
+            }
+            Err(e) => Json(LogcatDump {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                records: Vec::new(),
+            }),
+        }
+    }
+
+    /// Lists the allow-listed settings with their descriptions and current values.
+    #[oai(path = "/devices/:device_id/settings", method = "get")]
+    async fn known_settings(&self, device_id: Path<String>) -> Json<SettingList> {
+        let device_id = device_id.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.known_settings() {
+                    Ok(settings) => Json(SettingList {
+                        success: true,
+                        message: format!("{} known settings", settings.len()),
+                        settings,
+                    }),
+                    Err(e) => Json(SettingList {
+                        success: false,
+                        message: format!("Failed to read settings: {}", e),
+                        settings: Vec::new(),
+                    }),
+                }
+            }
+            Err(e) => Json(SettingList {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                settings: Vec::new(),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/settings/history", method = "get")]
+    async fn settings_history(&self, device_id: Path<String>) -> Json<SettingHistory> {
+        let device_id = device_id.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let ctrl = controller.lock().unwrap();
+                let changes = ctrl.settings_history().to_vec();
+                Json(SettingHistory {
+                    success: true,
+                    message: format!("{} recorded changes", changes.len()),
+                    changes,
+                })
+            }
+            Err(e) => Json(SettingHistory {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                changes: Vec::new(),
+            }),
+        }
+    }
+
+    /// Reverts the most recent settings change made through this API.
+    #[oai(path = "/devices/:device_id/settings/undo", method = "post")]
+    async fn undo_setting(&self, device_id: Path<String>) -> Json<SettingChangeResponse> {
+        let device_id = device_id.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.undo_setting_change() {
+                    Ok(Some(change)) => Json(SettingChangeResponse {
+                        success: true,
+                        message: format!(
+                            "Restored {} {} to {}",
+                            change.namespace.as_arg(),
+                            change.key,
+                            change.previous.as_deref().unwrap_or("unset")
+                        ),
+                        change: Some(change),
+                    }),
+                    Ok(None) => Json(SettingChangeResponse {
+                        success: false,
+                        message: "No settings changes to undo".to_string(),
+                        change: None,
+                    }),
+                    Err(e) => Json(SettingChangeResponse {
+                        success: false,
+                        message: format!("Failed to undo setting change: {}", e),
+                        change: None,
+                    }),
+                }
+            }
+            Err(e) => Json(SettingChangeResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                change: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/settings/:namespace", method = "get")]
+    async fn list_settings(
+        &self,
+        device_id: Path<String>,
+        namespace: Path<SettingsNamespace>,
+    ) -> Json<SettingList> {
+        let device_id = device_id.0;
+        let namespace = namespace.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.list_settings(namespace) {
+                    Ok(settings) => Json(SettingList {
+                        success: true,
+                        message: format!("{} settings in {}", settings.len(), namespace.as_arg()),
+                        settings,
+                    }),
+                    Err(e) => Json(SettingList {
+                        success: false,
+                        message: format!("Failed to list settings: {}", e),
+                        settings: Vec::new(),
+                    }),
+                }
+            }
+            Err(e) => Json(SettingList {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                settings: Vec::new(),
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/settings/:namespace/:key", method = "get")]
+    async fn get_setting(
+        &self,
+        device_id: Path<String>,
+        namespace: Path<SettingsNamespace>,
+        key: Path<String>,
+    ) -> Json<SettingResponse> {
+        let device_id = device_id.0;
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.get_setting(namespace.0, &key.0) {
+                    Ok(setting) => Json(SettingResponse {
+                        success: true,
+                        message: format!(
+                            "{} {} is {}",
+                            setting.namespace.as_arg(),
+                            setting.key,
+                            setting.value.as_deref().unwrap_or("unset")
+                        ),
+                        setting: Some(setting),
+                    }),
+                    Err(e) => Json(SettingResponse {
+                        success: false,
+                        message: format!("Failed to read setting: {}", e),
+                        setting: None,
+                    }),
+                }
+            }
+            Err(e) => Json(SettingResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                setting: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/devices/:device_id/settings/:namespace/:key", method = "put")]
+    async fn put_setting(
+        &self,
+        device_id: Path<String>,
+        namespace: Path<SettingsNamespace>,
+        key: Path<String>,
+        body: Json<PutSettingRequest>,
+    ) -> Json<SettingChangeResponse> {
+        let device_id = device_id.0;
+        let (namespace, key, body) = (namespace.0, key.0, body.0);
+        if let Err(e) = check_writable(namespace, &key, body.force) {
+            return Json(SettingChangeResponse {
+                success: false,
+                message: e.to_string(),
+                change: None,
+            });
+        }
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.put_setting(namespace, &key, &body.value) {
+                    Ok(change) => Json(SettingChangeResponse {
+                        success: true,
+                        message: format!("Set {} {} to {}", namespace.as_arg(), key, body.value),
+                        change: Some(change),
+                    }),
+                    Err(e) => Json(SettingChangeResponse {
+                        success: false,
+                        message: format!("Failed to write setting: {}", e),
+                        change: None,
+                    }),
+                }
+            }
+            Err(e) => Json(SettingChangeResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                change: None,
+            }),
+        }
+    }
+
+    #[oai(
+        path = "/devices/:device_id/settings/:namespace/:key",
+        method = "delete"
+    )]
+    async fn delete_setting(
+        &self,
+        device_id: Path<String>,
+        namespace: Path<SettingsNamespace>,
+        key: Path<String>,
+        force: Query<Option<bool>>,
+    ) -> Json<SettingChangeResponse> {
+        let device_id = device_id.0;
+        let (namespace, key) = (namespace.0, key.0);
+        if let Err(e) = check_writable(namespace, &key, force.0.unwrap_or(false)) {
+            return Json(SettingChangeResponse {
+                success: false,
+                message: e.to_string(),
+                change: None,
+            });
+        }
+        match self.device_manager.get_controller(&device_id) {
+            Ok(controller) => {
+                let mut ctrl = controller.lock().unwrap();
+                match ctrl.delete_setting(namespace, &key) {
+                    Ok(change) => Json(SettingChangeResponse {
+                        success: true,
+                        message: format!("Deleted {} {}", namespace.as_arg(), key),
+                        change: Some(change),
+                    }),
+                    Err(e) => Json(SettingChangeResponse {
+                        success: false,
+                        message: format!("Failed to delete setting: {}", e),
+                        change: None,
+                    }),
+                }
+            }
+            Err(e) => Json(SettingChangeResponse {
+                success: false,
+                message: format!("Failed to get controller: {}", e),
+                change: None,
+            }),
+        }
+    }
+
+    #[oai(path = "/groups", method = "get")]
+    async fn list_groups(&self) -> Json<GroupList> {
+        let groups = self.group_manager.list_groups();
+        Json(GroupList { groups })
+    }
+
+    #[oai(path = "/groups/:group", method = "put")]
+    async fn save_group(
+        &self,
+        group: Path<String>,
+        body: Json<SaveGroupRequest>,
+    ) -> Json<ApiResponse> {
+        let group = group.0;
+        let device_group = DeviceGroup {
+            name: group.clone(),
+            devices: body.0.devices,
+        };
+        match self.group_manager.save_group(device_group) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Group {} saved successfully", group),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to save group: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/groups/:group", method = "delete")]
+    async fn remove_group(&self, group: Path<String>) -> Json<ApiResponse> {
+        let group = group.0;
+        match self.group_manager.remove_group(&group) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Group {} removed successfully", group),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to remove group: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/groups/:group/key/:key_name", method = "post")]
+    async fn broadcast_key(
+        &self,
+        group: Path<String>,
+        key_name: Path<String>,
+    ) -> Json<BroadcastResponse> {
+        self.broadcast(&group.0, DeviceCommand::Key(key_name.0))
+            .await
+    }
+
+    #[oai(path = "/groups/:group/input/text", method = "post")]
+    async fn broadcast_text(
+        &self,
+        group: Path<String>,
+        body: Json<TextInputRequest>,
+    ) -> Json<BroadcastResponse> {
+        self.broadcast(&group.0, DeviceCommand::Text(body.0.text))
+            .await
+    }
+
+    #[oai(path = "/groups/:group/apps/:package/launch", method = "post")]
+    async fn broadcast_launch_app(
+        &self,
+        group: Path<String>,
+        package: Path<String>,
+    ) -> Json<BroadcastResponse> {
+        self.broadcast(&group.0, DeviceCommand::LaunchApp(package.0))
+            .await
+    }
+
+    #[oai(path = "/groups/:group/macros/:name/run", method = "post")]
+    async fn broadcast_macro(
+        &self,
+        group: Path<String>,
+        name: Path<String>,
+    ) -> Json<BroadcastResponse> {
+        self.broadcast(&group.0, DeviceCommand::Macro(name.0)).await
+    }
+
+    #[oai(path = "/profiles", method = "get")]
+    async fn list_profiles(&self) -> Json<ProfileList> {
+        let profiles = self.profile_store.list_profiles().unwrap_or_else(|e| {
+            eprintln!("Failed to list profiles: {}", e);
+            Vec::new()
+        });
+        Json(ProfileList { profiles })
+    }
+
+    #[oai(path = "/profiles/:name", method = "get")]
+    async fn get_profile(&self, name: Path<String>) -> Json<ProfileResponse> {
+        match self.profile_store.get_document(&name.0) {
+            Ok(profile) => Json(ProfileResponse {
+                success: true,
+                message: format!("Profile {} found", profile.name),
+                profile: Some(profile),
+            }),
+            Err(e) => Json(ProfileResponse {
+                success: false,
+                message: format!("Failed to load profile: {}", e),
+                profile: None,
+            }),
+        }
+    }
+
+    /// Stores a YAML or TOML profile; the document is checked before saving.
+    #[oai(path = "/profiles/:name", method = "put")]
+    async fn save_profile(
+        &self,
+        name: Path<String>,
+        body: Json<SaveProfileRequest>,
+    ) -> Json<ApiResponse> {
+        let name = name.0;
+        match self
+            .profile_store
+            .save_profile(&name, body.0.format, &body.0.document)
+        {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Profile {} saved successfully", name),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to save profile: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/profiles/:name", method = "delete")]
+    async fn remove_profile(&self, name: Path<String>) -> Json<ApiResponse> {
+        let name = name.0;
+        match self.profile_store.remove_profile(&name) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Profile {} removed successfully", name),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to remove profile: {}", e),
+            }),
+        }
+    }
+
+    /// Shows what applying the profile would change, without changing anything.
+    #[oai(path = "/devices/:device_id/profiles/:name/plan", method = "post")]
+    async fn plan_profile(
+        &self,
+        device_id: Path<String>,
+        name: Path<String>,
+    ) -> Json<ProvisionResponse> {
+        self.provision(&name.0, vec![device_id.0], false).await
+    }
+
+    #[oai(path = "/devices/:device_id/profiles/:name/apply", method = "post")]
+    async fn apply_profile(
+        &self,
+        device_id: Path<String>,
+        name: Path<String>,
+    ) -> Json<ProvisionResponse> {
+        self.provision(&name.0, vec![device_id.0], true).await
+    }
+
+    #[oai(path = "/groups/:group/profiles/:name/plan", method = "post")]
+    async fn plan_group_profile(
+        &self,
+        group: Path<String>,
+        name: Path<String>,
+    ) -> Json<ProvisionResponse> {
+        self.provision_group(&group.0, &name.0, false).await
+    }
+
+    #[oai(path = "/groups/:group/profiles/:name/apply", method = "post")]
+    async fn apply_group_profile(
+        &self,
+        group: Path<String>,
+        name: Path<String>,
+    ) -> Json<ProvisionResponse> {
+        self.provision_group(&group.0, &name.0, true).await
+    }
+
+    #[oai(path = "/schedules", method = "get")]
+    async fn list_schedules(&self) -> Json<ScheduleList> {
+        let schedules = self.scheduler.list_jobs();
+        Json(ScheduleList { schedules })
+    }
+
+    #[oai(path = "/schedules/:name", method = "put")]
+    async fn save_schedule(
+        &self,
+        name: Path<String>,
+        body: Json<SaveScheduleRequest>,
+    ) -> Json<ApiResponse> {
+        let name = name.0;
+        let body = body.0;
+        let job = ScheduledJob {
+            name: name.clone(),
+            cron: body.cron,
+            run_at: body.run_at,
+            device_id: body.device_id,
+            group: body.group,
+            action: body.action,
+            enabled: body.enabled.unwrap_or(true),
+        };
+        match self.scheduler.save_job(job) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Schedule {} saved successfully", name),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to save schedule: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/schedules/:name", method = "delete")]
+    async fn remove_schedule(&self, name: Path<String>) -> Json<ApiResponse> {
+        let name = name.0;
+        match self.scheduler.remove_job(&name) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Schedule {} removed successfully", name),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to remove schedule: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/schedules/:name/history", method = "get")]
+    async fn schedule_history(&self, name: Path<String>) -> Json<ScheduleHistory> {
+        let name = name.0;
+        match self.scheduler.job_history(&name) {
+            Ok(runs) => Json(ScheduleHistory {
+                success: true,
+                message: format!("{} runs recorded for schedule {}", runs.len(), name),
+                runs,
+            }),
+            Err(e) => Json(ScheduleHistory {
+                success: false,
+                message: format!("Failed to get schedule history: {}", e),
+                runs: Vec::new(),
+            }),
+        }
+    }
+
+    #[oai(path = "/schedules/:name/run", method = "post")]
+    async fn run_schedule(&self, name: Path<String>) -> Json<ApiResponse> {
+        let name = name.0;
+        match self.scheduler.run_job_now(&name).await {
+            Ok(run) => Json(ApiResponse {
+                success: run.success,
+                message: run.message,
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to run schedule: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/webhooks", method = "get")]
+    async fn list_webhooks(&self) -> Json<WebhookList> {
+        let webhooks = self.webhook_manager.list_webhooks();
+        Json(WebhookList { webhooks })
+    }
+
+    #[oai(path = "/webhooks/:name", method = "put")]
+    async fn save_webhook(
+        &self,
+        name: Path<String>,
+        body: Json<SaveWebhookRequest>,
+    ) -> Json<ApiResponse> {
+        let name = name.0;
+        let body = body.0;
+        let webhook = Webhook {
+            name: name.clone(),
+            url: body.url,
+            events: body.events.unwrap_or_default(),
+            secret: body.secret,
+            enabled: body.enabled.unwrap_or(true),
+        };
+        match self.webhook_manager.save_webhook(webhook) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Webhook {} saved successfully", name),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to save webhook: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/webhooks/:name", method = "delete")]
+    async fn remove_webhook(&self, name: Path<String>) -> Json<ApiResponse> {
+        let name = name.0;
+        match self.webhook_manager.remove_webhook(&name) {
+            Ok(_) => Json(ApiResponse {
+                success: true,
+                message: format!("Webhook {} removed successfully", name),
+            }),
+            Err(e) => Json(ApiResponse {
+                success: false,
+                message: format!("Failed to remove webhook: {}", e),
+            }),
+        }
+    }
+
+    #[oai(path = "/webhooks/:name/deliveries", method = "get")]
+    async fn webhook_deliveries(&self, name: Path<String>) -> Json<WebhookDeliveryLog> {
+        let name = name.0;
+        match self.webhook_manager.deliveries(&name) {
+            Ok(deliveries) => Json(WebhookDeliveryLog {
+                success: true,
+                message: format!(
+                    "{} deliveries recorded for webhook {}",
+                    deliveries.len(),
+                    name
+                ),
+                deliveries,
+            }),
+            Err(e) => Json(WebhookDeliveryLog {
+                success: false,
+                message: format!("Failed to get webhook deliveries: {}", e),
+                deliveries: Vec::new(),
+            }),
+        }
+    }
 }
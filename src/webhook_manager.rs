@@ -0,0 +1,301 @@
+use crate::events::{DeviceEvent, EventBus};
+use crate::storage::{load_json, save_json};
+use chrono::Local;
+use hmac::{Hmac, Mac};
+use poem_openapi::Object;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_DELIVERIES_PER_WEBHOOK: usize = 100;
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, Object)]
+pub struct Webhook {
+    pub name: String,
+    pub url: String,
+    /// Event names to deliver; an empty list delivers every event.
+    #[serde(default)]
+    #[oai(default)]
+    pub events: Vec<String>,
+    /// Used to sign payloads with HMAC-SHA256 in the `X-Atvmate-Signature` header.
+    #[oai(write_only)]
+    pub secret: Option<String>,
+    #[serde(default = "default_enabled")]
+    #[oai(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+impl Webhook {
+    fn accepts(&self, event: &DeviceEvent) -> bool {
+        self.enabled
+            && (self.events.is_empty() || self.events.iter().any(|name| name == event.name()))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Object)]
+pub struct WebhookDelivery {
+    pub id: u64,
+    pub event: String,
+    pub started_at: String,
+    pub finished_at: String,
+    pub attempts: u32,
+    pub success: bool,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+pub struct WebhookManager {
+    path: PathBuf,
+    webhooks: Mutex<BTreeMap<String, Webhook>>,
+    deliveries: Mutex<HashMap<String, VecDeque<WebhookDelivery>>>,
+    next_delivery_id: AtomicU64,
+    client: reqwest::Client,
+}
+
+impl WebhookManager {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let path = path.into();
+        let webhooks = load_json(&path)?;
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .user_agent(concat!("atvmate/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self {
+            path,
+            webhooks: Mutex::new(webhooks),
+            deliveries: Mutex::new(HashMap::new()),
+            next_delivery_id: AtomicU64::new(1),
+            client,
+        })
+    }
+
+    pub fn start(self: Arc<Self>, events: &EventBus) -> tokio::task::JoinHandle<()> {
+        let mut events = events.subscribe();
+        tokio::spawn(async move {
+            loop {
+                let event = match events.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        eprintln!("Webhook dispatcher skipped {} events", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                for webhook in self.matching_webhooks(&event) {
+                    let manager = self.clone();
+                    let event = event.clone();
+                    tokio::spawn(async move {
+                        manager.deliver(webhook, event).await;
+                    });
+                }
+            }
+        })
+    }
+
+    pub fn list_webhooks(&self) -> Vec<Webhook> {
+        let webhooks = self.webhooks.lock().unwrap_or_else(|e| e.into_inner());
+        webhooks.values().cloned().collect()
+    }
+
+    pub fn save_webhook(&self, webhook: Webhook) -> Result<(), Box<dyn Error>> {
+        validate_webhook(&webhook)?;
+        let mut webhooks = self
+            .webhooks
+            .lock()
+            .map_err(|e| format!("Failed to lock webhooks: {}", e))?;
+        webhooks.insert(webhook.name.clone(), webhook);
+        save_json(&self.path, &*webhooks)
+    }
+
+    pub fn remove_webhook(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let mut webhooks = self
+            .webhooks
+            .lock()
+            .map_err(|e| format!("Failed to lock webhooks: {}", e))?;
+        if webhooks.remove(name).is_none() {
+            return Err(format!("Webhook {} not found", name).into());
+        }
+        save_json(&self.path, &*webhooks)?;
+        drop(webhooks);
+        let mut deliveries = self.deliveries.lock().unwrap_or_else(|e| e.into_inner());
+        deliveries.remove(name);
+        Ok(())
+    }
+
+    pub fn deliveries(&self, name: &str) -> Result<Vec<WebhookDelivery>, Box<dyn Error>> {
+        let webhooks = self
+            .webhooks
+            .lock()
+            .map_err(|e| format!("Failed to lock webhooks: {}", e))?;
+        if !webhooks.contains_key(name) {
+            return Err(format!("Webhook {} not found", name).into());
+        }
+        drop(webhooks);
+        let deliveries = self.deliveries.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(deliveries
+            .get(name)
+            .map(|log| log.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn matching_webhooks(&self, event: &DeviceEvent) -> Vec<Webhook> {
+        let webhooks = self.webhooks.lock().unwrap_or_else(|e| e.into_inner());
+        webhooks
+            .values()
+            .filter(|webhook| webhook.accepts(event))
+            .cloned()
+            .collect()
+    }
+
+    async fn deliver(&self, webhook: Webhook, event: DeviceEvent) {
+        let id = self.next_delivery_id.fetch_add(1, Ordering::Relaxed);
+        let started_at = Local::now().to_rfc3339();
+        let body = match payload(&event, &started_at) {
+            Ok(body) => body,
+            Err(e) => {
+                eprintln!("Failed to encode webhook payload: {}", e);
+                return;
+            }
+        };
+        let signature = webhook
+            .secret
+            .as_deref()
+            .map(|secret| sign(secret.as_bytes(), &body));
+
+        let mut delivery = WebhookDelivery {
+            id,
+            event: event.name().to_string(),
+            started_at,
+            finished_at: String::new(),
+            attempts: 0,
+            success: false,
+            status: None,
+            error: None,
+        };
+        let mut backoff = INITIAL_BACKOFF;
+        while delivery.attempts < MAX_ATTEMPTS {
+            if delivery.attempts > 0 {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            delivery.attempts += 1;
+
+            let mut request = self
+                .client
+                .post(&webhook.url)
+                .header("Content-Type", "application/json")
+                .header("X-Atvmate-Event", event.name())
+                .header("X-Atvmate-Delivery", id.to_string())
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header("X-Atvmate-Signature", format!("sha256={}", signature));
+            }
+
+            match request.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    delivery.status = Some(status.as_u16());
+                    if status.is_success() {
+                        delivery.success = true;
+                        delivery.error = None;
+                        break;
+                    }
+                    delivery.error = Some(format!("Unexpected status {}", status));
+                    // Client errors other than rate limiting will not succeed on retry.
+                    if status.is_client_error() && status.as_u16() != 429 {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    delivery.status = None;
+                    delivery.error = Some(e.to_string());
+                }
+            }
+        }
+
+        if !delivery.success {
+            eprintln!(
+                "Webhook {} failed to deliver {} after {} attempts: {}",
+                webhook.name,
+                delivery.event,
+                delivery.attempts,
+                delivery.error.as_deref().unwrap_or("unknown error")
+            );
+        }
+        delivery.finished_at = Local::now().to_rfc3339();
+        self.record_delivery(&webhook.name, delivery);
+    }
+
+    fn record_delivery(&self, name: &str, delivery: WebhookDelivery) {
+        let mut deliveries = self.deliveries.lock().unwrap_or_else(|e| e.into_inner());
+        let log = deliveries.entry(name.to_string()).or_default();
+        log.push_back(delivery);
+        if log.len() > MAX_DELIVERIES_PER_WEBHOOK {
+            log.pop_front();
+        }
+    }
+}
+
+fn payload(event: &DeviceEvent, timestamp: &str) -> Result<Vec<u8>, serde_json::Error> {
+    let mut value = serde_json::to_value(event)?;
+    if let Some(object) = value.as_object_mut() {
+        object.insert("timestamp".to_string(), timestamp.into());
+        // Lets Slack-compatible receivers show the event without a template.
+        object.insert("text".to_string(), event.summary().into());
+    }
+    serde_json::to_vec(&value)
+}
+
+fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn validate_webhook(webhook: &Webhook) -> Result<(), Box<dyn Error>> {
+    let valid_name = !webhook.name.is_empty()
+        && webhook
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid_name {
+        return Err(format!("Invalid webhook name: {}", webhook.name).into());
+    }
+
+    let url = reqwest::Url::parse(&webhook.url)
+        .map_err(|e| format!("Invalid webhook URL '{}': {}", webhook.url, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("Unsupported webhook URL scheme: {}", url.scheme()).into());
+    }
+
+    if let Some(unknown) = webhook
+        .events
+        .iter()
+        .find(|name| !DeviceEvent::NAMES.contains(&name.as_str()))
+    {
+        return Err(format!(
+            "Unknown event '{}', expected one of: {}",
+            unknown,
+            DeviceEvent::NAMES.join(", ")
+        )
+        .into());
+    }
+
+    if webhook.secret.as_deref() == Some("") {
+        return Err("Webhook secret must not be empty".into());
+    }
+    Ok(())
+}
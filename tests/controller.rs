@@ -0,0 +1,367 @@
+use atvmate::atv_controller::ATVController;
+use atvmate::command::{DeviceCommand, PowerState, execute_command};
+use atvmate::events::{DeviceEvent, EventBus};
+use atvmate::file_browser::FileKind;
+use atvmate::global_device_manager::{DeviceHealth, GlobalDeviceManager};
+use atvmate::logcat::LogcatFilter;
+use atvmate::macro_manager::{MacroManager, MacroStep};
+use atvmate::mock_transport::{MockCall, MockTransport};
+use atvmate::reboot::RebootMode;
+use atvmate::settings::SettingsNamespace;
+use atvmate::wake_on_lan::{WakeOnLan, magic_packet};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+
+const ASLEEP: &str = "mWakefulness=Asleep\nDisplay Power: state=OFF\n";
+const AWAKE: &str = "mWakefulness=Awake\nDisplay Power: state=ON\n";
+
+fn controller() -> (ATVController, MockTransport, EventBus) {
+    let mock = MockTransport::new();
+    let events = EventBus::new();
+    let controller = ATVController::new("tv", Box::new(mock.clone()), events.clone());
+    (controller, mock, events)
+}
+
+#[test]
+fn press_key_sends_keyevent() {
+    let (mut controller, mock, _) = controller();
+    controller.press_key("home").unwrap();
+    controller.press_key("dpad_center").unwrap();
+    assert_eq!(
+        mock.shell_commands(),
+        vec!["input keyevent 3", "input keyevent 23"]
+    );
+}
+
+#[test]
+fn unknown_key_sends_nothing() {
+    let (mut controller, mock, _) = controller();
+    let error = controller.press_key("warp_drive").unwrap_err();
+    assert_eq!(error.to_string(), "Unknown key: warp_drive");
+    assert!(mock.calls().is_empty());
+}
+
+#[test]
+fn ping_checks_echo_output() {
+    let (mut controller, mock, _) = controller();
+    mock.respond("echo ok", "ok\n");
+    controller.ping().unwrap();
+
+    mock.respond("echo ok", "garbage\n");
+    assert!(controller.ping().is_err());
+}
+
+#[test]
+fn offline_device_fails_until_reconnected() {
+    let (mut controller, mock, _) = controller();
+    mock.respond("echo ok", "ok\n");
+    mock.set_offline(true);
+    assert!(controller.ping().is_err());
+    assert!(controller.reconnect().is_err());
+
+    mock.set_offline(false);
+    controller.reconnect().unwrap();
+    controller.ping().unwrap();
+    assert_eq!(
+        mock.calls(),
+        vec![MockCall::Reconnect, MockCall::Shell("echo ok".to_string())]
+    );
+}
+
+#[test]
+fn shell_errors_are_propagated() {
+    let (mut controller, mock, _) = controller();
+    mock.fail("input text", "closed");
+    let error = controller.input_text("hello").unwrap_err();
+    assert!(error.to_string().contains("closed"));
+}
+
+#[test]
+fn input_text_escapes_quotes() {
+    let (mut controller, mock, _) = controller();
+    controller.input_text("it's").unwrap();
+    assert_eq!(mock.shell_commands(), vec!["input text 'it\\'s'"]);
+}
+
+#[test]
+fn launch_app_publishes_event() {
+    let (mut controller, mock, events) = controller();
+    let mut receiver = events.subscribe();
+    mock.respond("monkey", "Events injected: 1\n");
+    controller.launch_app("com.example.tv").unwrap();
+
+    assert_eq!(
+        mock.shell_commands(),
+        vec!["monkey -p com.example.tv -c android.intent.category.LEANBACK_LAUNCHER 1"]
+    );
+    match receiver.try_recv().unwrap() {
+        DeviceEvent::AppLaunched { device_id, package } => {
+            assert_eq!(device_id, "tv");
+            assert_eq!(package, "com.example.tv");
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn launch_app_reports_missing_activity() {
+    let (mut controller, mock, events) = controller();
+    let mut receiver = events.subscribe();
+    mock.respond("monkey", "** No activities found to run, monkey aborted.\n");
+    assert!(controller.launch_app("com.example.missing").is_err());
+    assert!(receiver.try_recv().is_err());
+}
+
+#[test]
+fn launch_app_rejects_invalid_package() {
+    let (mut controller, mock, _) = controller();
+    assert!(controller.launch_app("com.example; reboot").is_err());
+    assert!(mock.calls().is_empty());
+}
+
+#[test]
+fn ensure_on_skips_awake_device() {
+    let (mut controller, mock, _) = controller();
+    mock.respond("dumpsys power", AWAKE);
+    assert!(!controller.ensure_on().unwrap());
+    assert!(
+        !mock
+            .shell_commands()
+            .iter()
+            .any(|command| command.starts_with("input keyevent"))
+    );
+}
+
+#[test]
+fn ensure_on_wakes_sleeping_device() {
+    let (mut controller, mock, _) = controller();
+    mock.respond("dumpsys power", AWAKE);
+    mock.respond_once("dumpsys power", ASLEEP);
+    assert!(controller.ensure_on().unwrap());
+    assert!(
+        mock.shell_commands()
+            .contains(&"input keyevent 224".to_string())
+    );
+}
+
+#[test]
+fn foreground_app_falls_back_to_resumed_activity() {
+    let (mut controller, mock, _) = controller();
+    mock.respond("dumpsys window", "  mCurrentFocus=null\n");
+    mock.respond(
+        "dumpsys activity",
+        "  topResumedActivity=ActivityRecord{9c u0 com.example.tv/.MainActivity t4}\n",
+    );
+    let app = controller.foreground_app().unwrap().unwrap();
+    assert_eq!(app.package, "com.example.tv");
+    assert_eq!(app.activity.as_deref(), Some("com.example.tv.MainActivity"));
+}
+
+#[test]
+fn put_setting_records_history_and_undo_restores() {
+    let (mut controller, mock, _) = controller();
+    mock.respond("settings get system screen_off_timeout", "30000\n");
+    let change = controller
+        .put_setting(SettingsNamespace::System, "screen_off_timeout", "60000")
+        .unwrap();
+    assert_eq!(change.previous.as_deref(), Some("30000"));
+    assert_eq!(controller.settings_history().len(), 1);
+
+    let undone = controller.undo_setting_change().unwrap().unwrap();
+    assert_eq!(undone.key, "screen_off_timeout");
+    assert!(controller.settings_history().is_empty());
+    assert_eq!(
+        mock.shell_commands(),
+        vec![
+            "settings get system screen_off_timeout",
+            "settings put system screen_off_timeout '60000'",
+            "settings put system screen_off_timeout '30000'",
+        ]
+    );
+    assert!(controller.undo_setting_change().unwrap().is_none());
+}
+
+#[test]
+fn failed_undo_keeps_history() {
+    let (mut controller, mock, _) = controller();
+    mock.respond("settings get", "null\n");
+    controller
+        .put_setting(SettingsNamespace::Global, "adb_enabled", "1")
+        .unwrap();
+    mock.fail("settings delete", "permission denied");
+    assert!(controller.undo_setting_change().is_err());
+    assert_eq!(controller.settings_history().len(), 1);
+}
+
+#[test]
+fn files_round_trip_through_sync_calls() {
+    let (mut controller, mock, _) = controller();
+    mock.add_file("/sdcard/Movies/clip.mp4", vec![0; 16]);
+    controller
+        .push_file(&mut &b"hello"[..], "/sdcard/notes.txt")
+        .unwrap();
+    assert_eq!(mock.file("/sdcard/notes.txt").unwrap(), b"hello");
+
+    let entries = controller.list_files("/sdcard").unwrap();
+    let names: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    assert_eq!(names, vec!["Movies", "notes.txt"]);
+    assert_eq!(entries[0].kind, FileKind::Directory);
+    assert_eq!(entries[1].size, 5);
+
+    let mut output = Vec::new();
+    controller
+        .pull_file("/sdcard/notes.txt", &mut output)
+        .unwrap();
+    assert_eq!(output, b"hello");
+    assert!(controller.stat_file("/sdcard/missing").unwrap().is_none());
+}
+
+#[test]
+fn delete_path_uses_stat_to_pick_command() {
+    let (mut controller, mock, _) = controller();
+    mock.add_file("/sdcard/Movies/clip.mp4", vec![0; 16]);
+    controller.delete_path("/sdcard/Movies", true).unwrap();
+    assert_eq!(mock.shell_commands(), vec!["rm -rf '/sdcard/Movies'"]);
+    assert!(controller.delete_path("/sdcard/gone", false).is_err());
+    assert!(controller.delete_path("/", true).is_err());
+}
+
+#[test]
+fn reboot_modes_map_to_transport_calls() {
+    let (mut controller, mock, _) = controller();
+    controller.reboot(RebootMode::Recovery).unwrap();
+    controller.reboot(RebootMode::Shutdown).unwrap();
+    assert_eq!(
+        mock.calls(),
+        vec![
+            MockCall::Reboot("Recovery".to_string()),
+            MockCall::Shell("svc power shutdown".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn logcat_dump_parses_records() {
+    let (mut controller, mock, _) = controller();
+    mock.respond(
+        "logcat",
+        "--------- beginning of main\n\
+         10-19 12:00:00.123  1234  1240 I ActivityManager: Start proc\n\
+         10-19 12:00:01.456  1234  1240 E AndroidRuntime: FATAL EXCEPTION\n",
+    );
+    let records = controller.logcat_dump(&LogcatFilter::default(), 1).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].tag, "AndroidRuntime");
+    assert_eq!(records[0].pid, 1234);
+}
+
+#[test]
+fn recording_captures_commands() {
+    let (mut controller, _mock, _) = controller();
+    controller.start_recording("evening").unwrap();
+    assert!(controller.start_recording("other").is_err());
+    controller.press_key("home").unwrap();
+    controller.send_keyevent(999).unwrap();
+    controller.tap(10, 20).unwrap();
+
+    let session = controller.stop_recording().unwrap();
+    assert_eq!(session.name, "evening");
+    assert_eq!(session.device_id, "tv");
+    let steps: Vec<_> = session
+        .steps
+        .iter()
+        .map(|recorded| &recorded.step)
+        .collect();
+    assert!(matches!(steps[0], MacroStep::Key(step) if step.key == "home"));
+    assert!(matches!(steps[1], MacroStep::Keycode(step) if step.keycode == 999));
+    assert!(matches!(steps[2], MacroStep::Tap(step) if step.x == 10 && step.y == 20));
+    assert!(controller.stop_recording().is_none());
+}
+
+#[test]
+fn device_manager_reconnects_and_opens_connections() {
+    let manager = GlobalDeviceManager::new();
+    let mock = MockTransport::new();
+    manager.attach_device("tv", Box::new(mock.clone())).unwrap();
+    assert_eq!(manager.list_devices(), vec!["tv"]);
+
+    manager.reconnect_device("tv").unwrap();
+    let mut connection = manager.open_connection("tv").unwrap();
+    let mut output = Vec::new();
+    connection.shell_command("echo ok", &mut output).unwrap();
+    assert_eq!(
+        mock.calls(),
+        vec![
+            MockCall::Reconnect,
+            MockCall::OpenConnection,
+            MockCall::Shell("echo ok".to_string()),
+        ]
+    );
+
+    mock.set_offline(true);
+    assert!(manager.reconnect_device("tv").is_err());
+    assert!(manager.open_connection("tv").is_err());
+    manager.remove_device("tv").unwrap();
+    assert!(manager.get_controller("tv").is_err());
+}
+
+#[test]
+fn mac_address_is_learned_from_wired_interface() {
+    let manager = GlobalDeviceManager::new();
+    let mock = MockTransport::new();
+    manager.attach_device("tv", Box::new(mock.clone())).unwrap();
+    mock.respond(
+        "ip -o link show",
+        "1: lo: <LOOPBACK,UP,LOWER_UP> mtu 65536 state UNKNOWN\\    link/loopback 00:00:00:00:00:00 brd 00:00:00:00:00:00\n\
+         2: wlan0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500 state UP\\    link/ether 02:00:00:00:00:00 brd ff:ff:ff:ff:ff:ff\n\
+         3: eth0: <NO-CARRIER,BROADCAST,MULTICAST,UP> mtu 1500 state DOWN\\    link/ether AA:BB:CC:00:11:22 brd ff:ff:ff:ff:ff:ff\n\
+         @@\n\n\n",
+    );
+
+    let mac = manager.learn_mac_address("tv").unwrap();
+    assert_eq!(mac.as_deref(), Some("aa:bb:cc:00:11:22"));
+    assert_eq!(
+        manager.wake_on_lan().mac_address("tv").as_deref(),
+        Some("aa:bb:cc:00:11:22")
+    );
+}
+
+#[tokio::test]
+async fn power_on_wakes_offline_device_over_lan() {
+    let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let SocketAddr::V4(addr) = listener.local_addr().unwrap() else {
+        unreachable!()
+    };
+    let manager = Arc::new(
+        GlobalDeviceManager::new().with_wake_on_lan(WakeOnLan::new().with_broadcast(addr)),
+    );
+    let mock = MockTransport::new();
+    mock.respond("echo ok", "ok\n")
+        .respond("dumpsys power", AWAKE);
+    manager.attach_device("tv", Box::new(mock.clone())).unwrap();
+    manager
+        .wake_on_lan()
+        .record("tv", "aa:bb:cc:00:11:22")
+        .unwrap();
+    manager.set_device_health("tv", DeviceHealth::Offline, "standby");
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let macro_manager = MacroManager::load(dir.path().join("macros.json")).unwrap();
+    let message = execute_command(
+        &manager,
+        &macro_manager,
+        "tv",
+        &DeviceCommand::Power(PowerState::On),
+    )
+    .await
+    .unwrap();
+    assert_eq!(message, "Device is already on");
+    assert_eq!(manager.device_health("tv").unwrap(), DeviceHealth::Online);
+
+    let mut packet = [0; 256];
+    let len = listener.recv(&mut packet).unwrap();
+    assert_eq!(
+        packet[..len],
+        magic_packet([0xaa, 0xbb, 0xcc, 0x00, 0x11, 0x22])[..]
+    );
+}
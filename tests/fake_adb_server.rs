@@ -0,0 +1,180 @@
+use atvmate::adb_service::ADBService;
+use atvmate::device::ADBDevice;
+use atvmate::fake_adb_server::{FakeAdbServer, ReceivedRequest, ScriptedShell, SimulatedTv};
+use atvmate::global_device_manager::GlobalDeviceManager;
+use atvmate::settings::SettingsNamespace::Secure;
+use atvmate::volume::VolumeStream;
+
+fn server() -> FakeAdbServer {
+    FakeAdbServer::start().unwrap()
+}
+
+#[test]
+fn adb_service_lists_devices() {
+    let server = server();
+    server
+        .add_device("SIMTV0001", ScriptedShell::new())
+        .add_device("192.168.1.20:5555", ScriptedShell::new());
+    server.set_device_state("192.168.1.20:5555", "offline");
+
+    let devices = ADBService::from_addr(server.addr()).get_devices().unwrap();
+    let devices: Vec<_> = devices
+        .iter()
+        .map(|device| (device.identifier.as_str(), device.state.to_string()))
+        .collect();
+    assert_eq!(
+        devices,
+        vec![
+            ("192.168.1.20:5555", "offline".to_string()),
+            ("SIMTV0001", "device".to_string()),
+        ]
+    );
+    assert_eq!(
+        server.requests(),
+        vec![ReceivedRequest {
+            serial: None,
+            request: "host:devices".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn discover_server_devices_reports_connection_types() {
+    let server = server();
+    server
+        .add_device("SIMTV0001", ScriptedShell::new())
+        .add_device("192.168.1.20:5555", ScriptedShell::new());
+
+    let discovered = GlobalDeviceManager::new().discover_server_devices(Some(server.addr()));
+    let discovered: Vec<_> = discovered
+        .iter()
+        .map(|device| {
+            (
+                device.id.as_str(),
+                device.connection_type.as_str(),
+                device.state.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        discovered,
+        vec![
+            ("server:192.168.1.20:5555", "server", "device"),
+            ("server:SIMTV0001", "server-usb", "device"),
+        ]
+    );
+}
+
+#[test]
+fn server_device_runs_shell_commands() {
+    let server = server();
+    server.add_device(
+        "SIMTV0001",
+        ScriptedShell::new().respond("getprop ro.product.model", "Shield\n"),
+    );
+
+    let mut device = ADBDevice::server("SIMTV0001".to_string(), Some(server.addr()));
+    assert_eq!(device.read_property("ro.product.model").unwrap(), "Shield");
+    assert_eq!(
+        server.shell_commands("SIMTV0001"),
+        vec!["getprop ro.product.model"]
+    );
+
+    let requests: Vec<_> = server
+        .requests()
+        .into_iter()
+        .map(|request| request.request)
+        .collect();
+    assert_eq!(requests[0], "host:transport:SIMTV0001");
+    assert!(requests.contains(&"host:features".to_string()));
+}
+
+#[test]
+fn missing_and_offline_devices_are_rejected() {
+    let server = server();
+    server.add_device("SIMTV0001", ScriptedShell::new());
+    server.set_device_state("SIMTV0001", "unauthorized");
+
+    let mut output = Vec::new();
+    let mut device = ADBDevice::server("SIMTV0001".to_string(), Some(server.addr()));
+    let error = device.shell_command(&"echo ok", &mut output).unwrap_err();
+    assert!(
+        error.to_string().contains("device unauthorized"),
+        "{}",
+        error
+    );
+
+    let mut device = ADBDevice::server("NOPE".to_string(), Some(server.addr()));
+    let error = device.shell_command(&"echo ok", &mut output).unwrap_err();
+    assert!(error.to_string().contains("not found"), "{}", error);
+    assert!(server.shell_commands("SIMTV0001").is_empty());
+}
+
+#[test]
+fn simulated_tv_reacts_to_controller() {
+    let server = server();
+    let tv = SimulatedTv::new();
+    server.add_device("SIMTV0001", tv.clone());
+
+    let manager = GlobalDeviceManager::new();
+    let device_id = manager
+        .add_server_device(Some(server.addr()), "SIMTV0001")
+        .unwrap();
+    assert_eq!(device_id, "server:SIMTV0001");
+    let controller = manager.get_controller(&device_id).unwrap();
+    let mut ctrl = controller.lock().unwrap();
+
+    ctrl.ping().unwrap();
+    ctrl.sleep().unwrap();
+    assert!(!tv.is_awake());
+    assert!(!ctrl.power_status().unwrap().awake);
+    // Navigation keys are ignored while asleep.
+    ctrl.press_key("volume_up").unwrap();
+    assert_eq!(tv.volume(), 7);
+
+    assert!(ctrl.ensure_on().unwrap());
+    assert!(tv.is_awake());
+    ctrl.press_key("volume_up").unwrap();
+    assert_eq!(ctrl.volume(VolumeStream::Music).unwrap().level, 8);
+
+    ctrl.launch_app("com.netflix.ninja").unwrap();
+    assert_eq!(
+        ctrl.foreground_package().unwrap().as_deref(),
+        Some("com.netflix.ninja")
+    );
+    assert!(ctrl.launch_app("com.example.missing").is_err());
+    ctrl.press_key("home").unwrap();
+    assert_eq!(tv.foreground(), "com.google.android.tvlauncher");
+
+    ctrl.input_text("it's on").unwrap();
+    assert_eq!(tv.typed_text(), "it's on");
+    assert_eq!(tv.keys(), vec![223, 24, 224, 24, 3]);
+    assert!(
+        server
+            .shell_commands("SIMTV0001")
+            .contains(&"input keyevent 224".to_string())
+    );
+}
+
+#[test]
+fn simulated_tv_keeps_settings() {
+    let server = server();
+    server.add_device("SIMTV0001", SimulatedTv::new());
+    let manager = GlobalDeviceManager::new();
+    let device_id = manager
+        .add_server_device(Some(server.addr()), "SIMTV0001")
+        .unwrap();
+    let controller = manager.get_controller(&device_id).unwrap();
+    let mut ctrl = controller.lock().unwrap();
+
+    let change = ctrl.put_setting(Secure, "sleep_timeout", "600000").unwrap();
+    assert_eq!(change.previous, None);
+    let setting = ctrl.get_setting(Secure, "sleep_timeout").unwrap();
+    assert_eq!(setting.value.as_deref(), Some("600000"));
+
+    ctrl.undo_setting_change().unwrap();
+    assert_eq!(
+        ctrl.get_setting(Secure, "sleep_timeout").unwrap().value,
+        None
+    );
+}
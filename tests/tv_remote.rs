@@ -0,0 +1,130 @@
+use atvmate::fake_tv_remote::FakeTvRemote;
+use atvmate::global_device_manager::GlobalDeviceManager;
+use atvmate::tv_remote::{PairingSession, RemoteIdentity, RemoteTransport};
+use atvmate::tv_remote_proto::KeyDirection;
+use atvmate::volume::VolumeStream;
+use std::time::{Duration, Instant};
+
+fn eventually(mut condition: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(Instant::now() < deadline, "condition not met in time");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn paired() -> (FakeTvRemote, RemoteIdentity) {
+    let tv = FakeTvRemote::start().unwrap();
+    let identity = RemoteIdentity::generate("atvmate-test").unwrap();
+    let mut session = PairingSession::start(tv.pairing_addr(), &identity).unwrap();
+    session.finish(&tv.pairing_code().unwrap()).unwrap();
+    (tv, identity)
+}
+
+#[test]
+fn pairing_checks_the_on_screen_code() {
+    let tv = FakeTvRemote::start().unwrap();
+    let identity = RemoteIdentity::generate("atvmate-test").unwrap();
+    let mut session = PairingSession::start(tv.pairing_addr(), &identity).unwrap();
+    let code = tv.pairing_code().unwrap();
+    assert_eq!(code.len(), 6);
+
+    // A wrong first byte is caught before anything is sent.
+    let first = u8::from_str_radix(&code[..2], 16).unwrap();
+    let typo = format!("{:02X}{}", first.wrapping_add(1), &code[2..]);
+    let error = session.finish(&typo).unwrap_err();
+    assert!(error.to_string().contains("does not match"), "{}", error);
+    assert!(session.finish("nope").is_err());
+    assert_eq!(tv.paired_clients(), 0);
+
+    session.finish(&code.to_lowercase()).unwrap();
+    assert_eq!(tv.paired_clients(), 1);
+}
+
+#[test]
+fn unpaired_clients_cannot_connect() {
+    let tv = FakeTvRemote::start().unwrap();
+    let identity = RemoteIdentity::generate("stranger").unwrap();
+    assert!(RemoteTransport::connect(tv.remote_addr(), &identity).is_err());
+}
+
+#[test]
+fn identity_survives_restarts() {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("tv_remote_identity.json");
+    let created = RemoteIdentity::load_or_create(&path).unwrap();
+    let loaded = RemoteIdentity::load_or_create(&path).unwrap();
+    assert_eq!(created.certificate(), loaded.certificate());
+}
+
+#[test]
+fn controller_drives_tv_over_remote() {
+    let (tv, identity) = paired();
+    let manager = GlobalDeviceManager::new();
+    let device_id = manager
+        .add_remote_device(tv.remote_addr(), &identity)
+        .unwrap();
+    assert_eq!(device_id, "remote:127.0.0.1");
+    let controller = manager.get_controller(&device_id).unwrap();
+    let mut ctrl = controller.lock().unwrap();
+
+    ctrl.ping().unwrap();
+    eventually(|| tv.pings_answered() == 1);
+    assert!(ctrl.power_status().unwrap().awake);
+
+    ctrl.press_key("dpad_up").unwrap();
+    ctrl.long_press_key("dpad_center").unwrap();
+    eventually(|| tv.keys().len() == 3);
+    assert_eq!(
+        tv.keys(),
+        vec![
+            (19, KeyDirection::Short),
+            (23, KeyDirection::StartLong),
+            (23, KeyDirection::EndLong),
+        ]
+    );
+
+    ctrl.launch_app("com.netflix.ninja").unwrap();
+    eventually(|| tv.foreground() == "com.netflix.ninja");
+    assert_eq!(tv.app_links(), vec!["market://launch?id=com.netflix.ninja"]);
+    eventually(|| ctrl.foreground_package().unwrap().as_deref() == Some("com.netflix.ninja"));
+
+    let volume = ctrl
+        .set_volume(VolumeStream::Music, Some(10), None)
+        .unwrap();
+    assert_eq!((volume.level, volume.max), (10, 15));
+    assert_eq!(tv.volume(), 10);
+    ctrl.volume_mute().unwrap();
+    eventually(|| tv.is_muted());
+    eventually(|| ctrl.volume(VolumeStream::Music).unwrap().muted);
+
+    assert!(ctrl.ensure_off().unwrap());
+    assert!(!tv.is_awake());
+    assert!(ctrl.ensure_on().unwrap());
+    assert!(tv.is_awake());
+
+    let error = ctrl.input_text("hello").unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("not supported over the Android TV Remote protocol"),
+        "{}",
+        error
+    );
+    assert!(ctrl.volume(VolumeStream::System).is_err());
+}
+
+#[test]
+fn reconnect_opens_a_new_session() {
+    let (tv, identity) = paired();
+    let manager = GlobalDeviceManager::new();
+    let device_id = manager
+        .add_remote_device(tv.remote_addr(), &identity)
+        .unwrap();
+    manager.reconnect_device(&device_id).unwrap();
+    eventually(|| tv.pings_answered() == 2);
+
+    let controller = manager.get_controller(&device_id).unwrap();
+    controller.lock().unwrap().press_key("back").unwrap();
+    eventually(|| tv.keys() == vec![(4, KeyDirection::Short)]);
+}
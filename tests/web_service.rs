@@ -0,0 +1,720 @@
+use atvmate::fake_tv_remote::FakeTvRemote;
+use atvmate::global_device_manager::GlobalDeviceManager;
+use atvmate::group_manager::GroupManager;
+use atvmate::macro_manager::MacroManager;
+use atvmate::mock_transport::{MockCall, MockTransport};
+use atvmate::provisioning::ProfileStore;
+use atvmate::resource_monitor::ResourceMonitor;
+use atvmate::scheduler::Scheduler;
+use atvmate::session_recorder::SessionStore;
+use atvmate::tv_remote::RemotePairing;
+use atvmate::web_service::ApiService;
+use atvmate::webhook_manager::WebhookManager;
+use poem::Route;
+use poem::test::{TestClient, TestResponse};
+use poem_openapi::OpenApiService;
+use serde_json::{Value, json};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+struct TestApp {
+    client: TestClient<Route>,
+    device_manager: Arc<GlobalDeviceManager>,
+    _data_dir: TempDir,
+}
+
+fn api_service(data_dir: &Path) -> (ApiService, Arc<GlobalDeviceManager>) {
+    let device_manager = Arc::new(GlobalDeviceManager::new());
+    let macro_manager = Arc::new(MacroManager::load(data_dir.join("macros.json")).unwrap());
+    let group_manager = Arc::new(GroupManager::load(data_dir.join("groups.json")).unwrap());
+    let scheduler = Arc::new(
+        Scheduler::load(
+            data_dir.join("schedules.json"),
+            device_manager.clone(),
+            macro_manager.clone(),
+            group_manager.clone(),
+        )
+        .unwrap(),
+    );
+    let webhook_manager = Arc::new(WebhookManager::load(data_dir.join("webhooks.json")).unwrap());
+    let resource_monitor = Arc::new(ResourceMonitor::new(device_manager.clone()));
+    let session_store = Arc::new(SessionStore::new(data_dir.join("sessions")));
+    let remote_pairing = Arc::new(RemotePairing::new(data_dir.join("tv_remote_identity.json")));
+    let profile_store = Arc::new(ProfileStore::new(data_dir.join("profiles")));
+    let api_service = ApiService::new(
+        device_manager.clone(),
+        macro_manager,
+        group_manager,
+        scheduler,
+        webhook_manager,
+        resource_monitor,
+        session_store,
+        remote_pairing,
+        profile_store,
+    );
+    (api_service, device_manager)
+}
+
+fn app() -> TestApp {
+    let data_dir = TempDir::new().unwrap();
+    let (api_service, device_manager) = api_service(data_dir.path());
+    let api_service = OpenApiService::new(api_service, "ATV Remote Control", "1.0");
+    TestApp {
+        client: TestClient::new(Route::new().nest("/api", api_service)),
+        device_manager,
+        _data_dir: data_dir,
+    }
+}
+
+impl TestApp {
+    fn attach(&self, device_id: &str) -> MockTransport {
+        let mock = MockTransport::new();
+        self.device_manager
+            .attach_device(device_id, Box::new(mock.clone()))
+            .unwrap();
+        mock
+    }
+}
+
+async fn json(response: TestResponse) -> Value {
+    response.assert_status_is_ok();
+    response.0.into_body().into_json().await.unwrap()
+}
+
+async fn body(response: TestResponse) -> Vec<u8> {
+    response.assert_status_is_ok();
+    response.0.into_body().into_vec().await.unwrap()
+}
+
+fn assert_success(value: &Value) {
+    assert_eq!(value["success"], true, "{}", value);
+}
+
+fn assert_failure(value: &Value, message: &str) {
+    assert_eq!(value["success"], false, "{}", value);
+    let actual = value["message"].as_str().unwrap();
+    assert!(
+        actual.contains(message),
+        "{:?} missing {:?}",
+        actual,
+        message
+    );
+}
+
+#[tokio::test]
+async fn lists_and_removes_devices() {
+    let app = app();
+    app.attach("tv");
+    let value = json(app.client.get("/api/devices").send().await).await;
+    assert_eq!(value["devices"], json!(["tv"]));
+
+    assert_success(&json(app.client.delete("/api/devices/tv").send().await).await);
+    let value = json(app.client.get("/api/devices").send().await).await;
+    assert_eq!(value["devices"], json!([]));
+}
+
+#[tokio::test]
+async fn unknown_device_is_reported() {
+    let app = app();
+    let value = json(
+        app.client
+            .post("/api/devices/missing/key/home")
+            .send()
+            .await,
+    )
+    .await;
+    assert_failure(&value, "Device missing not found");
+}
+
+#[tokio::test]
+async fn send_key_reaches_device() {
+    let app = app();
+    let mock = app.attach("tv");
+    let value = json(app.client.post("/api/devices/tv/key/home").send().await).await;
+    assert_success(&value);
+    assert_eq!(mock.shell_commands(), vec!["input keyevent 3"]);
+
+    let value = json(app.client.post("/api/devices/tv/key/nope").send().await).await;
+    assert_failure(&value, "Unknown key: nope");
+    assert_eq!(mock.shell_commands().len(), 1);
+}
+
+#[tokio::test]
+async fn send_text_reaches_device() {
+    let app = app();
+    let mock = app.attach("tv");
+    let value = json(
+        app.client
+            .post("/api/devices/tv/input/text")
+            .body_json(&json!({ "text": "hello" }))
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&value);
+    assert_eq!(mock.shell_commands(), vec!["input text 'hello'"]);
+}
+
+#[tokio::test]
+async fn device_errors_are_returned_as_failures() {
+    let app = app();
+    let mock = app.attach("tv");
+    mock.set_offline(true);
+    let value = json(app.client.post("/api/devices/tv/key/back").send().await).await;
+    assert_failure(&value, "Failed to send key");
+}
+
+#[tokio::test]
+async fn power_status_is_parsed() {
+    let app = app();
+    let mock = app.attach("tv");
+    mock.respond("dumpsys power", "mWakefulness=Dreaming\n");
+    let value = json(app.client.get("/api/devices/tv/power").send().await).await;
+    assert_success(&value);
+    assert_eq!(value["power"]["wakefulness"], "dreaming");
+    assert_eq!(value["power"]["awake"], false);
+}
+
+#[tokio::test]
+async fn settings_can_be_changed_and_undone() {
+    let app = app();
+    let mock = app.attach("tv");
+    mock.respond("settings get secure sleep_timeout", "-1\n");
+
+    let value = json(
+        app.client
+            .put("/api/devices/tv/settings/secure/sleep_timeout")
+            .body_json(&json!({ "value": "600000" }))
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&value);
+    assert_eq!(value["change"]["previous"], "-1");
+
+    let value = json(
+        app.client
+            .get("/api/devices/tv/settings/history")
+            .send()
+            .await,
+    )
+    .await;
+    assert_eq!(value["changes"].as_array().unwrap().len(), 1);
+
+    assert_success(
+        &json(
+            app.client
+                .post("/api/devices/tv/settings/undo")
+                .send()
+                .await,
+        )
+        .await,
+    );
+    assert_eq!(
+        mock.shell_commands().last().unwrap(),
+        "settings put secure sleep_timeout '-1'"
+    );
+}
+
+#[tokio::test]
+async fn unknown_settings_need_force() {
+    let app = app();
+    let mock = app.attach("tv");
+    let request = |force: bool| {
+        app.client
+            .put("/api/devices/tv/settings/global/some_vendor_flag")
+            .body_json(&json!({ "value": "1", "force": force }))
+            .send()
+    };
+    let value = json(request(false).await).await;
+    assert_eq!(value["success"], false);
+    assert!(mock.calls().is_empty());
+
+    assert_success(&json(request(true).await).await);
+    assert!(
+        mock.shell_commands()
+            .contains(&"settings put global some_vendor_flag '1'".to_string())
+    );
+}
+
+#[tokio::test]
+async fn files_upload_and_download() {
+    let app = app();
+    let mock = app.attach("tv");
+    let value = json(
+        app.client
+            .put("/api/devices/tv/files/content")
+            .query("path", &"/sdcard/notes.txt")
+            .content_type("application/octet-stream")
+            .body("remember the milk")
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&value);
+    assert_eq!(
+        mock.file("/sdcard/notes.txt").unwrap(),
+        b"remember the milk"
+    );
+
+    let value = json(
+        app.client
+            .get("/api/devices/tv/files")
+            .query("path", &"/sdcard")
+            .send()
+            .await,
+    )
+    .await;
+    assert_eq!(value["entries"][0]["name"], "notes.txt");
+    assert_eq!(value["entries"][0]["kind"], "file");
+
+    let response = app
+        .client
+        .get("/api/devices/tv/files/content")
+        .query("path", &"/sdcard/notes.txt")
+        .send()
+        .await;
+    assert_eq!(body(response).await, b"remember the milk");
+    assert!(
+        mock.calls()
+            .contains(&MockCall::Pull("/sdcard/notes.txt".to_string()))
+    );
+
+    let response = app
+        .client
+        .get("/api/devices/tv/files/content")
+        .query("path", &"/sdcard/missing.txt")
+        .send()
+        .await;
+    response.assert_status(poem::http::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn logcat_dump_filters_records() {
+    let app = app();
+    let mock = app.attach("tv");
+    mock.respond(
+        "logcat",
+        "10-19 12:00:00.123  1234  1240 I ActivityManager: Start proc\n\
+         10-19 12:00:01.456  1234  1240 E AndroidRuntime: FATAL EXCEPTION\n",
+    );
+    let value = json(
+        app.client
+            .get("/api/devices/tv/logcat/dump")
+            .query("regex", &"FATAL")
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&value);
+    let records = value["records"].as_array().unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["tag"], "AndroidRuntime");
+}
+
+#[tokio::test]
+async fn recording_is_saved_as_session() {
+    let app = app();
+    app.attach("tv");
+    let value = json(
+        app.client
+            .post("/api/devices/tv/recording/start")
+            .body_json(&json!({ "name": "movie_night" }))
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&value);
+    json(app.client.post("/api/devices/tv/key/home").send().await).await;
+    assert_success(
+        &json(
+            app.client
+                .post("/api/devices/tv/recording/stop")
+                .send()
+                .await,
+        )
+        .await,
+    );
+
+    let value = json(app.client.get("/api/sessions/movie_night").send().await).await;
+    assert_success(&value);
+    assert_eq!(value["session"]["steps"][0]["step"]["key"], "home");
+}
+
+#[tokio::test]
+async fn remote_devices_pair_and_take_keys() {
+    let app = app();
+    let tv = FakeTvRemote::start().unwrap();
+    let pairing_port = tv.pairing_addr().port();
+
+    let value = json(
+        app.client
+            .post("/api/remote/pairing/finish")
+            .body_json(&json!({ "host": "127.0.0.1", "port": pairing_port, "code": "ABCDEF" }))
+            .send()
+            .await,
+    )
+    .await;
+    assert_failure(&value, "No pairing in progress");
+
+    let value = json(
+        app.client
+            .post("/api/remote/pairing")
+            .body_json(&json!({ "host": "127.0.0.1", "port": pairing_port }))
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&value);
+    let value = json(
+        app.client
+            .post("/api/remote/pairing/finish")
+            .body_json(&json!({
+                "host": "127.0.0.1",
+                "port": pairing_port,
+                "code": tv.pairing_code().unwrap(),
+            }))
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&value);
+
+    let value = json(
+        app.client
+            .post("/api/devices/remote")
+            .body_json(&json!({ "host": "127.0.0.1", "port": tv.remote_addr().port() }))
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&value);
+    let value = json(
+        app.client
+            .post("/api/devices/remote:127.0.0.1/key/home")
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&value);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while tv.keys().is_empty() {
+        assert!(Instant::now() < deadline, "key never reached the TV");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(tv.keys()[0].0, 3);
+}
+
+#[tokio::test]
+async fn openapi_spec_is_served() {
+    let data_dir = TempDir::new().unwrap();
+    let (api_service, _) = api_service(data_dir.path());
+    let api_service = OpenApiService::new(api_service, "ATV Remote Control", "1.0");
+    let client = TestClient::new(api_service.spec_endpoint());
+    let spec = json(client.get("/").send().await).await;
+    assert!(spec["paths"]["/devices/{device_id}/key/{key_name}"].is_object());
+}
+
+#[tokio::test]
+async fn wake_on_lan_needs_a_learned_mac() {
+    let app = app();
+    let value = json(
+        app.client
+            .post("/api/devices/192.168.1.20/wol")
+            .body_json(&json!({ "broadcast": "192.168.1" }))
+            .send()
+            .await,
+    )
+    .await;
+    assert_failure(&value, "Invalid broadcast address");
+
+    let value = json(
+        app.client
+            .post("/api/devices/192.168.1.20/wol")
+            .body_json(&json!({}))
+            .send()
+            .await,
+    )
+    .await;
+    assert_failure(&value, "MAC address of 192.168.1.20 is unknown");
+}
+
+#[tokio::test]
+async fn profiles_are_planned_and_applied_idempotently() {
+    let app = app();
+    let mock = app.attach("tv");
+    let document = "\
+settings:
+  global:
+    stay_on_while_plugged_in: 3
+uninstall: [com.google.android.youtube.tv]
+disable: [com.google.android.tvrecommendations]
+launcher: com.example.home/.Home
+";
+    assert_success(
+        &json(
+            app.client
+                .put("/api/profiles/signage")
+                .body_json(&json!({ "format": "yaml", "document": document }))
+                .send()
+                .await,
+        )
+        .await,
+    );
+
+    mock.respond(
+        "pm list packages",
+        "package:com.google.android.youtube.tv\n\
+         package:com.google.android.tvrecommendations\n\
+         package:com.example.home\n",
+    )
+    .respond("pm list packages -d", "")
+    .respond("settings get global stay_on_while_plugged_in", "0\n")
+    .respond(
+        "resolve-activity",
+        "priority=0 preferredOrder=0 match=0x108000 specificIndex=-1 isDefault=true\n\
+         com.google.android.tvlauncher/.MainActivity\n",
+    )
+    .respond("pm uninstall", "Success\n")
+    .respond(
+        "pm disable-user",
+        "Package com.google.android.tvrecommendations new state: disabled-user\n",
+    )
+    .respond("set-home-activity", "Success\n");
+
+    let plan = json(
+        app.client
+            .post("/api/devices/tv/profiles/signage/plan")
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&plan);
+    let steps = &plan["devices"][0]["steps"];
+    assert_eq!(steps.as_array().unwrap().len(), 4);
+    assert!(
+        steps
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|step| step["status"] == "pending"),
+        "{}",
+        steps
+    );
+    assert_eq!(steps[0]["kind"], "uninstall");
+    assert_eq!(
+        steps[2]["current"],
+        "com.google.android.tvlauncher/.MainActivity"
+    );
+    assert!(
+        !mock
+            .shell_commands()
+            .iter()
+            .any(|command| command.contains("settings put"))
+    );
+
+    let applied = json(
+        app.client
+            .post("/api/devices/tv/profiles/signage/apply")
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&applied);
+    assert_eq!(
+        applied["devices"][0]["message"],
+        "4 applied, 0 unchanged, 0 failed, 0 skipped"
+    );
+    let commands = mock.shell_commands();
+    for expected in [
+        "pm uninstall --user 0 com.google.android.youtube.tv",
+        "settings put global stay_on_while_plugged_in '3'",
+        "cmd package set-home-activity com.example.home/.Home",
+        "pm disable-user --user 0 com.google.android.tvrecommendations",
+    ] {
+        assert!(commands.contains(&expected.to_string()), "{:?}", commands);
+    }
+
+    // The device now matches, so a second run changes nothing.
+    mock.respond(
+        "pm list packages",
+        "package:com.google.android.tvrecommendations\npackage:com.example.home\n",
+    )
+    .respond(
+        "pm list packages -d",
+        "package:com.google.android.tvrecommendations\n",
+    )
+    .respond("settings get global stay_on_while_plugged_in", "3\n")
+    .respond("resolve-activity", "com.example.home/.Home\n");
+    mock.clear_calls();
+    let applied = json(
+        app.client
+            .post("/api/groups/all/profiles/signage/apply")
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&applied);
+    assert_eq!(
+        applied["devices"][0]["message"],
+        "0 applied, 4 unchanged, 0 failed, 0 skipped"
+    );
+    assert!(
+        mock.shell_commands()
+            .iter()
+            .all(|command| !command.contains("pm uninstall") && !command.contains("settings put"))
+    );
+}
+
+#[tokio::test]
+async fn toml_profiles_are_validated_before_saving() {
+    let app = app();
+    let document = "\
+launcher = \"com.example.home/.Home\"
+macro = \"welcome\"
+
+[settings.secure]
+screensaver_enabled = false
+";
+    assert_success(
+        &json(
+            app.client
+                .put("/api/profiles/lobby")
+                .body_json(&json!({ "format": "toml", "document": document }))
+                .send()
+                .await,
+        )
+        .await,
+    );
+    let value = json(app.client.get("/api/profiles/lobby").send().await).await;
+    assert_eq!(value["profile"]["format"], "toml");
+    assert_eq!(value["profile"]["document"], document);
+
+    let value = json(
+        app.client
+            .put("/api/profiles/lobby")
+            .body_json(&json!({ "format": "toml", "document": "launcher = \"not a component\"" }))
+            .send()
+            .await,
+    )
+    .await;
+    assert_failure(&value, "Invalid component");
+    let value = json(
+        app.client
+            .put("/api/profiles/lobby")
+            .body_json(&json!({ "format": "yaml", "document": "uninstal: [com.example]" }))
+            .send()
+            .await,
+    )
+    .await;
+    assert_failure(&value, "unknown field");
+
+    let value = json(app.client.get("/api/profiles").send().await).await;
+    assert_eq!(
+        value["profiles"],
+        json!([{ "name": "lobby", "format": "toml" }])
+    );
+}
+
+#[tokio::test]
+async fn backups_restore_onto_another_device() {
+    let app = app();
+    let old = app.attach("old");
+    old.respond(
+        "pm list packages",
+        "package:com.example.signage\n\
+         package:com.google.android.tvlauncher\n\
+         package:com.google.android.tvrecommendations\n",
+    )
+    .respond("pm list packages -3", "package:com.example.signage\n")
+    .respond(
+        "pm list packages -d",
+        "package:com.google.android.tvrecommendations\n",
+    )
+    .respond(
+        "pm path com.example.signage",
+        "package:/data/app/com.example.signage-1/base.apk\n",
+    )
+    .respond("settings get secure screensaver_enabled", "0\n")
+    .respond("resolve-activity", "com.example.signage/.Home\n")
+    .add_file("/data/app/com.example.signage-1/base.apk", "signage apk")
+    .add_file("/data/system/users/0/wallpaper", "wallpaper");
+
+    let response = app
+        .client
+        .post("/api/devices/old/backup")
+        .body_json(&json!({ "include_apks": true }))
+        .send()
+        .await;
+    let archive = body(response).await;
+
+    let new = app.attach("new");
+    new.respond_once(
+        "pm list packages",
+        "package:com.google.android.tvlauncher\npackage:com.google.android.tvrecommendations\n",
+    )
+    .respond(
+        "pm list packages",
+        "package:com.example.signage\n\
+         package:com.google.android.tvlauncher\n\
+         package:com.google.android.tvrecommendations\n",
+    )
+    .respond("pm list packages -d", "")
+    .respond("pm install", "Success\n")
+    .respond("settings get secure screensaver_enabled", "1\n")
+    .respond(
+        "resolve-activity",
+        "com.google.android.tvlauncher/.MainActivity\n",
+    )
+    .respond("set-home-activity", "Success\n")
+    .respond(
+        "pm disable-user",
+        "Package com.google.android.tvrecommendations new state: disabled-user\n",
+    );
+
+    let value = json(
+        app.client
+            .post("/api/devices/new/restore")
+            .content_type("application/octet-stream")
+            .body(archive)
+            .send()
+            .await,
+    )
+    .await;
+    assert_success(&value);
+    assert_eq!(
+        value["message"],
+        "Restored backup of old: 5 applied, 1 unchanged, 0 failed"
+    );
+    assert_eq!(
+        new.file("/data/local/tmp/atvmate-com.example.signage.apk")
+            .unwrap(),
+        b"signage apk"
+    );
+    assert_eq!(
+        new.file("/data/system/users/0/wallpaper").unwrap(),
+        b"wallpaper"
+    );
+    let commands = new.shell_commands();
+    for expected in [
+        "pm install -r /data/local/tmp/atvmate-com.example.signage.apk",
+        "settings put secure screensaver_enabled '0'",
+        "cmd package set-home-activity com.example.signage/.Home",
+        "pm disable-user --user 0 com.google.android.tvrecommendations",
+    ] {
+        assert!(commands.contains(&expected.to_string()), "{:?}", commands);
+    }
+
+    let value = json(
+        app.client
+            .post("/api/devices/new/restore")
+            .content_type("application/octet-stream")
+            .body("not an archive")
+            .send()
+            .await,
+    )
+    .await;
+    assert_failure(&value, "Not a device backup");
+}